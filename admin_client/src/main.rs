@@ -0,0 +1,400 @@
+use clap::{Parser, Subcommand};
+use common::*;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+fn get_customer_db_addr() -> String {
+    std::env::var("CUSTOMER_DB_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string())
+}
+
+fn get_product_db_addr() -> String {
+    std::env::var("PRODUCT_DB_ADDR").unwrap_or_else(|_| "127.0.0.1:8081".to_string())
+}
+
+fn get_seller_server_addr() -> String {
+    std::env::var("SELLER_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:8082".to_string())
+}
+
+fn get_buyer_server_addr() -> String {
+    std::env::var("BUYER_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:8083".to_string())
+}
+
+#[derive(Parser)]
+#[command(name = "admin_client")]
+#[command(about = "Online Marketplace Admin Client (talks to the databases and servers directly)")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List item categories as a tree
+    ListCategories,
+    /// Create a category, top-level unless a parent is given
+    CreateCategory {
+        #[arg(short, long)]
+        name: String,
+        #[arg(short, long)]
+        parent_id: Option<i32>,
+    },
+    /// Rename a category
+    RenameCategory {
+        #[arg(short, long)]
+        category_id: i32,
+        #[arg(short, long)]
+        name: String,
+    },
+    /// Move a category under another parent, or to the top level if none is given
+    MoveCategory {
+        #[arg(short, long)]
+        category_id: i32,
+        #[arg(short, long)]
+        parent_id: Option<i32>,
+    },
+    /// Delete a category that has no subcategories and no items
+    DeleteCategory {
+        #[arg(short, long)]
+        category_id: i32,
+    },
+    /// Remove an item from the catalog
+    DeleteItem {
+        #[arg(short, long)]
+        item_id: String,
+    },
+    /// Check the product database's secondary indexes against its items and repair any drift
+    VerifyIndexes,
+    /// Check whether every service is up and can reach the services it depends on
+    Health,
+    /// Show uptime, connection counts and stored state of every service
+    Stats,
+}
+
+#[derive(Clone, Copy)]
+enum Service {
+    CustomerDb,
+    ProductDb,
+    SellerServer,
+    BuyerServer,
+}
+
+impl Service {
+    const ALL: [Service; 4] = [Service::CustomerDb, Service::ProductDb, Service::SellerServer, Service::BuyerServer];
+    
+    fn name(self) -> &'static str {
+        match self {
+            Service::CustomerDb => "customer_db",
+            Service::ProductDb => "product_db",
+            Service::SellerServer => "seller_server",
+            Service::BuyerServer => "buyer_server",
+        }
+    }
+    
+    fn addr(self) -> String {
+        match self {
+            Service::CustomerDb => get_customer_db_addr(),
+            Service::ProductDb => get_product_db_addr(),
+            Service::SellerServer => get_seller_server_addr(),
+            Service::BuyerServer => get_buyer_server_addr(),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    
+    match cli.command {
+        Commands::ListCategories => {
+            list_categories().await?;
+        }
+        Commands::CreateCategory { name, parent_id } => {
+            create_category(name, parent_id).await?;
+        }
+        Commands::RenameCategory { category_id, name } => {
+            update_category(ProductDbRequest::RenameCategory { category_id, name }, "Category renamed!").await?;
+        }
+        Commands::MoveCategory { category_id, parent_id } => {
+            update_category(ProductDbRequest::MoveCategory { category_id, parent_id }, "Category moved!").await?;
+        }
+        Commands::DeleteCategory { category_id } => {
+            delete_category(category_id).await?;
+        }
+        Commands::DeleteItem { item_id } => {
+            let item_id: ItemId = item_id.parse()?;
+            delete_item(item_id).await?;
+        }
+        Commands::VerifyIndexes => {
+            verify_indexes().await?;
+        }
+        Commands::Health => {
+            if !health().await {
+                std::process::exit(1);
+            }
+        }
+        Commands::Stats => {
+            stats().await;
+        }
+    }
+    
+    Ok(())
+}
+
+async fn send_request(request: ProductDbRequest) -> Result<ProductDbResponse, Box<dyn std::error::Error>> {
+    send_to(&get_product_db_addr(), request).await
+}
+
+// Every service accepts a request in an envelope, the frontends included
+async fn send_to<T, U>(addr: &str, request: T) -> Result<U, Box<dyn std::error::Error>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    
+    let envelope = Envelope {
+        trace_id: TraceId::generate(),
+        parent_span_id: None,
+        request,
+    };
+    let request_str = serde_json::to_string(&envelope)?;
+    stream.write_all(request_str.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    
+    let mut response_str = String::new();
+    let mut reader = BufReader::new(stream);
+    reader.read_line(&mut response_str).await?;
+    
+    let response: U = serde_json::from_str(&response_str)?;
+    Ok(response)
+}
+
+async fn list_categories() -> Result<(), Box<dyn std::error::Error>> {
+    match send_request(ProductDbRequest::ListCategories).await? {
+        ProductDbResponse::Categories(categories) => {
+            if categories.is_empty() {
+                println!("No categories.");
+                return Ok(());
+            }
+            
+            println!("Categories:");
+            print_category_tree(&categories, None, 1);
+            Ok(())
+        }
+        ProductDbResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn create_category(name: String, parent_id: Option<i32>) -> Result<(), Box<dyn std::error::Error>> {
+    match send_request(ProductDbRequest::CreateCategory { name, parent_id }).await? {
+        ProductDbResponse::CategoryCreated(category_id) => {
+            println!("Category created!");
+            println!("Category ID: {}", category_id);
+            Ok(())
+        }
+        ProductDbResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn update_category(request: ProductDbRequest, success: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match send_request(request).await? {
+        ProductDbResponse::CategoryUpdated => {
+            println!("{}", success);
+            Ok(())
+        }
+        ProductDbResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn delete_category(category_id: i32) -> Result<(), Box<dyn std::error::Error>> {
+    match send_request(ProductDbRequest::DeleteCategory { category_id }).await? {
+        ProductDbResponse::CategoryDeleted => {
+            println!("Category deleted!");
+            Ok(())
+        }
+        ProductDbResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn delete_item(item_id: ItemId) -> Result<(), Box<dyn std::error::Error>> {
+    match send_request(ProductDbRequest::DeleteItem { item_id, seller_id: None }).await? {
+        ProductDbResponse::ItemDeleted => {
+            println!("Item deleted!");
+            Ok(())
+        }
+        ProductDbResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn verify_indexes() -> Result<(), Box<dyn std::error::Error>> {
+    match send_request(ProductDbRequest::VerifyIndexes).await? {
+        ProductDbResponse::IndexReport(report) => {
+            println!("Checked {} items", report.items_checked);
+            let indexes = [
+                ("Seller index", &report.seller_index),
+                ("Category index", &report.category_index),
+                ("Text index", &report.text_index),
+            ];
+            let mut clean = true;
+            for (name, drift) in indexes {
+                println!("  {}: {} missing, {} stale", name, drift.missing, drift.stale);
+                clean &= drift.missing == 0 && drift.stale == 0;
+            }
+            if clean {
+                println!("Indexes are consistent.");
+            } else {
+                println!("Drift repaired.");
+            }
+            Ok(())
+        }
+        ProductDbResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+// Prints each service's readiness; true if all of them are ready
+async fn health() -> bool {
+    let mut all_ready = true;
+    for service in Service::ALL {
+        let addr = service.addr();
+        match fetch_readiness(service).await {
+            Ok(readiness) => {
+                let state = if readiness.ready { "ready" } else { "not ready" };
+                println!("{:<14} {:<21} {}", service.name(), addr, state);
+                for dependency in &readiness.dependencies {
+                    match &dependency.error {
+                        Some(error) => println!("  {} ({}): unreachable: {}", dependency.name, dependency.addr, error),
+                        None => println!("  {} ({}): reachable", dependency.name, dependency.addr),
+                    }
+                }
+                all_ready &= readiness.ready;
+            }
+            Err(e) => {
+                println!("{:<14} {:<21} unreachable: {}", service.name(), addr, e);
+                all_ready = false;
+            }
+        }
+    }
+    all_ready
+}
+
+async fn stats() {
+    for service in Service::ALL {
+        let addr = service.addr();
+        match fetch_stats(service).await {
+            Ok(stats) => {
+                println!("{} ({})", service.name(), addr);
+                println!("  uptime: {}", format_uptime(stats.uptime_secs));
+                println!("  connections: {} open, {} since start", stats.open_connections, stats.total_connections);
+                for (name, count) in &stats.entries {
+                    println!("  {}: {}", name.replace('_', " "), count);
+                }
+            }
+            Err(e) => println!("{} ({}): unreachable: {}", service.name(), addr, e),
+        }
+    }
+}
+
+async fn fetch_readiness(service: Service) -> Result<Readiness, Box<dyn std::error::Error>> {
+    let addr = service.addr();
+    let readiness = match service {
+        Service::CustomerDb => match send_to(&addr, CustomerDbRequest::Ready).await? {
+            CustomerDbResponse::Ready(readiness) => Ok(readiness),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+        Service::ProductDb => match send_to(&addr, ProductDbRequest::Ready).await? {
+            ProductDbResponse::Ready(readiness) => Ok(readiness),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+        Service::SellerServer => match send_to(&addr, SellerRequest::Ready).await? {
+            SellerResponse::Ready(readiness) => Ok(readiness),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+        Service::BuyerServer => match send_to(&addr, BuyerRequest::Ready).await? {
+            BuyerResponse::Ready(readiness) => Ok(readiness),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+    };
+    Ok(readiness?)
+}
+
+async fn fetch_stats(service: Service) -> Result<ServiceStats, Box<dyn std::error::Error>> {
+    let addr = service.addr();
+    let stats = match service {
+        Service::CustomerDb => match send_to(&addr, CustomerDbRequest::Stats).await? {
+            CustomerDbResponse::Stats(stats) => Ok(stats),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+        Service::ProductDb => match send_to(&addr, ProductDbRequest::Stats).await? {
+            ProductDbResponse::Stats(stats) => Ok(stats),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+        Service::SellerServer => match send_to(&addr, SellerRequest::Stats).await? {
+            SellerResponse::Stats(stats) => Ok(stats),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+        Service::BuyerServer => match send_to(&addr, BuyerRequest::Stats).await? {
+            BuyerResponse::Stats(stats) => Ok(stats),
+            other => Err(format!("Unexpected response: {:?}", other)),
+        },
+    };
+    Ok(stats?)
+}
+
+fn format_uptime(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn print_category_tree(categories: &[Category], parent_id: Option<i32>, depth: usize) {
+    for category in categories.iter().filter(|c| c.parent_id == parent_id) {
+        println!("{}[{}] {}", "  ".repeat(depth), category.category_id, category.name);
+        print_category_tree(categories, Some(category.category_id), depth + 1);
+    }
+}
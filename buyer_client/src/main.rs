@@ -1,6 +1,8 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use common::telemetry;
 use common::*;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::Instrument;
 use uuid::Uuid;
 
 fn get_buyer_server_addr() -> String {
@@ -44,6 +46,11 @@ enum Commands {
         category: Option<i32>,
         #[arg(short, long, num_args = 0..=5, value_delimiter = ',')]
         keywords: Vec<String>,
+        /// Keyword matching: exact, prefix or fuzzy (tolerates typos)
+        #[arg(short, long, default_value = "exact")]
+        match_mode: String,
+        #[command(flatten)]
+        filters: SearchFilterArgs,
     },
     /// Get item details
     GetItem {
@@ -106,11 +113,42 @@ enum Commands {
         #[arg(short, long)]
         session_id: String,
     },
+    /// List item categories as a tree
+    ListCategories {
+        #[arg(short, long)]
+        session_id: String,
+    },
+}
+
+#[derive(Args)]
+struct SearchFilterArgs {
+    /// Minimum sale price
+    #[arg(long)]
+    min_price: Option<f64>,
+    /// Maximum sale price
+    #[arg(long)]
+    max_price: Option<f64>,
+    /// Only 'new' or only 'used' items
+    #[arg(long)]
+    condition: Option<String>,
+    /// Only items from this seller
+    #[arg(long)]
+    seller_id: Option<String>,
+    /// Minimum share of thumbs up, between 0 and 1
+    #[arg(long)]
+    min_rating: Option<f64>,
+    /// Hide sold-out items
+    #[arg(long)]
+    in_stock: bool,
+    /// Sort order: relevance, price-asc, price-desc, newest or best-rated
+    #[arg(long, default_value = "relevance")]
+    sort: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    let _telemetry = telemetry::init_client("buyer_client");
     
     match cli.command {
         Commands::CreateAccount { name, password } => {
@@ -126,8 +164,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             session_id,
             category,
             keywords,
+            match_mode,
+            filters,
         } => {
-            search(session_id, category, keywords).await?;
+            search(session_id, category, keywords, match_mode, filters).await?;
         }
         Commands::GetItem { session_id, item_id } => {
             get_item(session_id, item_id).await?;
@@ -171,6 +211,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::GetPurchases { session_id } => {
             get_purchases(session_id).await?;
         }
+        Commands::ListCategories { session_id } => {
+            list_categories(session_id).await?;
+        }
     }
     
     Ok(())
@@ -178,18 +221,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 async fn send_request(request: BuyerRequest) -> Result<BuyerResponse, Box<dyn std::error::Error>> {
     let addr = get_buyer_server_addr();
-    let mut stream = tokio::net::TcpStream::connect(&addr).await?;
+    // Each operation is the root of its trace
+    let name: &'static str = (&request).into();
+    let span = telemetry::call_span("buyer_server", name);
+    let envelope = telemetry::envelope(&span, request);
     
-    let request_str = serde_json::to_string(&request)?;
-    stream.write_all(request_str.as_bytes()).await?;
-    stream.write_all(b"\n").await?;
-    
-    let mut response_str = String::new();
-    let mut reader = BufReader::new(stream);
-    reader.read_line(&mut response_str).await?;
-    
-    let response: BuyerResponse = serde_json::from_str(&response_str)?;
-    Ok(response)
+    async {
+        let mut stream = tokio::net::TcpStream::connect(&addr).await?;
+        
+        let request_str = serde_json::to_string(&envelope)?;
+        stream.write_all(request_str.as_bytes()).await?;
+        stream.write_all(b"\n").await?;
+        
+        let mut response_str = String::new();
+        let mut reader = BufReader::new(stream);
+        reader.read_line(&mut response_str).await?;
+        
+        let response: BuyerResponse = serde_json::from_str(&response_str)?;
+        Ok(response)
+    }
+    .instrument(span)
+    .await
 }
 
 async fn create_account(name: String, password: String) -> Result<(), Box<dyn std::error::Error>> {
@@ -264,9 +316,52 @@ async fn search(
     session_id_str: String,
     category: Option<i32>,
     keywords: Vec<String>,
+    match_mode_str: String,
+    filter_args: SearchFilterArgs,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
     
+    let match_mode = match match_mode_str.to_lowercase().as_str() {
+        "exact" => KeywordMatch::Exact,
+        "prefix" => KeywordMatch::Prefix,
+        "fuzzy" => KeywordMatch::Fuzzy,
+        _ => return Err("Match mode must be 'exact', 'prefix' or 'fuzzy'".into()),
+    };
+    
+    let condition = match filter_args.condition.as_deref().map(str::to_lowercase).as_deref() {
+        None => None,
+        Some("new") => Some(Condition::New),
+        Some("used") => Some(Condition::Used),
+        Some(_) => return Err("Condition must be 'new' or 'used'".into()),
+    };
+    
+    let seller_id = match filter_args.seller_id {
+        Some(id) => Some(Uuid::parse_str(&id)?),
+        None => None,
+    };
+    
+    if filter_args.min_rating.is_some_and(|r| !(0.0..=1.0).contains(&r)) {
+        return Err("Minimum rating must be between 0 and 1".into());
+    }
+    
+    let filters = SearchFilters {
+        min_price: filter_args.min_price,
+        max_price: filter_args.max_price,
+        condition,
+        seller_id,
+        min_feedback_ratio: filter_args.min_rating,
+        in_stock_only: filter_args.in_stock,
+    };
+    
+    let sort = match filter_args.sort.to_lowercase().as_str() {
+        "relevance" => SortOrder::Relevance,
+        "price-asc" => SortOrder::PriceAscending,
+        "price-desc" => SortOrder::PriceDescending,
+        "newest" => SortOrder::Newest,
+        "best-rated" => SortOrder::BestRated,
+        _ => return Err("Sort must be 'relevance', 'price-asc', 'price-desc', 'newest' or 'best-rated'".into()),
+    };
+    
     // Trim keywords to match seller_client behavior
     let keywords: Vec<String> = keywords.into_iter()
         .map(|k| {
@@ -284,18 +379,22 @@ async fn search(
         session_id,
         category,
         keywords,
+        match_mode,
+        filters,
+        sort,
     };
     
     match send_request(request).await? {
-        BuyerResponse::SearchItemsForSale(items) => {
-            if items.is_empty() {
+        BuyerResponse::SearchItemsForSale(results) => {
+            if results.is_empty() {
                 println!("No items found.");
                 return Ok(());
             }
             
-            println!("Search Results ({} items):", items.len());
+            println!("Search Results ({} items):", results.len());
             println!("{:-<80}", "");
-            for item in items {
+            for result in results {
+                let item = result.item;
                 println!("Item ID: {}", item.item_id);
                 println!("  Name: {}", item.item_name);
                 println!("  Category: {}", item.item_category);
@@ -304,6 +403,9 @@ async fn search(
                 println!("  Price: ${:.2}", item.sale_price);
                 println!("  Quantity: {}", item.quantity);
                 println!("  Feedback: ↑{} ↓{}", item.feedback.thumbs_up, item.feedback.thumbs_down);
+                if result.matched_keywords > 0 {
+                    println!("  Relevance: {:.3} ({} keywords matched)", result.score, result.matched_keywords);
+                }
                 println!("{:-<80}", "");
             }
             Ok(())
@@ -321,7 +423,7 @@ async fn search(
 
 async fn get_item(session_id_str: String, item_id_str: String) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
-    let item_id = Uuid::parse_str(&item_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
     
     let request = BuyerRequest::GetItem {
         session_id,
@@ -338,6 +440,7 @@ async fn get_item(session_id_str: String, item_id_str: String) -> Result<(), Box
             println!("  Condition: {:?}", item.condition);
             println!("  Price: ${:.2}", item.sale_price);
             println!("  Quantity: {}", item.quantity);
+            println!("  Status: {}", item.status);
             println!("  Feedback: ↑{} ↓{}", item.feedback.thumbs_up, item.feedback.thumbs_down);
             Ok(())
         }
@@ -362,7 +465,7 @@ async fn add_to_cart(
     quantity: i32,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
-    let item_id = Uuid::parse_str(&item_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
     
     let request = BuyerRequest::AddItemToCart {
         session_id,
@@ -392,7 +495,7 @@ async fn remove_from_cart(
     quantity: i32,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
-    let item_id = Uuid::parse_str(&item_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
     
     let request = BuyerRequest::RemoveItemFromCart {
         session_id,
@@ -501,7 +604,7 @@ async fn feedback(
     thumbs_up: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
-    let item_id = Uuid::parse_str(&item_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
     
     let request = BuyerRequest::ProvideFeedback {
         session_id,
@@ -587,4 +690,38 @@ async fn get_purchases(session_id_str: String) -> Result<(), Box<dyn std::error:
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+async fn list_categories(session_id_str: String) -> Result<(), Box<dyn std::error::Error>> {
+    let session_id = Uuid::parse_str(&session_id_str)?;
+    
+    let request = BuyerRequest::ListCategories { session_id };
+    
+    match send_request(request).await? {
+        BuyerResponse::ListCategories(categories) => {
+            if categories.is_empty() {
+                println!("No categories.");
+                return Ok(());
+            }
+            
+            println!("Categories:");
+            print_category_tree(&categories, None, 1);
+            Ok(())
+        }
+        BuyerResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+fn print_category_tree(categories: &[Category], parent_id: Option<i32>, depth: usize) {
+    for category in categories.iter().filter(|c| c.parent_id == parent_id) {
+        println!("{}[{}] {}", "  ".repeat(depth), category.category_id, category.name);
+        print_category_tree(categories, Some(category.category_id), depth + 1);
+    }
+}
@@ -0,0 +1,476 @@
+// The buyers' frontend: checks each request's session with the customer
+// database and carries it out against the databases. `serve` runs the server
+// loop on a listener the caller has bound, so the binary and in-process tests
+// start it the same way.
+
+use common::clock::Clock;
+use common::metrics::{self, ErrorKind};
+use common::{status, telemetry};
+use common::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, error, info, instrument, warn, Instrument};
+use uuid::Uuid;
+
+// How long a readiness check waits for each database
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+// What request handlers need besides the request
+pub struct Context {
+    pub backends: Backends,
+    // Decides whether a session has expired; customer_db's clock sets expirations
+    pub clock: Arc<dyn Clock>,
+}
+
+// Serves requests from `listener` until accepting a connection fails
+pub async fn serve(listener: TcpListener, context: Arc<Context>) -> std::io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, context).await {
+                error!(error = %e, "error handling connection");
+            }
+        });
+    }
+}
+
+async fn handle_connection(socket: TcpStream, context: Arc<Context>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let _connection = status::connection_opened();
+    let (read_half, mut write_half) = socket.into_split();
+    let reader = BufReader::new(read_half);
+    let mut lines = reader.lines();
+    
+    while let Ok(Some(line)) = lines.next_line().await {
+        // Clients may wrap the request in an envelope to continue their own trace
+        let parsed = serde_json::from_str::<Envelope<BuyerRequest>>(&line)
+            .map(|envelope| (Some(envelope.trace_id), envelope.parent_span_id, envelope.request))
+            .or_else(|_| serde_json::from_str::<BuyerRequest>(&line).map(|request| (None, None, request)));
+        let (trace_id, parent_span_id, request) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!(error = %e, "invalid request");
+                metrics::count_error(ErrorKind::InvalidRequest, "unknown");
+                let response = BuyerResponse::Error(format!("Invalid request: {}", e));
+                let _ = send_response(&mut write_half, response).await;
+                continue;
+            }
+        };
+        
+        let name: &'static str = (&request).into();
+        let (span, trace_id) = telemetry::request_span(name, trace_id, parent_span_id);
+        let response = telemetry::traced(span, trace_id, async {
+            let started = Instant::now();
+            let response = handle_request(request, &context).await;
+            let elapsed = started.elapsed();
+            metrics::observe_request(name, elapsed);
+            if let BuyerResponse::Error(msg) = &response {
+                metrics::count_error(ErrorKind::Rejected, name);
+                info!(error = %msg, elapsed_us = elapsed.as_micros() as u64, "request rejected");
+            } else {
+                debug!(elapsed_us = elapsed.as_micros() as u64, "request handled");
+            }
+            response
+        }).await;
+        if let Err(e) = send_response(&mut write_half, response).await {
+            warn!(%trace_id, request = name, error = %e, "failed to send response");
+            metrics::count_error(ErrorKind::Connection, name);
+        }
+    }
+    
+    Ok(())
+}
+
+async fn handle_request(request: BuyerRequest, context: &Context) -> BuyerResponse {
+    match request {
+        BuyerRequest::CreateAccount { buyer_name, password } => {
+            match send_to_customer_db(context, CustomerDbRequest::CreateBuyer {
+                buyer_name: buyer_name.clone(),
+                password,
+            }).await {
+                Ok(CustomerDbResponse::BuyerCreated(buyer_id)) => {
+                    BuyerResponse::CreateAccount(buyer_id)
+                }
+                Ok(CustomerDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                _ => BuyerResponse::Error("Failed to create buyer account".to_string()),
+            }
+        }
+        
+        BuyerRequest::Login { buyer_name, password } => {
+            match send_to_customer_db(context, CustomerDbRequest::GetBuyerByName {
+                buyer_name: buyer_name.clone(),
+            }).await {
+                Ok(CustomerDbResponse::Buyer(Some(buyer))) => {
+                    if buyer.password == password {
+                        match send_to_customer_db(context, CustomerDbRequest::CreateSession {
+                            user_id: buyer.buyer_id,
+                            user_type: UserType::Buyer,
+                        }).await {
+                            Ok(CustomerDbResponse::SessionCreated(session_id, _)) => {
+                                BuyerResponse::Login(session_id)
+                            }
+                            Ok(CustomerDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                            _ => BuyerResponse::Error("Failed to create session".to_string()),
+                        }
+                    } else {
+                        BuyerResponse::Error("Invalid password".to_string())
+                    }
+                }
+                Ok(CustomerDbResponse::Buyer(None)) => {
+                    BuyerResponse::Error("Buyer not found".to_string())
+                }
+                Ok(CustomerDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                _ => BuyerResponse::Error("Login failed".to_string()),
+            }
+        }
+        
+        BuyerRequest::Logout { session_id } => {
+            match send_to_customer_db(context, CustomerDbRequest::DeleteSession { session_id }).await {
+                Ok(CustomerDbResponse::SessionDeleted) => BuyerResponse::Logout,
+                Ok(CustomerDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                _ => BuyerResponse::Error("Logout failed".to_string()),
+            }
+        }
+        
+        BuyerRequest::SearchItemsForSale { session_id, category, keywords, match_mode, filters, sort } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(_) => {
+                    match send_to_product_db(context, ProductDbRequest::SearchItems {
+                        category,
+                        keywords,
+                        match_mode,
+                        filters,
+                        sort,
+                    }).await {
+                        Ok(ProductDbResponse::SearchResults(results)) => {
+                            BuyerResponse::SearchItemsForSale(results)
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Search failed".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::GetItem { session_id, item_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(_) => {
+                    match send_to_product_db(context, ProductDbRequest::GetItem { item_id }).await {
+                        Ok(ProductDbResponse::Item(item)) => BuyerResponse::GetItem(item),
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to get item".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::AddItemToCart { session_id, item_id, quantity } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(_) if quantity <= 0 => BuyerResponse::Error("Quantity must be positive".to_string()),
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::AddToCart {
+                        buyer_id: session.user_id,
+                        item_id,
+                        quantity,
+                    }).await {
+                        Ok(ProductDbResponse::CartSaved) => BuyerResponse::AddItemToCart,
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to add to cart".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::RemoveItemFromCart { session_id, item_id, quantity } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(_) if quantity <= 0 => BuyerResponse::Error("Quantity must be positive".to_string()),
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::RemoveFromCart {
+                        buyer_id: session.user_id,
+                        item_id,
+                        quantity,
+                    }).await {
+                        Ok(ProductDbResponse::CartSaved) => BuyerResponse::RemoveItemFromCart,
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to remove from cart".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::SaveCart { session_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::GetCart {
+                        buyer_id: session.user_id,
+                    }).await {
+                        Ok(ProductDbResponse::Cart(cart)) => {
+                            match send_to_product_db(context, ProductDbRequest::SaveCart {
+                                buyer_id: session.user_id,
+                                cart,
+                            }).await {
+                                Ok(ProductDbResponse::CartSaved) => BuyerResponse::SaveCart,
+                                Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                                _ => BuyerResponse::Error("Failed to save cart".to_string()),
+                            }
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to get cart".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::ClearCart { session_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::ClearCart {
+                        buyer_id: session.user_id,
+                    }).await {
+                        Ok(ProductDbResponse::CartCleared) => BuyerResponse::ClearCart,
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to clear cart".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::DisplayCart { session_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::GetCart {
+                        buyer_id: session.user_id,
+                    }).await {
+                        Ok(ProductDbResponse::Cart(cart)) => BuyerResponse::DisplayCart(cart),
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to get cart".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::ProvideFeedback { session_id, item_id, thumbs_up } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(session) => {
+                    // product_db updates the item's and the seller's rating together
+                    match send_to_product_db(context, ProductDbRequest::RecordFeedback {
+                        buyer_id: session.user_id,
+                        item_id,
+                        vote: Some(thumbs_up),
+                    }).await {
+                        Ok(ProductDbResponse::FeedbackRecorded) => BuyerResponse::ProvideFeedback,
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to update feedback".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::GetSellerRating { session_id, seller_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(_) => {
+                    match send_to_customer_db(context, CustomerDbRequest::GetSeller { seller_id }).await {
+                        Ok(CustomerDbResponse::Seller(Some(_))) => {}
+                        Ok(CustomerDbResponse::Seller(None)) => {
+                            return BuyerResponse::Error("Seller not found".to_string());
+                        }
+                        Ok(CustomerDbResponse::Error(msg)) => return BuyerResponse::Error(msg),
+                        _ => return BuyerResponse::Error("Failed to get seller rating".to_string()),
+                    }
+                    match send_to_product_db(context, ProductDbRequest::GetSellerFeedback { seller_id }).await {
+                        Ok(ProductDbResponse::SellerFeedback(feedback)) => BuyerResponse::GetSellerRating(feedback),
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to get seller rating".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::GetBuyerPurchases { session_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::GetPurchaseHistory {
+                        buyer_id: session.user_id,
+                    }).await {
+                        Ok(ProductDbResponse::PurchaseHistory(history)) => {
+                            BuyerResponse::GetBuyerPurchases(history)
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to get purchase history".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::ListCategories { session_id } => {
+            match validate_session(context, session_id, UserType::Buyer).await {
+                Ok(_) => {
+                    match send_to_product_db(context, ProductDbRequest::ListCategories).await {
+                        Ok(ProductDbResponse::Categories(categories)) => {
+                            BuyerResponse::ListCategories(categories)
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => BuyerResponse::Error(msg),
+                        _ => BuyerResponse::Error("Failed to list categories".to_string()),
+                    }
+                }
+                Err(e) => BuyerResponse::Error(e),
+            }
+        }
+        
+        BuyerRequest::Health => BuyerResponse::Health,
+        
+        BuyerRequest::Ready => BuyerResponse::Ready(readiness(&context.backends).await),
+        
+        BuyerRequest::Stats => BuyerResponse::Stats(status::stats(BTreeMap::new())),
+    }
+}
+
+// Ready when both databases answer a health check
+pub async fn readiness(backends: &Backends) -> Readiness {
+    let (customer_db, product_db) = tokio::join!(
+        check_dependency::<_, CustomerDbResponse>("customer_db", backends.customer_db.clone(), CustomerDbRequest::Health),
+        check_dependency::<_, ProductDbResponse>("product_db", backends.product_db.clone(), ProductDbRequest::Health),
+    );
+    Readiness::of(vec![customer_db, product_db])
+}
+
+// Any well-formed answer within the timeout counts as reachable. Failures
+// are reported to the caller rather than logged, since probes repeat them.
+async fn check_dependency<T, U>(name: &str, addr: String, request: T) -> DependencyStatus
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let envelope = Envelope {
+        trace_id: telemetry::current_trace_id(),
+        parent_span_id: None,
+        request,
+    };
+    let error = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, send_and_receive::<_, U>(&addr, envelope)).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("No answer within {} ms", HEALTH_CHECK_TIMEOUT.as_millis())),
+    };
+    DependencyStatus {
+        name: name.to_string(),
+        addr,
+        reachable: error.is_none(),
+        error,
+    }
+}
+
+#[instrument(skip_all, fields(trace_id = %telemetry::current_trace_id()))]
+async fn validate_session(context: &Context, session_id: Uuid, expected_type: UserType) -> Result<Session, String> {
+    match send_to_customer_db(context, CustomerDbRequest::GetSession { session_id }).await {
+        Ok(CustomerDbResponse::Session(Some(session))) => {
+            let now = context.clock.now();
+            
+            if session.expiration < now {
+                let _ = send_to_customer_db(context, CustomerDbRequest::DeleteSession { session_id }).await;
+                return Err("Session expired".to_string());
+            }
+            
+            if session.user_type != expected_type {
+                return Err("Invalid session type".to_string());
+            }
+            
+            Ok(session)
+        }
+        Ok(CustomerDbResponse::Session(None)) => Err("Session not found".to_string()),
+        Ok(CustomerDbResponse::Error(msg)) => Err(msg),
+        Ok(other) => {
+            warn!(response = ?other, "unexpected response to GetSession");
+            Err("Failed to validate session".to_string())
+        }
+        // Already logged by call_backend
+        Err(_) => Err("Failed to validate session".to_string()),
+    }
+}
+
+async fn send_to_customer_db(context: &Context, request: CustomerDbRequest) -> Result<CustomerDbResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let name: &'static str = (&request).into();
+    call_backend("customer_db", &context.backends.customer_db, context.backends.timeout, name, request).await
+}
+
+async fn send_to_product_db(context: &Context, request: ProductDbRequest) -> Result<ProductDbResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let name: &'static str = (&request).into();
+    call_backend("product_db", &context.backends.product_db, context.backends.timeout, name, request).await
+}
+
+// One timed round trip to a database, tagged with the current trace. A
+// database that does not answer within `timeout` counts as unavailable, so a
+// stalled link fails the request instead of holding it open.
+async fn call_backend<T, U>(
+    backend: &str,
+    addr: &str,
+    timeout: Duration,
+    name: &str,
+    request: T,
+) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let span = telemetry::call_span(backend, name);
+    let envelope = telemetry::envelope(&span, request);
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, send_and_receive(addr, envelope)).instrument(span).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("No answer within {} ms", timeout.as_millis()).into()),
+    };
+    let elapsed = started.elapsed();
+    metrics::observe_backend(backend, name, elapsed);
+    match &result {
+        Ok(_) => debug!(backend, request = name, elapsed_us = elapsed.as_micros() as u64, "backend call"),
+        Err(e) => {
+            metrics::count_error(ErrorKind::BackendUnavailable, name);
+            error!(backend, request = name, %addr, error = %e, "backend call failed");
+        }
+    }
+    result
+}
+
+async fn send_and_receive<T, U>(
+    addr: &str,
+    request: T,
+) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    let request_str = serde_json::to_string(&request)?;
+    stream.write_all(request_str.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+
+    let mut response_str = String::new();
+    let (reader, _) = stream.split();
+    let mut buf_reader = BufReader::new(reader);
+    buf_reader.read_line(&mut response_str).await?;
+
+    let response: U = serde_json::from_str(response_str.trim())?;
+    Ok(response)
+}
+
+async fn send_response(
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    response: BuyerResponse,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response_str = serde_json::to_string(&response)?;
+    writer.write_all(response_str.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,281 @@
+// A TCP proxy to put between two components, e.g. buyer_server and
+// product_db, that adds latency, jitter and bandwidth limits, resets
+// connections and partitions the link one way or both. `run` starts a proxy;
+// the other commands change a running proxy through its control port, which
+// takes one JSON `ProxyRequest` per connection like the services do.
+
+mod proxy;
+
+use clap::{Args, Parser, Subcommand};
+use common::telemetry;
+use common::*;
+use proxy::Proxy;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+// Pause after a failed accept on the control port, so running out of file
+// descriptors does not turn the control loop into a busy loop
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+fn get_control_addr() -> String {
+    std::env::var("CHAOS_PROXY_CONTROL_ADDR").unwrap_or_else(|_| "127.0.0.1:8190".to_string())
+}
+
+#[derive(Parser)]
+#[command(name = "chaos_proxy")]
+#[command(about = "TCP proxy that injects latency, bandwidth limits, resets and partitions between components")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+    /// Control address of the proxy (default: CHAOS_PROXY_CONTROL_ADDR or 127.0.0.1:8190)
+    #[arg(long, global = true)]
+    control: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Start a proxy
+    Run {
+        /// Address to accept connections on
+        #[arg(long)]
+        listen: String,
+        /// Address of the component to forward them to
+        #[arg(long)]
+        upstream: String,
+        /// Seed for latency jitter and random resets, for repeatable runs
+        #[arg(long)]
+        seed: Option<u64>,
+        #[command(flatten)]
+        settings: SettingsArgs,
+    },
+    /// Show a running proxy's settings
+    Show,
+    /// Change some of a running proxy's settings, keeping the rest
+    Set {
+        #[command(flatten)]
+        settings: SettingsArgs,
+    },
+    /// Turn all faults off
+    Clear,
+    /// Reset the next new connections, whatever the settings say
+    ResetNext {
+        connections: u64,
+    },
+    /// Reset every open connection now
+    ResetOpen,
+    /// Show connection and byte counts
+    Stats,
+}
+
+#[derive(Args)]
+struct SettingsArgs {
+    /// Delay added to data in each direction, in milliseconds
+    #[arg(long)]
+    latency_ms: Option<u64>,
+    /// Up to this much more delay, drawn at random for each chunk of data
+    #[arg(long)]
+    jitter_ms: Option<u64>,
+    /// Bytes per second in each direction, shared by all connections; 0 for no limit
+    #[arg(long)]
+    bandwidth: Option<u64>,
+    /// Share of new connections to reset, from 0 to 1
+    #[arg(long)]
+    reset_probability: Option<f64>,
+    /// Reset once the request has reached the component instead of right away
+    #[arg(long)]
+    reset_after_request: Option<bool>,
+    /// Direction to hold data back in: none, upstream, downstream or both
+    #[arg(long)]
+    partition: Option<Partition>,
+}
+
+impl SettingsArgs {
+    fn apply(&self, settings: &mut ChaosSettings) {
+        if let Some(latency_ms) = self.latency_ms {
+            settings.latency_ms = latency_ms;
+        }
+        if let Some(jitter_ms) = self.jitter_ms {
+            settings.jitter_ms = jitter_ms;
+        }
+        if let Some(bandwidth) = self.bandwidth {
+            settings.bandwidth_bytes_per_sec = (bandwidth > 0).then_some(bandwidth);
+        }
+        if let Some(reset_probability) = self.reset_probability {
+            settings.reset_probability = reset_probability;
+        }
+        if let Some(reset_after_request) = self.reset_after_request {
+            settings.reset_after_request = reset_after_request;
+        }
+        if let Some(partition) = self.partition {
+            settings.partition = partition;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let control = cli.control.unwrap_or_else(get_control_addr);
+
+    let result = match cli.command {
+        Commands::Run { listen, upstream, seed, settings } => {
+            let mut initial = ChaosSettings::default();
+            settings.apply(&mut initial);
+            return run(listen, upstream, control, seed, initial).await;
+        }
+        Commands::Show => show(&control).await,
+        Commands::Set { settings } => set(&control, &settings).await,
+        Commands::Clear => update(&control, ChaosSettings::default()).await,
+        Commands::ResetNext { connections } => match send_to(&control, ProxyRequest::ResetNext { connections }).await {
+            Ok(ProxyResponse::ResetScheduled) => {
+                println!("The next {} connections will be reset", connections);
+                Ok(())
+            }
+            Ok(other) => Err(unexpected(other)),
+            Err(e) => Err(e.to_string()),
+        },
+        Commands::ResetOpen => match send_to(&control, ProxyRequest::ResetOpen).await {
+            Ok(ProxyResponse::OpenReset(count)) => {
+                println!("Reset {} open connections", count);
+                Ok(())
+            }
+            Ok(other) => Err(unexpected(other)),
+            Err(e) => Err(e.to_string()),
+        },
+        Commands::Stats => stats(&control).await,
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(2);
+    }
+    Ok(())
+}
+
+async fn run(listen: String, upstream: String, control: String, seed: Option<u64>, settings: ChaosSettings) -> Result<(), Box<dyn std::error::Error>> {
+    let _telemetry = telemetry::init("chaos_proxy");
+    proxy::validate(&settings)?;
+    let listener = TcpListener::bind(&listen).await?;
+    let control_listener = TcpListener::bind(&control).await?;
+    info!(%listen, %upstream, %control, ?settings, "Chaos proxy listening");
+
+    let proxy = Arc::new(Proxy::new(listen, upstream, settings, seed));
+    let controlled = proxy.clone();
+    tokio::spawn(async move {
+        loop {
+            let socket = match control_listener.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    warn!(error = %e, "failed to accept a control connection");
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            let proxy = controlled.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_control(socket, &proxy).await {
+                    warn!(error = %e, "control request failed");
+                }
+            });
+        }
+    });
+    proxy.serve(listener).await?;
+    Ok(())
+}
+
+async fn handle_control(socket: TcpStream, proxy: &Proxy) -> std::io::Result<()> {
+    let mut reader = BufReader::new(socket);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let response = match serde_json::from_str::<ProxyRequest>(line.trim()) {
+        Ok(ProxyRequest::GetSettings) => ProxyResponse::Settings(proxy.settings()),
+        Ok(ProxyRequest::SetSettings { settings }) => match proxy.set_settings(settings) {
+            Ok(()) => ProxyResponse::SettingsUpdated,
+            Err(e) => ProxyResponse::Error(e),
+        },
+        Ok(ProxyRequest::ResetNext { connections }) => {
+            proxy.reset_next(connections);
+            ProxyResponse::ResetScheduled
+        }
+        Ok(ProxyRequest::ResetOpen) => ProxyResponse::OpenReset(proxy.reset_open()),
+        Ok(ProxyRequest::Stats) => ProxyResponse::Stats(proxy.stats()),
+        Err(e) => ProxyResponse::Error(format!("Invalid request: {}", e)),
+    };
+    let mut socket = reader.into_inner();
+    socket.write_all(serde_json::to_string(&response)?.as_bytes()).await?;
+    socket.write_all(b"\n").await
+}
+
+async fn show(control: &str) -> Result<(), String> {
+    let settings = fetch_settings(control).await?;
+    println!("latency: {} ms, jitter: up to {} ms", settings.latency_ms, settings.jitter_ms);
+    match settings.bandwidth_bytes_per_sec {
+        Some(rate) => println!("bandwidth: {} bytes/s each way", rate),
+        None => println!("bandwidth: unlimited"),
+    }
+    println!(
+        "resets: {:.1}% of connections{}",
+        settings.reset_probability * 100.0,
+        if settings.reset_after_request { ", after the request" } else { "" }
+    );
+    println!("partition: {:?}", settings.partition);
+    Ok(())
+}
+
+async fn set(control: &str, args: &SettingsArgs) -> Result<(), String> {
+    let mut settings = fetch_settings(control).await?;
+    args.apply(&mut settings);
+    update(control, settings).await
+}
+
+async fn update(control: &str, settings: ChaosSettings) -> Result<(), String> {
+    match send_to(control, ProxyRequest::SetSettings { settings }).await {
+        Ok(ProxyResponse::SettingsUpdated) => show(control).await,
+        Ok(ProxyResponse::Error(e)) => Err(e),
+        Ok(other) => Err(unexpected(other)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn stats(control: &str) -> Result<(), String> {
+    match send_to(control, ProxyRequest::Stats).await {
+        Ok(ProxyResponse::Stats(stats)) => {
+            println!("{} -> {}", stats.listen, stats.upstream);
+            println!("  connections: {} open, {} since start, {} reset", stats.open_connections, stats.connections, stats.resets);
+            println!("  bytes: {} upstream, {} downstream", stats.bytes_upstream, stats.bytes_downstream);
+            Ok(())
+        }
+        Ok(other) => Err(unexpected(other)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn fetch_settings(control: &str) -> Result<ChaosSettings, String> {
+    match send_to(control, ProxyRequest::GetSettings).await {
+        Ok(ProxyResponse::Settings(settings)) => Ok(settings),
+        Ok(other) => Err(unexpected(other)),
+        Err(e) => Err(format!("Failed to reach the proxy at {}: {}", control, e)),
+    }
+}
+
+fn unexpected(response: ProxyResponse) -> String {
+    match response {
+        ProxyResponse::Error(e) => e,
+        other => format!("Unexpected response: {:?}", other),
+    }
+}
+
+async fn send_to(addr: &str, request: ProxyRequest) -> Result<ProxyResponse, Box<dyn std::error::Error>> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request_str = serde_json::to_string(&request)?;
+    stream.write_all(request_str.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+
+    let mut response_str = String::new();
+    let mut reader = BufReader::new(stream);
+    reader.read_line(&mut response_str).await?;
+
+    Ok(serde_json::from_str(&response_str)?)
+}
@@ -0,0 +1,346 @@
+// Forwards TCP connections to one upstream address, delaying, throttling,
+// holding back or resetting the data as the current settings say. Settings
+// can change at any time and apply to open connections from their next chunk
+// of data on. Random choices come from one seeded generator, so a sequence of
+// connections made one after another sees the same delays and resets every
+// time.
+
+use common::{ChaosSettings, ProxyStats};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+const CHUNK_SIZE: usize = 16 * 1024;
+// Chunks read but not yet written, per direction of a connection. Once this
+// many are held back, by latency or a partition, reading stops and TCP flow
+// control pushes back on the sender instead of the proxy buffering without end.
+const MAX_BUFFERED_CHUNKS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Upstream,
+    Downstream,
+}
+
+impl Direction {
+    fn blocked(self, settings: &ChaosSettings) -> bool {
+        match self {
+            Direction::Upstream => settings.partition.blocks_upstream(),
+            Direction::Downstream => settings.partition.blocks_downstream(),
+        }
+    }
+}
+
+// One direction of the link, shared by every connection for the bandwidth limit
+#[derive(Default)]
+struct Link {
+    // When the data already let through will have been sent
+    busy_until: Mutex<Option<Instant>>,
+    bytes: AtomicU64,
+}
+
+impl Link {
+    // Waits as long as sending `len` bytes takes at the given rate, after
+    // whatever was sent before
+    async fn transmit(&self, len: usize, bytes_per_sec: Option<u64>) {
+        let Some(rate) = bytes_per_sec.filter(|rate| *rate > 0) else {
+            return;
+        };
+        let done = {
+            let mut busy_until = self.busy_until.lock().unwrap_or_else(|e| e.into_inner());
+            let start = busy_until.map_or_else(Instant::now, |busy| busy.max(Instant::now()));
+            let done = start + Duration::from_secs_f64(len as f64 / rate as f64);
+            *busy_until = Some(done);
+            done
+        };
+        tokio::time::sleep_until(done).await;
+    }
+}
+
+pub struct Proxy {
+    listen: String,
+    upstream: String,
+    settings: watch::Sender<ChaosSettings>,
+    rng: Mutex<StdRng>,
+    // New connections still to be reset whatever the settings say
+    reset_next: AtomicU64,
+    // Bumped to reset every open connection
+    reset_open: watch::Sender<u64>,
+    upstream_link: Link,
+    downstream_link: Link,
+    connections: AtomicU64,
+    open_connections: AtomicU64,
+    resets: AtomicU64,
+}
+
+impl Proxy {
+    pub fn new(listen: String, upstream: String, settings: ChaosSettings, seed: Option<u64>) -> Self {
+        Proxy {
+            listen,
+            upstream,
+            settings: watch::Sender::new(settings),
+            rng: Mutex::new(seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)),
+            reset_next: AtomicU64::new(0),
+            reset_open: watch::Sender::new(0),
+            upstream_link: Link::default(),
+            downstream_link: Link::default(),
+            connections: AtomicU64::new(0),
+            open_connections: AtomicU64::new(0),
+            resets: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> ChaosSettings {
+        self.settings.borrow().clone()
+    }
+
+    pub fn set_settings(&self, settings: ChaosSettings) -> Result<(), String> {
+        validate(&settings)?;
+        info!(?settings, "settings changed");
+        self.settings.send_replace(settings);
+        Ok(())
+    }
+
+    pub fn reset_next(&self, connections: u64) {
+        self.reset_next.fetch_add(connections, Ordering::Relaxed);
+    }
+
+    // Returns how many connections were open
+    pub fn reset_open(&self) -> u64 {
+        self.reset_open.send_modify(|generation| *generation += 1);
+        self.open_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> ProxyStats {
+        ProxyStats {
+            listen: self.listen.clone(),
+            upstream: self.upstream.clone(),
+            connections: self.connections.load(Ordering::Relaxed),
+            open_connections: self.open_connections.load(Ordering::Relaxed),
+            resets: self.resets.load(Ordering::Relaxed),
+            bytes_upstream: self.upstream_link.bytes.load(Ordering::Relaxed),
+            bytes_downstream: self.downstream_link.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (client, peer) = listener.accept().await?;
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                debug!(%peer, "connection accepted");
+                proxy.open_connections.fetch_add(1, Ordering::Relaxed);
+                proxy.handle(client).await;
+                proxy.open_connections.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    }
+
+    async fn handle(&self, mut client: TcpStream) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        // Subscribed first, so a reset asked for from here on reaches this connection
+        let mut reset_open = self.reset_open.subscribe();
+        let settings = self.settings();
+        let reset = self.take_reset(&settings);
+        if reset && !settings.reset_after_request {
+            self.reset(&[&client]);
+            return;
+        }
+
+        let mut server = match TcpStream::connect(&self.upstream).await {
+            Ok(server) => server,
+            Err(e) => {
+                warn!(upstream = %self.upstream, error = %e, "failed to connect upstream");
+                self.reset(&[&client]);
+                return;
+            }
+        };
+        let _ = client.set_nodelay(true);
+        let _ = server.set_nodelay(true);
+
+        let reset = {
+            let (client_read, client_write) = client.split();
+            let (server_read, server_write) = server.split();
+            let (reached_tx, mut reached) = oneshot::channel();
+            let upstream = self.pipe(client_read, server_write, Direction::Upstream, Some(reached_tx));
+            let downstream = self.pipe(server_read, client_write, Direction::Downstream, None);
+            tokio::select! {
+                _ = async { tokio::try_join!(upstream, downstream) } => false,
+                _ = reset_open.changed() => true,
+                Ok(()) = &mut reached, if reset => true,
+            }
+        };
+        if reset {
+            self.reset(&[&client, &server]);
+        }
+    }
+
+    // Whether a new connection is to be reset
+    fn take_reset(&self, settings: &ChaosSettings) -> bool {
+        let forced = self
+            .reset_next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+        let probability = settings.reset_probability.clamp(0.0, 1.0);
+        forced || (probability > 0.0 && self.rng.lock().unwrap_or_else(|e| e.into_inner()).gen_bool(probability))
+    }
+
+    // Closes the sockets with a TCP reset instead of an orderly shutdown
+    fn reset(&self, sockets: &[&TcpStream]) {
+        self.resets.fetch_add(1, Ordering::Relaxed);
+        for socket in sockets {
+            // With a zero linger time the close sends RST and does not block
+            let _ = socket2::SockRef::from(*socket).set_linger(Some(Duration::ZERO));
+        }
+        debug!("connection reset");
+    }
+
+    // Copies one direction of a connection. Reading runs ahead of writing, up
+    // to MAX_BUFFERED_CHUNKS, so added latency does not slow the data down,
+    // only delays it. `reached` fires once the first bytes have been written.
+    async fn pipe<R, W>(&self, mut reader: R, mut writer: W, direction: Direction, mut reached: Option<oneshot::Sender<()>>) -> io::Result<()>
+    where
+        R: AsyncReadExt + Unpin,
+        W: AsyncWriteExt + Unpin,
+    {
+        let (chunks_tx, mut chunks_rx) = mpsc::channel::<(Instant, Vec<u8>)>(MAX_BUFFERED_CHUNKS);
+        let read = async move {
+            let mut buf = vec![0; CHUNK_SIZE];
+            let mut last_due = Instant::now();
+            loop {
+                let n = reader.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok::<(), io::Error>(());
+                }
+                // Later data never overtakes earlier data
+                let due = (Instant::now() + self.delay()).max(last_due);
+                last_due = due;
+                if chunks_tx.send((due, buf[..n].to_vec())).await.is_err() {
+                    return Ok(());
+                }
+            }
+        };
+        let write = async move {
+            let link = match direction {
+                Direction::Upstream => &self.upstream_link,
+                Direction::Downstream => &self.downstream_link,
+            };
+            let mut settings = self.settings.subscribe();
+            while let Some((due, chunk)) = chunks_rx.recv().await {
+                tokio::time::sleep_until(due).await;
+                while direction.blocked(&settings.borrow_and_update()) {
+                    if settings.changed().await.is_err() {
+                        break;
+                    }
+                }
+                let bandwidth = settings.borrow().bandwidth_bytes_per_sec;
+                link.transmit(chunk.len(), bandwidth).await;
+                writer.write_all(&chunk).await?;
+                link.bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                if let Some(reached) = reached.take() {
+                    let _ = reached.send(());
+                }
+            }
+            // Passes the end of the stream on once everything before it is through
+            writer.shutdown().await
+        };
+        tokio::try_join!(read, write).map(|_| ())
+    }
+
+    // Latency plus jitter for a chunk read now
+    fn delay(&self) -> Duration {
+        let (latency, jitter) = {
+            let settings = self.settings.borrow();
+            (settings.latency_ms, settings.jitter_ms)
+        };
+        let jitter = if jitter > 0 {
+            self.rng.lock().unwrap_or_else(|e| e.into_inner()).gen_range(0..=jitter)
+        } else {
+            0
+        };
+        Duration::from_millis(latency + jitter)
+    }
+}
+
+pub fn validate(settings: &ChaosSettings) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&settings.reset_probability) {
+        return Err("reset_probability must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Partition;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    // Echoes every line back on each connection
+    async fn echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (reader, mut writer) = socket.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let _ = writer.write_all(format!("{}\n", line).as_bytes()).await;
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn start(upstream: String, settings: ChaosSettings) -> (Arc<Proxy>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen = listener.local_addr().unwrap().to_string();
+        let proxy = Arc::new(Proxy::new(listen.clone(), upstream, settings, Some(1)));
+        tokio::spawn(proxy.clone().serve(listener));
+        (proxy, listen)
+    }
+
+    #[tokio::test]
+    async fn partition_holds_traffic_until_healed() {
+        let settings = ChaosSettings { partition: Partition::Both, ..ChaosSettings::default() };
+        let (proxy, listen) = start(echo_server().await, settings).await;
+
+        let mut client = BufReader::new(TcpStream::connect(&listen).await.unwrap());
+        client.get_mut().write_all(b"ping\n").await.unwrap();
+        let mut line = String::new();
+        let blocked = tokio::time::timeout(Duration::from_millis(300), client.read_line(&mut line)).await;
+        assert!(blocked.is_err(), "got {:?} through a partition", line);
+        assert_eq!(proxy.stats().bytes_upstream, 0);
+
+        proxy.set_settings(ChaosSettings::default()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), client.read_line(&mut line))
+            .await
+            .expect("traffic still blocked after healing")
+            .unwrap();
+        assert_eq!(line, "ping\n");
+        assert_eq!(proxy.stats().bytes_upstream, 5);
+        assert_eq!(proxy.stats().bytes_downstream, 5);
+    }
+
+    #[tokio::test]
+    async fn partition_buffers_a_bounded_amount() {
+        let settings = ChaosSettings { partition: Partition::Upstream, ..ChaosSettings::default() };
+        let (proxy, listen) = start(echo_server().await, settings).await;
+
+        // Far more than the proxy and both sockets' kernel buffers hold
+        let data = vec![b'x'; 64 * MAX_BUFFERED_CHUNKS * CHUNK_SIZE];
+        let mut client = TcpStream::connect(&listen).await.unwrap();
+        let sent = tokio::time::timeout(Duration::from_secs(2), client.write_all(&data)).await;
+        assert!(sent.is_err(), "the proxy took all {} bytes while partitioned", data.len());
+        assert_eq!(proxy.stats().bytes_upstream, 0);
+    }
+}
@@ -0,0 +1,47 @@
+// Where the services get the time for session expiry. They run on
+// `SystemClock`; tests hand every service the same `ManualClock` and move it
+// forward by hand, so a timeout can be checked without waiting it out.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub trait Clock: Send + Sync {
+    // Unix timestamp in seconds
+    fn now(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs() as i64)
+            .unwrap_or(0)
+    }
+}
+
+// Stands still until it is advanced
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        ManualClock { now: AtomicI64::new(now) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.fetch_add(by.as_secs() as i64, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
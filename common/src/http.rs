@@ -0,0 +1,94 @@
+// Minimal HTTP/1.1 server for the metrics and probe endpoints: GET only, one
+// request per connection, headers ignored.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+// Pause after a failed accept, which usually means the process is out of file
+// descriptors; retrying at once would only spin
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Response { status: "200 OK", content_type, body }
+    }
+
+    pub fn not_found() -> Self {
+        Response {
+            status: "404 Not Found",
+            content_type: "text/plain",
+            body: "Not found\n".to_string(),
+        }
+    }
+}
+
+// Serves `handler` on `addr` in the background. The handler gets the request
+// path and produces the whole response.
+pub async fn serve<H, F>(addr: &str, handler: H) -> std::io::Result<()>
+where
+    H: Fn(String) -> F + Send + Sync + 'static,
+    F: Future<Output = Response> + Send,
+{
+    let listener = TcpListener::bind(addr).await?;
+    let handler = Arc::new(handler);
+    let addr = addr.to_string();
+
+    tokio::spawn(async move {
+        loop {
+            let socket = match listener.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    warn!(%addr, error = %e, "failed to accept a connection");
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let _ = respond(socket, handler.as_ref()).await;
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn respond<H, F>(socket: TcpStream, handler: &H) -> std::io::Result<()>
+where
+    H: Fn(String) -> F,
+    F: Future<Output = Response>,
+{
+    let mut reader = BufReader::new(socket);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+    let response = handler(path).await;
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    let mut socket = reader.into_inner();
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
@@ -1,11 +1,155 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use strum::IntoStaticStr;
 use uuid::Uuid;
 
+pub mod clock;
+pub mod http;
+pub mod metrics;
+pub mod status;
+pub mod telemetry;
+
 // Shared data structures
 
+// Item id per the spec: <item category, integer>, assigned by product DB.
+// The category is the one the item was first registered under. The string
+// form "<category>-<sequence>" (e.g. "3-1042") is used on the wire and by the CLIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct ItemId {
+    pub category: i32,
+    pub sequence: u64,
+}
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.category, self.sequence)
+    }
+}
+
+impl FromStr for ItemId {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid item ID '{}', expected <category>-<number>", s);
+        let (category, sequence) = s.trim().rsplit_once('-').ok_or_else(invalid)?;
+        Ok(ItemId {
+            category: category.parse().map_err(|_| invalid())?,
+            sequence: sequence.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Serialize for ItemId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ItemId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Identifies one client operation across every tier it touches. Shown and
+// sent as 32 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(pub u128);
+
+impl TraceId {
+    pub fn generate() -> Self {
+        TraceId(Uuid::new_v4().as_u128())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for TraceId {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16)
+            .map(TraceId)
+            .map_err(|_| format!("Invalid trace ID '{}'", s))
+    }
+}
+
+impl Serialize for TraceId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TraceId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// One span within a trace, sent as 16 hex digits so a callee can attach its
+// own span to the caller's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(pub u64);
+
+impl SpanId {
+    // The low half of a v4 UUID carries the variant bits, so it is never the
+    // all-zero ID that tracing treats as invalid
+    pub fn generate() -> Self {
+        SpanId(Uuid::new_v4().as_u128() as u64)
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for SpanId {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16)
+            .map(SpanId)
+            .map_err(|_| format!("Invalid span ID '{}'", s))
+    }
+}
+
+impl Serialize for SpanId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SpanId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Every request to a database travels in an envelope naming the trace it
+// belongs to. Clients may wrap their requests to a frontend the same way.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub trace_id: TraceId,
+    // The caller's span, present when the caller exports traces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<SpanId>,
+    pub request: T,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Item {
-    pub item_id: Uuid,
+    pub item_id: ItemId,
     pub item_name: String,
     pub item_category: i32,
     pub keywords: Vec<String>,
@@ -14,25 +158,95 @@ pub struct Item {
     pub quantity: i32,
     pub feedback: Feedback,
     pub seller_id: Uuid,
+    // Unix timestamp of registration, assigned by product DB
+    pub listed_at: i64,
+    // Bumped by product DB on every change, for optimistic concurrency control
+    pub version: u64,
+    pub status: ItemStatus,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+// Listing lifecycle. Only active items show up in search and can be added to
+// carts. Product DB moves items between active and sold out as stock runs out
+// or is restocked; delisting is final.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ItemStatus {
+    Draft,
+    #[default]
+    Active,
+    Paused,
+    SoldOut,
+    Delisted,
+}
+
+impl fmt::Display for ItemStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ItemStatus::Draft => "draft",
+            ItemStatus::Active => "active",
+            ItemStatus::Paused => "paused",
+            ItemStatus::SoldOut => "sold out",
+            ItemStatus::Delisted => "delisted",
+        };
+        f.write_str(name)
+    }
+}
+
+// How UpdateUnitsForSale changes the quantity for sale
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum UnitsUpdate {
+    // Replace the quantity
+    SetTo(i32),
+    // Take this many units off sale; fails rather than going below zero or
+    // below the units held in buyers' carts
+    Remove(i32),
+}
+
+// Status changes a seller can ask for
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum StatusAction {
+    Publish,
+    Pause,
+    Resume,
+    Delist,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum Condition {
     New,
     Used,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Feedback {
     pub thumbs_up: i32,
     pub thumbs_down: i32,
 }
 
+impl Feedback {
+    // Share of thumbs up, or None if nobody has voted yet
+    pub fn ratio(&self) -> Option<f64> {
+        let total = self.thumbs_up + self.thumbs_down;
+        if total > 0 {
+            Some(self.thumbs_up as f64 / total as f64)
+        } else {
+            None
+        }
+    }
+    
+    // (thumbs_up, thumbs_down) change caused by replacing one buyer's vote with another
+    pub fn vote_delta(previous: Option<bool>, current: Option<bool>) -> (i32, i32) {
+        let count = |vote: Option<bool>, up: bool| (vote == Some(up)) as i32;
+        (
+            count(current, true) - count(previous, true),
+            count(current, false) - count(previous, false),
+        )
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Seller {
     pub seller_id: Uuid,
     pub seller_name: String,
-    pub feedback: Feedback,
     pub items_sold: i32,
     pub password: String,
 }
@@ -59,15 +273,155 @@ pub enum UserType {
     Seller,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Category {
+    pub category_id: i32,
+    pub name: String,
+    // None for top-level categories
+    pub parent_id: Option<i32>,
+}
+
+// Per-field item changes for EditItem, PatchItem and UpdateItem; None leaves a
+// field unchanged
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ItemPatch {
+    pub item_name: Option<String>,
+    pub item_category: Option<i32>,
+    pub keywords: Option<Vec<String>>,
+    pub condition: Option<Condition>,
+    pub sale_price: Option<f64>,
+    pub quantity: Option<i32>,
+}
+
+// Index entries found out of line with the items by a consistency check
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct IndexDrift {
+    // Entries an item should have had but didn't (added back)
+    pub missing: usize,
+    // Entries for items that are gone or no longer match, or duplicates (removed)
+    pub stale: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct IndexReport {
+    pub items_checked: usize,
+    pub seller_index: IndexDrift,
+    pub category_index: IndexDrift,
+    pub text_index: IndexDrift,
+}
+
+// Whether a service can serve requests, judged by the dependencies it needs
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Readiness {
+    pub ready: bool,
+    pub dependencies: Vec<DependencyStatus>,
+}
+
+impl Readiness {
+    // Ready when every dependency is reachable
+    pub fn of(dependencies: Vec<DependencyStatus>) -> Self {
+        Readiness {
+            ready: dependencies.iter().all(|dependency| dependency.reachable),
+            dependencies,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub addr: String,
+    pub reachable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// How long a frontend waits for a database's answer unless told otherwise
+pub const BACKEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Where a frontend finds the databases
+#[derive(Debug, Clone)]
+pub struct Backends {
+    pub customer_db: String,
+    pub product_db: String,
+    // How long a call waits for the database to answer before it fails
+    pub timeout: std::time::Duration,
+}
+
+impl Backends {
+    // From CUSTOMER_DB_ADDR and PRODUCT_DB_ADDR, defaulting to the local ports
+    pub fn from_env() -> Self {
+        Backends {
+            customer_db: std::env::var("CUSTOMER_DB_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string()),
+            product_db: std::env::var("PRODUCT_DB_ADDR").unwrap_or_else(|_| "127.0.0.1:8081".to_string()),
+            timeout: BACKEND_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceStats {
+    pub service: String,
+    pub uptime_secs: u64,
+    pub open_connections: u64,
+    // Connections accepted since startup
+    pub total_connections: u64,
+    // Size of each piece of state the service holds, e.g. "sellers" or "items"
+    pub entries: BTreeMap<String, u64>,
+}
+
+// How search keywords are matched against item keywords and name tokens
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum KeywordMatch {
+    #[default]
+    Exact,
+    // Also match indexed terms that start with the keyword
+    Prefix,
+    // Prefix matches plus terms one edit away, to tolerate typos
+    Fuzzy,
+}
+
+// Optional search filters; unset fields don't restrict results
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchFilters {
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub condition: Option<Condition>,
+    pub seller_id: Option<Uuid>,
+    // Minimum share of thumbs up in [0, 1]; items nobody has rated don't qualify
+    pub min_feedback_ratio: Option<f64>,
+    pub in_stock_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SortOrder {
+    #[default]
+    Relevance,
+    PriceAscending,
+    PriceDescending,
+    Newest,
+    // Highest thumbs-up ratio first, more votes breaking ties
+    BestRated,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchResult {
+    pub item: Item,
+    // Number of distinct query keywords the item matched
+    pub matched_keywords: usize,
+    // Relevance weight; see product_db/src/search.rs for the formula
+    pub score: f64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CartItem {
-    pub item_id: Uuid,
+    pub item_id: ItemId,
     pub quantity: i32,
 }
 
 // Message types for TCP communication
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 pub enum SellerRequest {
     CreateAccount {
         seller_name: String,
@@ -91,20 +445,53 @@ pub enum SellerRequest {
         condition: Condition,
         sale_price: f64,
         quantity: i32,
+        // Keep the listing as a draft until it is published
+        draft: bool,
     },
     ChangeItemPrice {
         session_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
         new_price: f64,
     },
     UpdateUnitsForSale {
         session_id: Uuid,
-        item_id: Uuid,
-        quantity: i32,
+        item_id: ItemId,
+        update: UnitsUpdate,
     },
     DisplayItemsForSale {
         session_id: Uuid,
     },
+    // Changes any listing fields at once, keeping the item's ID and feedback
+    EditItem {
+        session_id: Uuid,
+        item_id: ItemId,
+        changes: ItemPatch,
+    },
+    PublishItem {
+        session_id: Uuid,
+        item_id: ItemId,
+    },
+    PauseItem {
+        session_id: Uuid,
+        item_id: ItemId,
+    },
+    ResumeItem {
+        session_id: Uuid,
+        item_id: ItemId,
+    },
+    DelistItem {
+        session_id: Uuid,
+        item_id: ItemId,
+    },
+    ListCategories {
+        session_id: Uuid,
+    },
+    // Answered as long as the process is serving requests
+    Health,
+    // Whether the service and everything it depends on can serve requests
+    Ready,
+    // Uptime, connection counts and state sizes (admin)
+    Stats,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -113,14 +500,24 @@ pub enum SellerResponse {
     Login(Uuid),
     Logout,
     GetSellerRating(Feedback),
-    RegisterItemForSale(Uuid),
+    RegisterItemForSale(ItemId),
     ChangeItemPrice,
-    UpdateUnitsForSale,
+    // Quantity for sale after the update
+    UpdateUnitsForSale(i32),
     DisplayItemsForSale(Vec<Item>),
+    EditItem(Item),
+    PublishItem,
+    PauseItem,
+    ResumeItem,
+    DelistItem,
+    ListCategories(Vec<Category>),
+    Health,
+    Ready(Readiness),
+    Stats(ServiceStats),
     Error(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 pub enum BuyerRequest {
     CreateAccount {
         buyer_name: String,
@@ -135,21 +532,25 @@ pub enum BuyerRequest {
     },
     SearchItemsForSale {
         session_id: Uuid,
+        // Also matches items in all of the category's subcategories
         category: Option<i32>,
         keywords: Vec<String>,
+        match_mode: KeywordMatch,
+        filters: SearchFilters,
+        sort: SortOrder,
     },
     GetItem {
         session_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
     },
     AddItemToCart {
         session_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
         quantity: i32,
     },
     RemoveItemFromCart {
         session_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
         quantity: i32,
     },
     SaveCart {
@@ -163,7 +564,7 @@ pub enum BuyerRequest {
     },
     ProvideFeedback {
         session_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
         thumbs_up: bool,
     },
     GetSellerRating {
@@ -173,6 +574,12 @@ pub enum BuyerRequest {
     GetBuyerPurchases {
         session_id: Uuid,
     },
+    ListCategories {
+        session_id: Uuid,
+    },
+    Health,
+    Ready,
+    Stats,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -180,7 +587,7 @@ pub enum BuyerResponse {
     CreateAccount(Uuid),
     Login(Uuid),
     Logout,
-    SearchItemsForSale(Vec<Item>),
+    SearchItemsForSale(Vec<SearchResult>),
     GetItem(Option<Item>),
     AddItemToCart,
     RemoveItemFromCart,
@@ -189,13 +596,17 @@ pub enum BuyerResponse {
     DisplayCart(Vec<CartItem>),
     ProvideFeedback,
     GetSellerRating(Feedback),
-    GetBuyerPurchases(Vec<Uuid>),
+    GetBuyerPurchases(Vec<ItemId>),
+    ListCategories(Vec<Category>),
+    Health,
+    Ready(Readiness),
+    Stats(ServiceStats),
     Error(String),
 }
 
 // Database request/response types
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 pub enum CustomerDbRequest {
     CreateSeller {
         seller_name: String,
@@ -234,6 +645,11 @@ pub enum CustomerDbRequest {
         session_id: Uuid,
     },
     CleanupSessions,
+    // Probes and admin stats, as on the frontends. A database has no
+    // dependencies, so it is ready as soon as it answers.
+    Health,
+    Ready,
+    Stats,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -248,19 +664,56 @@ pub enum CustomerDbResponse {
     Session(Option<Session>),
     SessionDeleted,
     SessionsCleaned(usize),
+    Health,
+    Ready(Readiness),
+    Stats(ServiceStats),
     Error(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 pub enum ProductDbRequest {
     CreateItem {
         item: Item,
     },
+    // PatchItem that is only applied if the stored item is still at
+    // `expected_version`, otherwise answered with VersionConflict
     UpdateItem {
-        item: Item,
+        item_id: ItemId,
+        seller_id: Option<Uuid>,
+        patch: ItemPatch,
+        expected_version: u64,
+    },
+    // Field-level mutations, applied atomically in one round trip. Where a
+    // `seller_id` is given, the item must belong to that seller.
+    SetPrice {
+        item_id: ItemId,
+        seller_id: Option<Uuid>,
+        price: f64,
+    },
+    // Rejected if the quantity would end up below `min_result`, or, when
+    // decreasing, below the units held in buyers' carts
+    AdjustQuantity {
+        item_id: ItemId,
+        seller_id: Option<Uuid>,
+        delta: i32,
+        min_result: i32,
+    },
+    PatchItem {
+        item_id: ItemId,
+        seller_id: Option<Uuid>,
+        patch: ItemPatch,
+    },
+    ChangeStatus {
+        item_id: ItemId,
+        seller_id: Option<Uuid>,
+        action: StatusAction,
+    },
+    DeleteItem {
+        item_id: ItemId,
+        seller_id: Option<Uuid>,
     },
     GetItem {
-        item_id: Uuid,
+        item_id: ItemId,
     },
     GetItemsBySeller {
         seller_id: Uuid,
@@ -268,15 +721,18 @@ pub enum ProductDbRequest {
     SearchItems {
         category: Option<i32>,
         keywords: Vec<String>,
+        match_mode: KeywordMatch,
+        filters: SearchFilters,
+        sort: SortOrder,
     },
     AddToCart {
         buyer_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
         quantity: i32,
     },
     RemoveFromCart {
         buyer_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
         quantity: i32,
     },
     GetCart {
@@ -291,23 +747,166 @@ pub enum ProductDbRequest {
     },
     AddPurchaseHistory {
         buyer_id: Uuid,
-        item_id: Uuid,
+        item_id: ItemId,
     },
     GetPurchaseHistory {
         buyer_id: Uuid,
     },
+    // One vote per (buyer, item); `vote: None` withdraws a previous vote.
+    // The seller's rating changes in the same step as the item's.
+    RecordFeedback {
+        buyer_id: Uuid,
+        item_id: ItemId,
+        vote: Option<bool>,
+    },
+    // Votes on all of a seller's items, including items since deleted
+    GetSellerFeedback {
+        seller_id: Uuid,
+    },
+    // Every category with its parent, for building the tree
+    ListCategories,
+    // Category management (admin)
+    CreateCategory {
+        name: String,
+        parent_id: Option<i32>,
+    },
+    RenameCategory {
+        category_id: i32,
+        name: String,
+    },
+    MoveCategory {
+        category_id: i32,
+        parent_id: Option<i32>,
+    },
+    DeleteCategory {
+        category_id: i32,
+    },
+    // Checks every secondary index against the items and repairs any drift (admin)
+    VerifyIndexes,
+    Health,
+    Ready,
+    Stats,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ProductDbResponse {
-    ItemCreated(Uuid),
-    ItemUpdated,
+    ItemCreated(ItemId),
+    // The item changed since it was read; carries the current copy to retry against
+    VersionConflict(Item),
+    // Result of a field-level mutation: the item as stored afterwards
+    ItemChanged(Item),
+    ItemDeleted,
     Item(Option<Item>),
     Items(Vec<Item>),
+    SearchResults(Vec<SearchResult>),
     Cart(Vec<CartItem>),
     CartSaved,
     CartCleared,
-    PurchaseHistory(Vec<Uuid>),
+    PurchaseHistory(Vec<ItemId>),
     PurchaseRecorded,
+    FeedbackRecorded,
+    SellerFeedback(Feedback),
+    Categories(Vec<Category>),
+    CategoryCreated(i32),
+    CategoryUpdated,
+    CategoryDeleted,
+    IndexReport(IndexReport),
+    Health,
+    Ready(Readiness),
+    Stats(ServiceStats),
+    Error(String),
+}
+
+// What the chaos proxy does to the traffic it forwards. Upstream is from the
+// proxy's clients to the component behind it, downstream is the way back.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ChaosSettings {
+    // Added to every chunk of data in each direction, plus up to `jitter_ms`
+    // more; data on one connection stays in order
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    // Shared by all connections, per direction; None for no limit
+    pub bandwidth_bytes_per_sec: Option<u64>,
+    // Share of new connections that are reset
+    pub reset_probability: f64,
+    // Reset connections once the client's first bytes have reached the
+    // component, rather than as soon as they are accepted, so the request may
+    // have been carried out without the client hearing back
+    pub reset_after_request: bool,
+    // Data sent in a partitioned direction is held until the partition is
+    // lifted; once the proxy's buffer is full the sender is held back too
+    pub partition: Partition,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Partition {
+    #[default]
+    None,
+    Upstream,
+    Downstream,
+    Both,
+}
+
+impl Partition {
+    pub fn blocks_upstream(self) -> bool {
+        matches!(self, Partition::Upstream | Partition::Both)
+    }
+
+    pub fn blocks_downstream(self) -> bool {
+        matches!(self, Partition::Downstream | Partition::Both)
+    }
+}
+
+impl FromStr for Partition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Partition::None),
+            "upstream" => Ok(Partition::Upstream),
+            "downstream" => Ok(Partition::Downstream),
+            "both" => Ok(Partition::Both),
+            _ => Err(format!("Invalid partition '{}' (expected none, upstream, downstream or both)", s)),
+        }
+    }
+}
+
+// Requests to a chaos proxy's control port
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ProxyRequest {
+    GetSettings,
+    SetSettings {
+        settings: ChaosSettings,
+    },
+    // Resets the next `connections` new connections, whatever the settings say
+    ResetNext {
+        connections: u64,
+    },
+    // Resets every open connection now
+    ResetOpen,
+    Stats,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ProxyResponse {
+    Settings(ChaosSettings),
+    SettingsUpdated,
+    ResetScheduled,
+    // How many connections were reset
+    OpenReset(u64),
+    Stats(ProxyStats),
     Error(String),
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyStats {
+    pub listen: String,
+    pub upstream: String,
+    // Connections accepted since startup
+    pub connections: u64,
+    pub open_connections: u64,
+    pub resets: u64,
+    pub bytes_upstream: u64,
+    pub bytes_downstream: u64,
+}
@@ -0,0 +1,183 @@
+// Prometheus metrics for the servers. Each process calls `init` once with its
+// name and then `serve` to expose the metrics as text at GET /metrics. Until
+// `init` is called every recording function is a no-op, so shared code can
+// record unconditionally.
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use crate::http::{self, Response};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+// Request latencies range from well under a millisecond (in-memory lookups)
+// to seconds (frontends under load)
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorKind {
+    // The request line could not be parsed
+    InvalidRequest,
+    // The request was handled and answered with an Error response
+    Rejected,
+    // A call from a frontend to a database failed at the transport level
+    BackendUnavailable,
+    // The response could not be written back to the caller
+    Connection,
+}
+
+impl ErrorKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRequest => "invalid_request",
+            ErrorKind::Rejected => "rejected",
+            ErrorKind::BackendUnavailable => "backend_unavailable",
+            ErrorKind::Connection => "connection",
+        }
+    }
+}
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    errors: IntCounterVec,
+    open_connections: IntGauge,
+    backend_duration: HistogramVec,
+    store_entries: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new(service: &str) -> prometheus::Result<Self> {
+        let labels = HashMap::from([("service".to_string(), service.to_string())]);
+        let registry = Registry::new_custom(Some("marketplace".to_string()), Some(labels))?;
+        
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Requests handled, by request type"),
+            &["request"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "Time to handle a request, by request type")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["request"],
+        )?;
+        let errors = IntCounterVec::new(
+            Opts::new("errors_total", "Failed requests, by kind of failure and request type"),
+            &["kind", "request"],
+        )?;
+        let open_connections = IntGauge::new("open_connections", "Client connections currently open")?;
+        let backend_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "backend_request_duration_seconds",
+                "Round trip of a frontend's call to a database, by database and request type",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["backend", "request"],
+        )?;
+        let store_entries = IntGaugeVec::new(
+            Opts::new("store_entries", "Entries in each in-memory map, sampled when scraped"),
+            &["map"],
+        )?;
+        
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(errors.clone()))?;
+        registry.register(Box::new(open_connections.clone()))?;
+        registry.register(Box::new(backend_duration.clone()))?;
+        registry.register(Box::new(store_entries.clone()))?;
+        
+        Ok(Metrics {
+            registry,
+            requests,
+            request_duration,
+            errors,
+            open_connections,
+            backend_duration,
+            store_entries,
+        })
+    }
+}
+
+pub fn init(service: &str) {
+    match Metrics::new(service) {
+        Ok(metrics) => {
+            let _ = METRICS.set(metrics);
+        }
+        Err(e) => tracing::error!(error = %e, "failed to set up metrics"),
+    }
+}
+
+pub fn observe_request(request: &str, elapsed: Duration) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.requests.with_label_values(&[request]).inc();
+        metrics.request_duration.with_label_values(&[request]).observe(elapsed.as_secs_f64());
+    }
+}
+
+pub fn count_error(kind: ErrorKind, request: &str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.errors.with_label_values(&[kind.as_str(), request]).inc();
+    }
+}
+
+pub fn observe_backend(backend: &str, request: &str, elapsed: Duration) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.backend_duration.with_label_values(&[backend, request]).observe(elapsed.as_secs_f64());
+    }
+}
+
+pub fn set_store_entries(map: &str, entries: usize) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.store_entries.with_label_values(&[map]).set(entries as i64);
+    }
+}
+
+// Counts a connection as open until the guard is dropped
+pub fn connection_opened() -> ConnectionGuard {
+    if let Some(metrics) = METRICS.get() {
+        metrics.open_connections.inc();
+    }
+    ConnectionGuard
+}
+
+pub struct ConnectionGuard;
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(metrics) = METRICS.get() {
+            metrics.open_connections.dec();
+        }
+    }
+}
+
+// Serves GET /metrics on `addr` in the background. `refresh` runs before each
+// scrape to sample gauges that are cheaper to read on demand, like map sizes.
+pub async fn serve(addr: &str, refresh: impl Fn() + Send + Sync + 'static) -> std::io::Result<()> {
+    http::serve(addr, move |path| {
+        let response = if path == "/metrics" {
+            refresh();
+            Response::ok("text/plain; version=0.0.4", render())
+        } else {
+            Response::not_found()
+        };
+        std::future::ready(response)
+    })
+    .await
+}
+
+fn render() -> String {
+    let metrics = match METRICS.get() {
+        Some(metrics) => metrics,
+        None => return String::new(),
+    };
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        tracing::error!(error = %e, "failed to encode metrics");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
@@ -0,0 +1,82 @@
+// Liveness, readiness and the figures behind the `Stats` request. Each server
+// calls `init` once at startup and counts client connections through
+// `connection_opened`.
+
+use crate::http::{self, Response};
+use crate::metrics;
+use crate::{Readiness, ServiceStats};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+static STARTED: OnceLock<(&'static str, Instant)> = OnceLock::new();
+static OPEN_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+static TOTAL_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub fn init(service: &'static str) {
+    let _ = STARTED.set((service, Instant::now()));
+}
+
+// Counts a connection as open until the guard is dropped
+pub fn connection_opened() -> ConnectionGuard {
+    OPEN_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    TOTAL_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    ConnectionGuard {
+        _metrics: metrics::connection_opened(),
+    }
+}
+
+pub struct ConnectionGuard {
+    _metrics: metrics::ConnectionGuard,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        OPEN_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// This process's uptime and connection counts, plus the sizes of whatever
+// state the caller holds
+pub fn stats(entries: BTreeMap<String, u64>) -> ServiceStats {
+    let (service, started) = STARTED.get().copied().unwrap_or(("unknown", Instant::now()));
+    ServiceStats {
+        service: service.to_string(),
+        uptime_secs: started.elapsed().as_secs(),
+        open_connections: OPEN_CONNECTIONS.load(Ordering::Relaxed),
+        total_connections: TOTAL_CONNECTIONS.load(Ordering::Relaxed),
+        entries,
+    }
+}
+
+// Serves the probes on `addr` in the background: GET /healthz answers 200
+// while the process is up, GET /readyz answers 200 or 503 depending on what
+// `ready` reports, with the readiness as JSON.
+pub async fn serve_probes<R, F>(addr: &str, ready: R) -> std::io::Result<()>
+where
+    R: Fn() -> F + Send + Sync + 'static,
+    F: Future<Output = Readiness> + Send,
+{
+    let ready = std::sync::Arc::new(ready);
+    http::serve(addr, move |path| {
+        let ready = ready.clone();
+        async move {
+            match path.as_str() {
+                "/healthz" => Response::ok("text/plain", "ok\n".to_string()),
+                "/readyz" => {
+                    let readiness = ready().await;
+                    let body = serde_json::to_string(&readiness).unwrap_or_default() + "\n";
+                    let mut response = Response::ok("application/json", body);
+                    if !readiness.ready {
+                        response.status = "503 Service Unavailable";
+                    }
+                    response
+                }
+                _ => Response::not_found(),
+            }
+        }
+    })
+    .await
+}
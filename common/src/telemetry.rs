@@ -0,0 +1,286 @@
+// Structured logging and distributed tracing. A frontend starts a trace for
+// each client request (or joins the one the client sent) and passes it to the
+// databases in every `Envelope`, so one client operation can be followed
+// through all of its backend calls.
+//
+// RUST_LOG sets the filter (default "info"; "debug" logs every request) and
+// LOG_FORMAT=json writes one JSON object per line instead of plain text.
+//
+// TRACE_EXPORT additionally exports spans: "otlp" sends them over OTLP/HTTP to
+// OTEL_EXPORTER_OTLP_ENDPOINT (default http://localhost:4318), "file" appends
+// them as JSON lines to TRACE_FILE (default <service>_traces.jsonl). Unset or
+// "none" exports nothing.
+
+use crate::{Envelope, SpanId, TraceId};
+use opentelemetry::trace::{
+    SpanContext, TraceContextExt, TraceFlags, TraceState, TracerProvider as _,
+};
+use opentelemetry::Context;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{display, Empty};
+use tracing::{info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+static SERVICE: OnceLock<&'static str> = OnceLock::new();
+
+tokio::task_local! {
+    static TRACE_ID: TraceId;
+}
+
+// Keeps span export running; dropping it flushes the spans not yet exported
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+// Logging plus span export, for the servers
+pub fn init(service: &'static str) -> Telemetry {
+    let _ = SERVICE.set(service);
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = std::env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json"));
+    let provider = trace_provider(service);
+    let otel = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(service)));
+    let text = (!json).then(|| tracing_subscriber::fmt::layer().with_target(false));
+    let json = json.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+    });
+
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .with(otel)
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("Failed to set up logging: {}", e);
+    }
+    Telemetry { provider }
+}
+
+// Span export only, for the command-line clients whose output is for the user
+pub fn init_client(service: &'static str) -> Telemetry {
+    let _ = SERVICE.set(service);
+
+    let provider = trace_provider(service);
+    if let Some(provider) = &provider {
+        let result = tracing_subscriber::registry()
+            .with(EnvFilter::new("info"))
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(service)))
+            .try_init();
+        if let Err(e) = result {
+            eprintln!("Failed to set up tracing: {}", e);
+        }
+    }
+    Telemetry { provider }
+}
+
+fn trace_provider(service: &'static str) -> Option<SdkTracerProvider> {
+    let export = std::env::var("TRACE_EXPORT").unwrap_or_else(|_| "none".to_string());
+    let builder = SdkTracerProvider::builder()
+        .with_resource(Resource::builder().with_service_name(service).build());
+
+    let builder = match export.to_ascii_lowercase().as_str() {
+        "" | "none" => return None,
+        "otlp" => match opentelemetry_otlp::SpanExporter::builder().with_http().build() {
+            Ok(exporter) => builder.with_batch_exporter(exporter),
+            Err(e) => {
+                eprintln!("Failed to set up OTLP trace export: {}", e);
+                return None;
+            }
+        },
+        "file" => {
+            let path = std::env::var("TRACE_FILE")
+                .unwrap_or_else(|_| format!("{}_traces.jsonl", service));
+            match FileExporter::open(service, &path) {
+                Ok(exporter) => builder.with_batch_exporter(exporter),
+                Err(e) => {
+                    eprintln!("Failed to open trace file {}: {}", path, e);
+                    return None;
+                }
+            }
+        }
+        other => {
+            eprintln!("Unknown TRACE_EXPORT '{}' (expected none, otlp or file)", other);
+            return None;
+        }
+    };
+    Some(builder.build())
+}
+
+fn service() -> &'static str {
+    SERVICE.get().copied().unwrap_or("unknown")
+}
+
+// The span for one incoming request and the trace it belongs to. A caller that
+// sent its own span becomes the parent. A caller that sent only a trace ID
+// (because it exports no spans) gets a stand-in parent, so the exported span
+// still carries the trace ID the logs show. A request arriving without a trace
+// takes the exported span's trace ID, or a fresh one when nothing is exported.
+pub fn request_span(
+    request: &str,
+    trace_id: Option<TraceId>,
+    parent_span_id: Option<SpanId>,
+) -> (Span, TraceId) {
+    let service = service();
+    let span = info_span!(
+        "request",
+        otel.name = %format_args!("{} {}", service, request),
+        otel.kind = "server",
+        service,
+        trace_id = Empty,
+        request,
+    );
+    if let Some(trace_id) = trace_id {
+        let span_id = parent_span_id.unwrap_or_else(SpanId::generate);
+        let parent = SpanContext::new(
+            trace_id.0.into(),
+            span_id.0.into(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let _ = span.set_parent(Context::new().with_remote_span_context(parent));
+    }
+
+    let trace_id = trace_id
+        .or_else(|| exported_ids(&span).map(|(trace_id, _)| trace_id))
+        .unwrap_or_else(TraceId::generate);
+    span.record("trace_id", display(trace_id));
+    (span, trace_id)
+}
+
+// The span for one outgoing call to `target`, a child of the current request
+pub fn call_span(target: &str, request: &str) -> Span {
+    info_span!(
+        "call",
+        otel.name = %format_args!("{} {}", target, request),
+        otel.kind = "client",
+        target,
+        request,
+    )
+}
+
+// Wraps a request sent from within `span` so the callee can join its trace
+pub fn envelope<T>(span: &Span, request: T) -> Envelope<T> {
+    match exported_ids(span) {
+        Some((trace_id, span_id)) => Envelope {
+            trace_id,
+            parent_span_id: Some(span_id),
+            request,
+        },
+        None => Envelope {
+            trace_id: current_trace_id(),
+            parent_span_id: None,
+            request,
+        },
+    }
+}
+
+// The IDs of a span that is being exported. Starts the span if it hasn't yet.
+fn exported_ids(span: &Span) -> Option<(TraceId, SpanId)> {
+    let cx = span.context();
+    let span_context = cx.span().span_context().clone();
+    span_context.is_valid().then(|| {
+        (
+            TraceId(u128::from_be_bytes(span_context.trace_id().to_bytes())),
+            SpanId(u64::from_be_bytes(span_context.span_id().to_bytes())),
+        )
+    })
+}
+
+// Runs one request inside its span. Backend calls made while it runs pick the
+// trace ID up through `current_trace_id`.
+pub async fn traced<F: Future>(span: Span, trace_id: TraceId, f: F) -> F::Output {
+    TRACE_ID.scope(trace_id, f.instrument(span)).await
+}
+
+// The trace being served, or a fresh one outside of `traced`
+pub fn current_trace_id() -> TraceId {
+    TRACE_ID.try_with(|trace_id| *trace_id).unwrap_or_else(|_| TraceId::generate())
+}
+
+// Appends finished spans to a file, one JSON object per line, for use without
+// a collector
+#[derive(Debug)]
+struct FileExporter {
+    service: &'static str,
+    file: Mutex<File>,
+}
+
+impl FileExporter {
+    fn open(service: &'static str, path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileExporter { service, file: Mutex::new(file) })
+    }
+
+    fn to_json(&self, span: &SpanData) -> serde_json::Value {
+        let parent_span_id = (span.parent_span_id != opentelemetry::trace::SpanId::INVALID)
+            .then(|| span.parent_span_id.to_string());
+        let attributes: serde_json::Map<String, serde_json::Value> = span
+            .attributes
+            .iter()
+            .map(|kv| (kv.key.to_string(), kv.value.to_string().into()))
+            .collect();
+        let duration = span.end_time.duration_since(span.start_time).unwrap_or_default();
+
+        serde_json::json!({
+            "service": self.service,
+            "trace_id": span.span_context.trace_id().to_string(),
+            "span_id": span.span_context.span_id().to_string(),
+            "parent_span_id": parent_span_id,
+            "name": span.name,
+            "kind": format!("{:?}", span.span_kind).to_lowercase(),
+            "start_unix_us": unix_micros(span.start_time),
+            "duration_us": duration.as_micros() as u64,
+            "status": format!("{:?}", span.status),
+            "attributes": attributes,
+        })
+    }
+}
+
+fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64
+}
+
+impl SpanExporter for FileExporter {
+    fn export(&self, batch: Vec<SpanData>) -> impl Future<Output = OTelSdkResult> + Send {
+        let mut lines = String::new();
+        for span in &batch {
+            lines.push_str(&self.to_json(span).to_string());
+            lines.push('\n');
+        }
+        let result = match self.file.lock() {
+            Ok(mut file) => file
+                .write_all(lines.as_bytes())
+                .map_err(|e| OTelSdkError::InternalFailure(e.to_string())),
+            Err(_) => Err(OTelSdkError::InternalFailure("Trace file lock poisoned".to_string())),
+        };
+        std::future::ready(result)
+    }
+}
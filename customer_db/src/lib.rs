@@ -0,0 +1,257 @@
+// The customer database: sellers, buyers and login sessions, kept in memory.
+// `serve` runs the server loop on a listener the caller has bound, so the
+// binary and in-process tests start it the same way.
+
+use common::clock::Clock;
+use common::metrics::{self, ErrorKind};
+use common::{status, telemetry};
+use common::*;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+// All customer DB state, shared by every connection
+pub struct Store {
+    pub sellers: DashMap<Uuid, Seller>,
+    pub buyers: DashMap<Uuid, Buyer>,
+    pub sessions: DashMap<Uuid, Session>,
+    clock: Arc<dyn Clock>,
+    // Sessions expire after going unused this long
+    session_timeout: Duration,
+}
+
+impl Store {
+    pub fn new(clock: Arc<dyn Clock>, session_timeout: Duration) -> Self {
+        Store {
+            sellers: DashMap::new(),
+            buyers: DashMap::new(),
+            sessions: DashMap::new(),
+            clock,
+            session_timeout,
+        }
+    }
+    
+    // When a session created or used now expires
+    fn session_expiration(&self) -> i64 {
+        self.clock.now() + self.session_timeout.as_secs() as i64
+    }
+    
+    // Removes every session past its expiration and returns how many there were
+    fn remove_expired_sessions(&self) -> usize {
+        let now = self.clock.now();
+        let expired: Vec<Uuid> = self.sessions.iter()
+            .filter(|s| s.expiration < now)
+            .map(|s| s.session_id)
+            .collect();
+        
+        for session_id in &expired {
+            self.sessions.remove(session_id);
+        }
+        
+        expired.len()
+    }
+    
+    // Publishes the store sizes as metrics
+    pub fn record_entries(&self) {
+        metrics::set_store_entries("sellers", self.sellers.len());
+        metrics::set_store_entries("buyers", self.buyers.len());
+        metrics::set_store_entries("sessions", self.sessions.len());
+    }
+}
+
+// Serves requests from `listener` until accepting a connection fails, and
+// removes expired sessions in the background meanwhile
+pub async fn serve(listener: TcpListener, store: Arc<Store>) -> std::io::Result<()> {
+    let cleaner = store.clone();
+    tokio::spawn(async move {
+        cleanup_sessions(cleaner).await;
+    });
+    
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let store = store.clone();
+        
+        tokio::spawn(async move {
+            handle_connection(socket, store).await;
+        });
+    }
+}
+
+async fn handle_connection(socket: TcpStream, store: Arc<Store>) {
+    let _connection = status::connection_opened();
+    let (read_half, mut write_half) = socket.into_split();
+    let reader = BufReader::new(read_half);
+    let mut lines = reader.lines();
+    
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Envelope { trace_id, parent_span_id, request } = match serde_json::from_str::<Envelope<CustomerDbRequest>>(&line) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!(error = %e, "invalid request");
+                metrics::count_error(ErrorKind::InvalidRequest, "unknown");
+                let response = CustomerDbResponse::Error(format!("Invalid request: {}", e));
+                let _ = send_response(&mut write_half, response).await;
+                continue;
+            }
+        };
+        
+        let name: &'static str = (&request).into();
+        let (span, trace_id) = telemetry::request_span(name, Some(trace_id), parent_span_id);
+        let response = telemetry::traced(span, trace_id, async {
+            let started = Instant::now();
+            let response = handle_request(request, &store).await;
+            let elapsed = started.elapsed();
+            metrics::observe_request(name, elapsed);
+            if let CustomerDbResponse::Error(msg) = &response {
+                metrics::count_error(ErrorKind::Rejected, name);
+                info!(error = %msg, elapsed_us = elapsed.as_micros() as u64, "request rejected");
+            } else {
+                debug!(elapsed_us = elapsed.as_micros() as u64, "request handled");
+            }
+            response
+        }).await;
+        if let Err(e) = send_response(&mut write_half, response).await {
+            warn!(%trace_id, request = name, error = %e, "failed to send response");
+            metrics::count_error(ErrorKind::Connection, name);
+        }
+    }
+}
+
+async fn handle_request(request: CustomerDbRequest, store: &Store) -> CustomerDbResponse {
+    let Store { sellers, buyers, sessions, .. } = store;
+    
+    match request {
+        CustomerDbRequest::CreateSeller { seller_name, password } => {
+            let seller_id = Uuid::new_v4();
+            let seller = Seller {
+                seller_id,
+                seller_name,
+                items_sold: 0,
+                password,
+            };
+            sellers.insert(seller_id, seller);
+            CustomerDbResponse::SellerCreated(seller_id)
+        }
+        
+        CustomerDbRequest::CreateBuyer { buyer_name, password } => {
+            let buyer_id = Uuid::new_v4();
+            let buyer = Buyer {
+                buyer_id,
+                buyer_name,
+                items_purchased: 0,
+                password,
+            };
+            buyers.insert(buyer_id, buyer);
+            CustomerDbResponse::BuyerCreated(buyer_id)
+        }
+        
+        CustomerDbRequest::GetSellerByName { seller_name } => {
+            let seller = sellers.iter()
+                .find(|s| s.seller_name == seller_name)
+                .map(|s| s.value().clone());
+            CustomerDbResponse::Seller(seller)
+        }
+        
+        CustomerDbRequest::GetBuyerByName { buyer_name } => {
+            let buyer = buyers.iter()
+                .find(|b| b.buyer_name == buyer_name)
+                .map(|b| b.value().clone());
+            CustomerDbResponse::Buyer(buyer)
+        }
+        
+        CustomerDbRequest::GetSeller { seller_id } => {
+            let seller = sellers.get(&seller_id).map(|s| s.clone());
+            CustomerDbResponse::Seller(seller)
+        }
+        
+        CustomerDbRequest::UpdateSeller { seller } => {
+            sellers.insert(seller.seller_id, seller);
+            CustomerDbResponse::SellerUpdated
+        }
+        
+        CustomerDbRequest::GetBuyer { buyer_id } => {
+            let buyer = buyers.get(&buyer_id).map(|b| b.clone());
+            CustomerDbResponse::Buyer(buyer)
+        }
+        
+        CustomerDbRequest::UpdateBuyer { buyer } => {
+            buyers.insert(buyer.buyer_id, buyer);
+            CustomerDbResponse::BuyerUpdated
+        }
+        
+        CustomerDbRequest::CreateSession { user_id, user_type } => {
+            let session_id = Uuid::new_v4();
+            let expiration = store.session_expiration();
+            let session = Session {
+                session_id,
+                user_id,
+                user_type,
+                expiration,
+            };
+            sessions.insert(session_id, session);
+            CustomerDbResponse::SessionCreated(session_id, expiration)
+        }
+        
+        CustomerDbRequest::GetSession { session_id } => {
+            let session = sessions.get(&session_id).map(|s| s.clone());
+            // Refresh expiration on use, so sessions time out after inactivity (per assignment)
+            if let Some(ref s) = session {
+                let mut updated = s.clone();
+                updated.expiration = store.session_expiration();
+                sessions.insert(session_id, updated);
+            }
+            CustomerDbResponse::Session(session)
+        }
+        
+        CustomerDbRequest::DeleteSession { session_id } => {
+            sessions.remove(&session_id);
+            CustomerDbResponse::SessionDeleted
+        }
+        
+        CustomerDbRequest::CleanupSessions => {
+            CustomerDbResponse::SessionsCleaned(store.remove_expired_sessions())
+        }
+        
+        CustomerDbRequest::Health => CustomerDbResponse::Health,
+        
+        CustomerDbRequest::Ready => CustomerDbResponse::Ready(Readiness::of(Vec::new())),
+        
+        CustomerDbRequest::Stats => {
+            // Expired sessions linger until the next cleanup; leave them out
+            let now = store.clock.now();
+            let active_sessions = sessions.iter().filter(|s| s.expiration >= now).count();
+            let entries = BTreeMap::from([
+                ("sellers".to_string(), sellers.len() as u64),
+                ("buyers".to_string(), buyers.len() as u64),
+                ("active_sessions".to_string(), active_sessions as u64),
+            ]);
+            CustomerDbResponse::Stats(status::stats(entries))
+        }
+    }
+}
+
+async fn send_response(
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    response: CustomerDbResponse,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response_str = serde_json::to_string(&response)?;
+    writer.write_all(response_str.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
+
+async fn cleanup_sessions(store: Arc<Store>) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        
+        let expired = store.remove_expired_sessions();
+        if expired > 0 {
+            info!(expired, "cleaned up expired sessions");
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,185 @@
+// Runs the four services as child processes on ports the OS picks, so an
+// evaluation needs nothing else running and components can be killed and
+// started again mid-run. A restarted component keeps its ports, so the
+// others find it where it was. Each component's output is appended to
+// `<dir>/<component>.log`, and product_db keeps its sequence file in `dir`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+
+// How long a component may take to accept connections after starting
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    CustomerDb,
+    ProductDb,
+    SellerServer,
+    BuyerServer,
+}
+
+impl Component {
+    // In start order: the databases before the frontends that use them
+    pub const ALL: [Component; 4] = [
+        Component::CustomerDb,
+        Component::ProductDb,
+        Component::SellerServer,
+        Component::BuyerServer,
+    ];
+
+    // Also the binary's name
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::CustomerDb => "customer_db",
+            Component::ProductDb => "product_db",
+            Component::SellerServer => "seller_server",
+            Component::BuyerServer => "buyer_server",
+        }
+    }
+
+    // Prefix of the environment variables the component is configured with
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Component::CustomerDb => "CUSTOMER_DB",
+            Component::ProductDb => "PRODUCT_DB",
+            Component::SellerServer => "SELLER_SERVER",
+            Component::BuyerServer => "BUYER_SERVER",
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+struct Service {
+    addr: String,
+    env: Vec<(String, String)>,
+    // None while the component is down
+    child: Option<Child>,
+}
+
+pub struct Cluster {
+    bin_dir: PathBuf,
+    dir: PathBuf,
+    services: BTreeMap<Component, Service>,
+}
+
+impl Cluster {
+    // Starts every component and waits until each accepts connections.
+    // Children are killed when the cluster is dropped.
+    pub async fn launch(bin_dir: &Path, dir: &Path) -> Result<Self, String> {
+        for component in Component::ALL {
+            let binary = bin_dir.join(component.name());
+            if !binary.is_file() {
+                return Err(format!("{} not found; build the workspace first", binary.display()));
+            }
+        }
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let mut addrs = BTreeMap::new();
+        for component in Component::ALL {
+            addrs.insert(component, free_addr()?);
+        }
+        let mut services = BTreeMap::new();
+        for component in Component::ALL {
+            let prefix = component.env_prefix();
+            let mut env = vec![
+                (format!("{}_BIND_ADDR", prefix), addrs[&component].clone()),
+                (format!("{}_METRICS_ADDR", prefix), free_addr()?),
+                (format!("{}_PROBE_ADDR", prefix), free_addr()?),
+            ];
+            match component {
+                Component::CustomerDb => {}
+                Component::ProductDb => {
+                    env.push(("PRODUCT_DB_DATA_DIR".to_string(), dir.display().to_string()));
+                }
+                Component::SellerServer | Component::BuyerServer => {
+                    env.push(("CUSTOMER_DB_ADDR".to_string(), addrs[&Component::CustomerDb].clone()));
+                    env.push(("PRODUCT_DB_ADDR".to_string(), addrs[&Component::ProductDb].clone()));
+                }
+            }
+            services.insert(component, Service { addr: addrs[&component].clone(), env, child: None });
+        }
+
+        let mut cluster = Cluster {
+            bin_dir: bin_dir.to_path_buf(),
+            dir: dir.to_path_buf(),
+            services,
+        };
+        for component in Component::ALL {
+            cluster.start(component).await?;
+        }
+        Ok(cluster)
+    }
+
+    // Where the component takes requests
+    pub fn addr(&self, component: Component) -> &str {
+        &self.services[&component].addr
+    }
+
+    // Starts the component if it is down and waits until it accepts connections
+    pub async fn start(&mut self, component: Component) -> Result<(), String> {
+        let log_path = self.dir.join(format!("{}.log", component.name()));
+        let service = self.services.get_mut(&component).expect("every component has a service");
+        if service.child.is_some() {
+            return Ok(());
+        }
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .map_err(|e| format!("Failed to open {}: {}", log_path.display(), e))?;
+        let stderr = log.try_clone().map_err(|e| format!("Failed to open {}: {}", log_path.display(), e))?;
+        let child = Command::new(self.bin_dir.join(component.name()))
+            .envs(service.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(stderr)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", component, e))?;
+        let child = service.child.insert(child);
+        wait_ready(component, &service.addr, child, &log_path).await
+    }
+
+    // Kills the component without warning, as a crash would
+    pub async fn kill(&mut self, component: Component) -> Result<(), String> {
+        let service = self.services.get_mut(&component).expect("every component has a service");
+        if let Some(mut child) = service.child.take() {
+            child.kill().await.map_err(|e| format!("Failed to kill {}: {}", component, e))?;
+        }
+        Ok(())
+    }
+}
+
+// A loopback address with a port that was free a moment ago
+fn free_addr() -> Result<String, String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Failed to pick a port: {}", e))?;
+    let addr = listener.local_addr().map_err(|e| format!("Failed to pick a port: {}", e))?;
+    Ok(addr.to_string())
+}
+
+async fn wait_ready(component: Component, addr: &str, child: &mut Child, log: &Path) -> Result<(), String> {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+        if tokio::net::TcpStream::connect(addr).await.is_ok() {
+            return Ok(());
+        }
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("{} exited with {}; see {}", component, status, log.display()));
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("{} did not accept connections on {} within {:?}", component, addr, READY_TIMEOUT));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
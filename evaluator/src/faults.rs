@@ -0,0 +1,294 @@
+// Fault injection: kills and restarts launched components on a profile's
+// schedule while a run is measured, then sums up what clients saw. A fault's
+// window runs from the kill until shortly after the component accepts
+// connections again; calls sent in it are counted toward the fault, the rest
+// toward the run outside any fault. After the run the databases are asked for
+// every account and item the run created, so whatever a restart lost shows up
+// as data loss.
+
+use crate::cluster::{Cluster, Component};
+use crate::profile::Fault;
+use crate::workload::{self, RunResult};
+use common::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+// Calls sent this long after a component is back still count toward its fault
+const RECOVERY_GRACE: Duration = Duration::from_secs(1);
+
+pub enum CallResult {
+    Ok,
+    Error(String),
+    NoResponse,
+}
+
+struct Call {
+    sent: Instant,
+    api: &'static str,
+    result: CallResult,
+}
+
+// Every measured call of a run, as the clients saw it
+#[derive(Default)]
+pub struct CallLog {
+    calls: Mutex<Vec<Call>>,
+}
+
+impl CallLog {
+    pub fn record(&self, sent: Instant, api: &'static str, result: CallResult) {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).push(Call { sent, api, result });
+    }
+}
+
+// A fault as it was carried out
+pub struct Injected {
+    fault: Fault,
+    killed: Instant,
+    // False if the kill failed, so the component never went down
+    went_down: bool,
+    // When the component accepted connections again
+    ready: Option<Instant>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CallCounts {
+    pub calls: u64,
+    pub errors: u64,
+    pub no_response: u64,
+    // Errors saying the client's session is gone
+    pub session_lost: u64,
+    // Calls per "API: error", unanswered ones as "API: no response"
+    pub by_error: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FaultSummary {
+    pub component: Component,
+    // When the component was killed, from the start of the measured window
+    pub at_secs: f64,
+    pub down_secs: f64,
+    // From the kill until the component accepted connections again; None if
+    // it did not come back
+    pub recovery_secs: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub calls: CallCounts,
+}
+
+// A fault whose kill failed; it has no window, so its calls count as outside
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailedFault {
+    pub component: Component,
+    pub at_secs: f64,
+    pub error: String,
+}
+
+// Accounts and items the run created that the databases no longer have
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataLoss {
+    pub sellers: usize,
+    pub sellers_lost: usize,
+    pub buyers: usize,
+    pub buyers_lost: usize,
+    pub items: usize,
+    pub items_lost: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FaultReport {
+    pub faults: Vec<FaultSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub not_injected: Vec<FailedFault>,
+    // Calls sent outside every fault window
+    pub outside: CallCounts,
+    // None if the databases could not be asked
+    pub data_loss: Option<DataLoss>,
+}
+
+// Carries out the faults, timed from `started`, until they are all done or
+// `done` fires. A killed component is always started again, even if the run
+// ends while it is down.
+pub async fn inject(cluster: &mut Cluster, faults: &[Fault], started: Instant, mut done: oneshot::Receiver<()>) -> Vec<Injected> {
+    let mut faults = faults.to_vec();
+    faults.sort_by(|a, b| a.at_secs.total_cmp(&b.at_secs));
+    let mut injected = Vec::with_capacity(faults.len());
+    for fault in faults {
+        let due = started + Duration::from_secs_f64(fault.at_secs);
+        tokio::select! {
+            _ = tokio::time::sleep_until(tokio::time::Instant::from_std(due)) => {}
+            _ = &mut done => break,
+        }
+        let killed = Instant::now();
+        if let Err(e) = cluster.kill(fault.component).await {
+            injected.push(Injected { fault, killed, went_down: false, ready: None, error: Some(e) });
+            continue;
+        }
+        println!("  Killed {} at {:.1}s", fault.component, killed.duration_since(started).as_secs_f64());
+        tokio::time::sleep(Duration::from_secs_f64(fault.down_secs)).await;
+        let (ready, error) = match cluster.start(fault.component).await {
+            Ok(()) => (Some(Instant::now()), None),
+            Err(e) => (None, Some(e)),
+        };
+        injected.push(Injected { fault, killed, went_down: true, ready, error });
+    }
+    injected
+}
+
+// Looks up every account and item of the run in the databases
+pub async fn audit(cluster: &Cluster, run: &RunResult) -> Result<DataLoss, String> {
+    let customer_db = cluster.addr(Component::CustomerDb);
+    let product_db = cluster.addr(Component::ProductDb);
+    let mut loss = DataLoss {
+        sellers: run.sellers.len(),
+        sellers_lost: 0,
+        buyers: run.buyers.len(),
+        buyers_lost: 0,
+        items: run.catalog.len(),
+        items_lost: 0,
+    };
+    for seller_name in &run.sellers {
+        let request = CustomerDbRequest::GetSellerByName { seller_name: seller_name.clone() };
+        match ask(customer_db, request).await {
+            Ok(CustomerDbResponse::Seller(Some(_))) => {}
+            Ok(CustomerDbResponse::Seller(None)) => loss.sellers_lost += 1,
+            Ok(other) => return Err(format!("Unexpected response from customer_db: {:?}", other)),
+            Err(e) => return Err(format!("Failed to reach customer_db: {}", e)),
+        }
+    }
+    for buyer_name in &run.buyers {
+        let request = CustomerDbRequest::GetBuyerByName { buyer_name: buyer_name.clone() };
+        match ask(customer_db, request).await {
+            Ok(CustomerDbResponse::Buyer(Some(_))) => {}
+            Ok(CustomerDbResponse::Buyer(None)) => loss.buyers_lost += 1,
+            Ok(other) => return Err(format!("Unexpected response from customer_db: {:?}", other)),
+            Err(e) => return Err(format!("Failed to reach customer_db: {}", e)),
+        }
+    }
+    for item_id in &run.catalog {
+        match ask(product_db, ProductDbRequest::GetItem { item_id: *item_id }).await {
+            Ok(ProductDbResponse::Item(Some(_))) => {}
+            Ok(ProductDbResponse::Item(None)) => loss.items_lost += 1,
+            Ok(other) => return Err(format!("Unexpected response from product_db: {:?}", other)),
+            Err(e) => return Err(format!("Failed to reach product_db: {}", e)),
+        }
+    }
+    Ok(loss)
+}
+
+// Databases only take requests in an envelope
+async fn ask<T, U>(addr: &str, request: T) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: Serialize,
+    U: for<'de> Deserialize<'de>,
+{
+    let envelope = Envelope {
+        trace_id: TraceId::generate(),
+        parent_span_id: None,
+        request,
+    };
+    workload::send_and_receive(addr, envelope).await
+}
+
+impl FaultReport {
+    pub fn new(injected: &[Injected], started: Instant, calls: &CallLog, data_loss: Option<DataLoss>) -> Self {
+        let (injected, failed): (Vec<&Injected>, Vec<&Injected>) = injected.iter().partition(|injected| injected.went_down);
+        let not_injected = failed
+            .into_iter()
+            .map(|failed| FailedFault {
+                component: failed.fault.component,
+                at_secs: failed.killed.duration_since(started).as_secs_f64(),
+                error: failed.error.clone().unwrap_or_default(),
+            })
+            .collect();
+        let mut faults: Vec<FaultSummary> = injected
+            .iter()
+            .map(|injected| FaultSummary {
+                component: injected.fault.component,
+                at_secs: injected.killed.duration_since(started).as_secs_f64(),
+                down_secs: injected.fault.down_secs,
+                recovery_secs: injected.ready.map(|ready| ready.duration_since(injected.killed).as_secs_f64()),
+                error: injected.error.clone(),
+                calls: CallCounts::default(),
+            })
+            .collect();
+        let mut outside = CallCounts::default();
+        for call in calls.calls.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            // A component that did not come back keeps its window open
+            let window = injected.iter().position(|injected| {
+                let before_end = match injected.ready {
+                    Some(ready) => call.sent < ready + RECOVERY_GRACE,
+                    None => true,
+                };
+                call.sent >= injected.killed && before_end
+            });
+            match window {
+                Some(index) => faults[index].calls.count(call),
+                None => outside.count(call),
+            }
+        }
+        FaultReport { faults, not_injected, outside, data_loss }
+    }
+}
+
+impl CallCounts {
+    fn count(&mut self, call: &Call) {
+        self.calls += 1;
+        match &call.result {
+            CallResult::Ok => {}
+            CallResult::Error(msg) => {
+                self.errors += 1;
+                if workload::session_lost(msg) {
+                    self.session_lost += 1;
+                }
+                *self.by_error.entry(format!("{}: {}", call.api, msg)).or_insert(0) += 1;
+            }
+            CallResult::NoResponse => {
+                self.no_response += 1;
+                *self.by_error.entry(format!("{}: no response", call.api)).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn injected(killed: Instant, went_down: bool, ready: Option<Instant>, error: Option<&str>) -> Injected {
+        Injected {
+            fault: Fault { component: Component::ProductDb, at_secs: 0.0, down_secs: 1.0 },
+            killed,
+            went_down,
+            ready,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn failed_kill_opens_no_window() {
+        let started = Instant::now();
+        let at = |secs: u64| started + Duration::from_secs(secs);
+        let calls = CallLog::default();
+        calls.record(at(3), "SearchItemsForSale", CallResult::Ok);
+        calls.record(at(12), "SearchItemsForSale", CallResult::NoResponse);
+        calls.record(at(30), "SearchItemsForSale", CallResult::Ok);
+
+        let injected = [
+            injected(at(2), false, None, Some("Failed to kill product_db")),
+            injected(at(10), true, Some(at(14)), None),
+        ];
+        let report = FaultReport::new(&injected, started, &calls, None);
+
+        assert_eq!(report.faults.len(), 1);
+        assert_eq!(report.faults[0].at_secs, 10.0);
+        assert_eq!((report.faults[0].calls.calls, report.faults[0].calls.no_response), (1, 1));
+        assert_eq!(report.outside.calls, 2);
+        assert_eq!(report.not_injected.len(), 1);
+        assert_eq!(report.not_injected[0].at_secs, 2.0);
+        assert_eq!(report.not_injected[0].error, "Failed to kill product_db");
+    }
+}
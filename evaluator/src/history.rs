@@ -0,0 +1,329 @@
+// Recorded histories of the calls that read or change items and carts. Each
+// entry is one call: who made it, when it was sent and answered, and what it
+// did, reduced to what the sequential model in `linearizability` needs.
+// Searches, listings and ratings are not recorded.
+
+use common::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Entry {
+    // The seller or buyer account; every client has its own
+    pub client: String,
+    // Microseconds since the run started
+    pub invoked_us: u64,
+    // None if no response came back
+    pub completed_us: Option<u64>,
+    pub op: Op,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "op")]
+pub enum Op {
+    Register { item: ItemId, price: f64, quantity: i32 },
+    SetPrice { item: ItemId, price: f64 },
+    SetQuantity { item: ItemId, quantity: i32 },
+    GetItem { item: ItemId },
+    AddToCart { item: ItemId, quantity: i32 },
+    RemoveFromCart { item: ItemId, quantity: i32 },
+    DisplayCart,
+    SaveCart,
+    ClearCart,
+    // A change the model does not follow: removing units, editing the item or
+    // changing its status. The item's history can no longer be checked.
+    Untracked { item: ItemId },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "outcome")]
+pub enum Outcome {
+    Ok,
+    // The quantity UpdateUnitsForSale reports
+    Quantity { quantity: i32 },
+    Item { price: f64, quantity: i32, status: ItemStatus },
+    Missing,
+    // Units of each item in the cart
+    Cart { items: BTreeMap<ItemId, i32> },
+    Rejected { reason: Rejection },
+    // No response, or an error that does not say whether the call took effect
+    Unknown,
+}
+
+// Errors the model can explain
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    InsufficientQuantity,
+    NotAvailable,
+    NotFound,
+    // Any other error from the product database; the call had no effect
+    Other,
+}
+
+impl Entry {
+    // The time span a call may have taken effect in; unanswered calls stay
+    // open forever
+    pub fn completed_or_never(&self) -> u64 {
+        self.completed_us.unwrap_or(u64::MAX)
+    }
+}
+
+// One line per call, times in milliseconds since the run started
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |us: u64| us as f64 / 1000.0;
+        match self.completed_us {
+            Some(completed) => write!(f, "{:.3}..{:.3} ms", millis(self.invoked_us), millis(completed))?,
+            None => write!(f, "{:.3}..never ms", millis(self.invoked_us))?,
+        }
+        write!(f, " {}: {:?} -> {:?}", self.client, self.op, self.outcome)
+    }
+}
+
+// Collects entries from every client of a run
+pub struct Recorder {
+    started: Instant,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            started: Instant::now(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, client: &str, sent: Instant, received: Instant, call: Option<(Op, Outcome)>) {
+        let Some((op, outcome)) = call else {
+            return;
+        };
+        let micros = |at: Instant| at.saturating_duration_since(self.started).as_micros() as u64;
+        let entry = Entry {
+            client: client.to_string(),
+            invoked_us: micros(sent),
+            completed_us: (outcome != Outcome::Unknown).then(|| micros(received)),
+            op,
+            outcome,
+        };
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).push(entry);
+    }
+
+    // Entries so far, in the order their calls were sent
+    pub fn take(&self) -> Vec<Entry> {
+        let mut entries = std::mem::take(&mut *self.entries.lock().unwrap_or_else(|e| e.into_inner()));
+        entries.sort_by_key(|entry| entry.invoked_us);
+        entries
+    }
+}
+
+// One entry per line
+pub fn save(path: &Path, entries: &[Entry]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for entry in entries {
+        serde_json::to_writer(&mut file, entry)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid history entry at {}:{}: {}", path.display(), number + 1, e))?;
+        entries.push(entry);
+    }
+    entries.sort_by_key(|entry: &Entry| entry.invoked_us);
+    Ok(entries)
+}
+
+// Calls the frontends turn down before reaching the product database
+fn rejected_by_frontend(msg: &str) -> bool {
+    msg == "Session expired"
+        || msg == "Session not found"
+        || msg == "Invalid session type"
+        || msg == "Failed to validate session"
+}
+
+fn rejection(msg: &str) -> Rejection {
+    if msg == "Insufficient quantity" {
+        Rejection::InsufficientQuantity
+    } else if msg.starts_with("Item is not available") {
+        Rejection::NotAvailable
+    } else if msg == "Item not found" {
+        Rejection::NotFound
+    } else {
+        Rejection::Other
+    }
+}
+
+// Frontend errors that start with "Failed to" mean the product database call
+// itself went wrong, so the change may or may not have been applied
+fn error_outcome(msg: &str) -> Option<Outcome> {
+    if rejected_by_frontend(msg) {
+        return None;
+    }
+    if msg.starts_with("Failed to") {
+        return Some(Outcome::Unknown);
+    }
+    Some(Outcome::Rejected { reason: rejection(msg) })
+}
+
+// What a seller call did, None if it is not recorded or cannot have had an
+// effect
+pub fn seller_call(request: &SellerRequest, response: Option<&SellerResponse>) -> Option<(Op, Outcome)> {
+    let op = match request {
+        SellerRequest::RegisterItemForSale { sale_price, quantity, .. } => match response {
+            // The item is only known once registered
+            Some(SellerResponse::RegisterItemForSale(item)) => {
+                return Some((Op::Register { item: *item, price: *sale_price, quantity: *quantity }, Outcome::Ok));
+            }
+            _ => return None,
+        },
+        SellerRequest::ChangeItemPrice { item_id, new_price, .. } => Op::SetPrice { item: *item_id, price: *new_price },
+        SellerRequest::UpdateUnitsForSale { item_id, update: UnitsUpdate::SetTo(quantity), .. } => {
+            Op::SetQuantity { item: *item_id, quantity: *quantity }
+        }
+        SellerRequest::UpdateUnitsForSale { item_id, update: UnitsUpdate::Remove(_), .. }
+        | SellerRequest::EditItem { item_id, .. }
+        | SellerRequest::PublishItem { item_id, .. }
+        | SellerRequest::PauseItem { item_id, .. }
+        | SellerRequest::ResumeItem { item_id, .. }
+        | SellerRequest::DelistItem { item_id, .. } => Op::Untracked { item: *item_id },
+        // Listed so that a new call that changes items has to be added above
+        SellerRequest::CreateAccount { .. }
+        | SellerRequest::Login { .. }
+        | SellerRequest::Logout { .. }
+        | SellerRequest::GetSellerRating { .. }
+        | SellerRequest::DisplayItemsForSale { .. }
+        | SellerRequest::ListCategories { .. }
+        | SellerRequest::Health
+        | SellerRequest::Ready
+        | SellerRequest::Stats => return None,
+    };
+    let outcome = match response {
+        None => Outcome::Unknown,
+        Some(SellerResponse::ChangeItemPrice) => Outcome::Ok,
+        Some(SellerResponse::UpdateUnitsForSale(quantity)) => Outcome::Quantity { quantity: *quantity },
+        Some(SellerResponse::Error(msg)) => error_outcome(msg)?,
+        Some(_) => Outcome::Unknown,
+    };
+    Some((op, outcome))
+}
+
+// What a buyer call did, None if it is not recorded or cannot have had an
+// effect
+pub fn buyer_call(request: &BuyerRequest, response: Option<&BuyerResponse>) -> Option<(Op, Outcome)> {
+    let op = match request {
+        BuyerRequest::GetItem { item_id, .. } => Op::GetItem { item: *item_id },
+        BuyerRequest::AddItemToCart { item_id, quantity, .. } => Op::AddToCart { item: *item_id, quantity: *quantity },
+        BuyerRequest::RemoveItemFromCart { item_id, quantity, .. } => Op::RemoveFromCart { item: *item_id, quantity: *quantity },
+        BuyerRequest::DisplayCart { .. } => Op::DisplayCart,
+        BuyerRequest::SaveCart { .. } => Op::SaveCart,
+        BuyerRequest::ClearCart { .. } => Op::ClearCart,
+        _ => return None,
+    };
+    let outcome = match response {
+        None => Outcome::Unknown,
+        Some(BuyerResponse::GetItem(Some(item))) => Outcome::Item {
+            price: item.sale_price,
+            quantity: item.quantity,
+            status: item.status,
+        },
+        Some(BuyerResponse::GetItem(None)) => Outcome::Missing,
+        Some(BuyerResponse::DisplayCart(cart)) => {
+            let mut items = BTreeMap::new();
+            for cart_item in cart {
+                *items.entry(cart_item.item_id).or_insert(0) += cart_item.quantity;
+            }
+            Outcome::Cart { items }
+        }
+        Some(BuyerResponse::AddItemToCart | BuyerResponse::RemoveItemFromCart | BuyerResponse::SaveCart | BuyerResponse::ClearCart) => Outcome::Ok,
+        Some(BuyerResponse::Error(msg)) => error_outcome(msg)?,
+        Some(_) => Outcome::Unknown,
+    };
+    Some((op, outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    const ITEM: ItemId = ItemId { category: 1, sequence: 1 };
+
+    fn add_to_cart(response: BuyerResponse) -> Option<(Op, Outcome)> {
+        let request = BuyerRequest::AddItemToCart { session_id: Uuid::nil(), item_id: ITEM, quantity: 2 };
+        buyer_call(&request, Some(&response))
+    }
+
+    #[test]
+    fn errors_map_to_outcomes() {
+        let error = |msg: &str| add_to_cart(BuyerResponse::Error(msg.to_string())).map(|(_, outcome)| outcome);
+        let rejected = |reason| Some(Outcome::Rejected { reason });
+
+        assert_eq!(error("Insufficient quantity"), rejected(Rejection::InsufficientQuantity));
+        assert_eq!(error("Item is not available (paused)"), rejected(Rejection::NotAvailable));
+        assert_eq!(error("Item not found"), rejected(Rejection::NotFound));
+        assert_eq!(error("Price must be a non-negative number"), rejected(Rejection::Other));
+        // The product database may or may not have applied the call
+        assert_eq!(error("Failed to add item to cart"), Some(Outcome::Unknown));
+        // Turned down before reaching the product database, so not recorded
+        assert_eq!(error("Session expired"), None);
+    }
+
+    #[test]
+    fn calls_without_a_response_stay_open() {
+        let request = SellerRequest::ChangeItemPrice { session_id: Uuid::nil(), item_id: ITEM, new_price: 5.0 };
+        let recorder = Recorder::new();
+        let sent = Instant::now();
+        recorder.record("alice", sent, Instant::now(), seller_call(&request, None));
+        recorder.record("alice", sent, Instant::now(), seller_call(&request, Some(&SellerResponse::ChangeItemPrice)));
+
+        let entries = recorder.take();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].outcome.clone(), entries[0].completed_us), (Outcome::Unknown, None));
+        assert_eq!(entries[1].outcome, Outcome::Ok);
+        assert!(entries[1].completed_us.is_some());
+        // A registration that failed created no item to record
+        let request = SellerRequest::RegisterItemForSale {
+            session_id: Uuid::nil(),
+            item_name: "lamp".to_string(),
+            item_category: 1,
+            keywords: Vec::new(),
+            condition: Condition::New,
+            sale_price: 5.0,
+            quantity: 1,
+            draft: false,
+        };
+        assert!(seller_call(&request, Some(&SellerResponse::Error("Unknown category".to_string()))).is_none());
+    }
+
+    #[test]
+    fn unmodelled_item_changes_are_recorded_as_untracked() {
+        let session_id = Uuid::nil();
+        let requests = [
+            SellerRequest::UpdateUnitsForSale { session_id, item_id: ITEM, update: UnitsUpdate::Remove(1) },
+            SellerRequest::PauseItem { session_id, item_id: ITEM },
+            SellerRequest::DelistItem { session_id, item_id: ITEM },
+        ];
+        for request in &requests {
+            let call = seller_call(request, None);
+            assert_eq!(call, Some((Op::Untracked { item: ITEM }, Outcome::Unknown)), "{:?}", request);
+        }
+        let listing = SellerRequest::DisplayItemsForSale { session_id };
+        assert!(seller_call(&listing, Some(&SellerResponse::DisplayItemsForSale(Vec::new()))).is_none());
+    }
+}
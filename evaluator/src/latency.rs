@@ -0,0 +1,145 @@
+// Per-API latency histograms. Every call is timed on its own and recorded
+// under its request variant, so percentiles come from individual response
+// times rather than from the length of a run.
+
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// Latencies are recorded in microseconds, up to a minute, to 3 significant digits
+const MAX_LATENCY_US: u64 = 60_000_000;
+const SIGNIFICANT_DIGITS: u8 = 3;
+
+#[derive(Default)]
+pub struct ApiLatencies {
+    apis: BTreeMap<&'static str, ApiStats>,
+}
+
+struct ApiStats {
+    // Calls that got a response, errors included
+    histogram: Histogram<u64>,
+    // Calls answered with an Error response
+    rejected: u64,
+    // Calls that got no response at all
+    failed: u64,
+}
+
+// What ends up in result files and reports
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct LatencySummary {
+    pub calls: u64,
+    pub errors: u64,
+    pub failed: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl ApiStats {
+    fn new() -> Self {
+        ApiStats {
+            histogram: Histogram::new_with_bounds(1, MAX_LATENCY_US, SIGNIFICANT_DIGITS)
+                .expect("valid histogram bounds"),
+            rejected: 0,
+            failed: 0,
+        }
+    }
+
+    fn merge(&mut self, other: &ApiStats) {
+        // Both histograms share the same bounds, so adding cannot fail
+        let _ = self.histogram.add(&other.histogram);
+        self.rejected += other.rejected;
+        self.failed += other.failed;
+    }
+
+    fn summary(&self) -> LatencySummary {
+        let histogram = &self.histogram;
+        LatencySummary {
+            calls: histogram.len(),
+            errors: self.rejected,
+            failed: self.failed,
+            mean_ms: histogram.mean() / 1000.0,
+            p50_ms: millis(histogram.value_at_quantile(0.50)),
+            p90_ms: millis(histogram.value_at_quantile(0.90)),
+            p99_ms: millis(histogram.value_at_quantile(0.99)),
+            max_ms: millis(histogram.max()),
+        }
+    }
+}
+
+impl ApiLatencies {
+    fn stats(&mut self, api: &'static str) -> &mut ApiStats {
+        self.apis.entry(api).or_insert_with(ApiStats::new)
+    }
+
+    pub fn record(&mut self, api: &'static str, elapsed: Duration, rejected: bool) {
+        let stats = self.stats(api);
+        stats.histogram.saturating_record(elapsed.as_micros() as u64);
+        if rejected {
+            stats.rejected += 1;
+        }
+    }
+
+    pub fn record_failure(&mut self, api: &'static str) {
+        self.stats(api).failed += 1;
+    }
+
+    pub fn merge(&mut self, other: &ApiLatencies) {
+        for (api, theirs) in &other.apis {
+            self.stats(api).merge(theirs);
+        }
+    }
+
+    // Calls that got a response, errors included
+    pub fn completed(&self) -> u64 {
+        self.apis.values().map(|stats| stats.histogram.len()).sum()
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.apis.values().map(|stats| stats.rejected).sum()
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.apis.values().map(|stats| stats.failed).sum()
+    }
+
+    pub fn summaries(&self) -> BTreeMap<String, LatencySummary> {
+        self.apis.iter().map(|(api, stats)| (api.to_string(), stats.summary())).collect()
+    }
+
+    // All APIs together
+    pub fn overall(&self) -> LatencySummary {
+        let mut all = ApiStats::new();
+        for stats in self.apis.values() {
+            all.merge(stats);
+        }
+        all.summary()
+    }
+
+    pub fn print_table(&self) {
+        println!(
+            "  {:<20} {:>8} {:>7} {:>7} {:>9} {:>9} {:>9} {:>9}",
+            "API", "calls", "errors", "failed", "p50 ms", "p90 ms", "p99 ms", "max ms"
+        );
+        for (api, summary) in self.summaries() {
+            println!(
+                "  {:<20} {:>8} {:>7} {:>7} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+                api,
+                summary.calls,
+                summary.errors,
+                summary.failed,
+                summary.p50_ms,
+                summary.p90_ms,
+                summary.p99_ms,
+                summary.max_ms,
+            );
+        }
+    }
+}
+
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
@@ -0,0 +1,554 @@
+// Checks a recorded history against a sequential model of product_db items and
+// carts: is there an order of the calls, each placed between when it was sent
+// and when it was answered, in which every response is what one product_db
+// applying the calls one at a time would have returned?
+//
+// Each item and each buyer's cart is checked on its own, since a history is
+// linearizable if and only if the history of every object in it is. Adding to
+// a cart reads the item and changes the cart, so it shows up in both. The
+// search tries orders depth first and remembers which (calls placed, state)
+// pairs it has already explored. For each object that fails, the calls are
+// cut down to a smallest set that still cannot be ordered.
+//
+// The model:
+// - An item exists once registered, and reads return its latest price and
+//   quantity. It is active while it has units left and sold out at zero.
+// - Adding n units to a cart succeeds only if the item is active with at
+//   least n units, so a cart never takes more than is for sale.
+// - Removing units from a cart takes away at most what it holds; clearing
+//   empties it; saving leaves it as it is. DisplayCart returns its contents.
+// - Calls rejected by the product database change nothing. Unanswered calls
+//   may have taken effect at any point after they were sent, or never.
+//
+// Items that a seller edited, paused, delisted or took units off are left
+// unchecked rather than checked against a model that does not cover those
+// calls.
+
+use crate::history::{Entry, Op, Outcome, Rejection};
+use common::{ItemId, ItemStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+// States explored per object before giving up on it
+const SEARCH_BUDGET: usize = 500_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryCheck {
+    pub calls: usize,
+    pub items: usize,
+    pub carts: usize,
+    pub violations: Vec<Violation>,
+    // Objects whose histories were too tangled to decide within the budget
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inconclusive: Vec<String>,
+    // Items changed by calls the model does not cover
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchecked: Vec<String>,
+}
+
+// A smallest set of calls on one object that no sequential order explains
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Violation {
+    pub object: String,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Object {
+    Item(ItemId),
+    Cart(String),
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Item(item) => write!(f, "item {}", item),
+            Object::Cart(client) => write!(f, "cart of {}", client),
+        }
+    }
+}
+
+pub fn check(entries: &[Entry]) -> HistoryCheck {
+    let mut objects: BTreeMap<Object, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        for object in touches(entry) {
+            objects.entry(object).or_default().push(entry);
+        }
+    }
+
+    let mut result = HistoryCheck {
+        calls: entries.len(),
+        items: objects.keys().filter(|object| matches!(object, Object::Item(_))).count(),
+        carts: objects.keys().filter(|object| matches!(object, Object::Cart(_))).count(),
+        violations: Vec::new(),
+        inconclusive: Vec::new(),
+        unchecked: Vec::new(),
+    };
+    for (object, ops) in objects {
+        if ops.iter().any(|entry| matches!(entry.op, Op::Untracked { .. })) {
+            result.unchecked.push(object.to_string());
+            continue;
+        }
+        let outcome = match object {
+            Object::Item(_) => check_object(ItemModel::default(), ops, SEARCH_BUDGET),
+            Object::Cart(_) => check_object(CartModel::default(), ops, SEARCH_BUDGET),
+        };
+        match outcome {
+            Checked::Linearizable => {}
+            Checked::Violation(entries) => result.violations.push(Violation {
+                object: object.to_string(),
+                entries,
+            }),
+            Checked::Inconclusive => result.inconclusive.push(object.to_string()),
+        }
+    }
+    result
+}
+
+// The objects a call reads or changes, leaving out calls that can tell the
+// model nothing about an object
+fn touches(entry: &Entry) -> Vec<Object> {
+    let unknown = entry.outcome == Outcome::Unknown;
+    let cart = || Object::Cart(entry.client.clone());
+    match entry.op {
+        Op::Register { item, .. } | Op::SetPrice { item, .. } | Op::SetQuantity { item, .. } | Op::Untracked { item } => {
+            vec![Object::Item(item)]
+        }
+        Op::GetItem { item } if !unknown => vec![Object::Item(item)],
+        // Adding only reads the item, so without a response it says nothing about it
+        Op::AddToCart { item, .. } if !unknown => vec![Object::Item(item), cart()],
+        Op::AddToCart { .. } | Op::RemoveFromCart { .. } | Op::ClearCart => vec![cart()],
+        Op::DisplayCart if !unknown => vec![cart()],
+        Op::GetItem { .. } | Op::DisplayCart | Op::SaveCart => Vec::new(),
+    }
+}
+
+trait Model: Clone + Eq + Hash {
+    // The state after `op` took effect with `outcome`, or None if this state
+    // could not have produced that outcome. Unknown outcomes take effect as
+    // if the call succeeded; leaving the call out covers it failing.
+    fn step(&self, op: &Op, outcome: &Outcome) -> Option<Self>;
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+struct ItemModel(Option<ItemState>);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ItemState {
+    // f64 bits, so the state can be hashed
+    price: u64,
+    quantity: i32,
+}
+
+impl ItemState {
+    fn status(&self) -> ItemStatus {
+        if self.quantity > 0 {
+            ItemStatus::Active
+        } else {
+            ItemStatus::SoldOut
+        }
+    }
+}
+
+impl Model for ItemModel {
+    fn step(&self, op: &Op, outcome: &Outcome) -> Option<Self> {
+        let unchanged = Some(self.clone());
+        if let Outcome::Rejected { reason: Rejection::Other } = outcome {
+            return unchanged;
+        }
+        let set = |price: u64, quantity: i32| Some(ItemModel(Some(ItemState { price, quantity })));
+        match (op, &self.0) {
+            (Op::Register { price, quantity, .. }, None) => set(price.to_bits(), *quantity),
+            (Op::Register { .. }, Some(_)) => None,
+
+            (Op::SetPrice { .. } | Op::SetQuantity { .. } | Op::GetItem { .. } | Op::AddToCart { .. }, None) => match outcome {
+                Outcome::Missing | Outcome::Rejected { reason: Rejection::NotFound } => unchanged,
+                _ => None,
+            },
+
+            (Op::SetPrice { price, .. }, Some(state)) => match outcome {
+                Outcome::Ok | Outcome::Unknown => set(price.to_bits(), state.quantity),
+                _ => None,
+            },
+            (Op::SetQuantity { quantity, .. }, Some(state)) => match outcome {
+                Outcome::Quantity { quantity: reported } if reported == quantity => set(state.price, *quantity),
+                Outcome::Ok | Outcome::Unknown => set(state.price, *quantity),
+                _ => None,
+            },
+            (Op::GetItem { .. }, Some(state)) => match outcome {
+                Outcome::Item { price, quantity, status }
+                    if same_price(*price, state.price) && *quantity == state.quantity && *status == state.status() =>
+                {
+                    unchanged
+                }
+                _ => None,
+            },
+            (Op::AddToCart { quantity, .. }, Some(state)) => {
+                let available = state.status() == ItemStatus::Active;
+                let valid = match outcome {
+                    Outcome::Ok => available && state.quantity >= *quantity,
+                    Outcome::Rejected { reason: Rejection::InsufficientQuantity } => available && state.quantity < *quantity,
+                    Outcome::Rejected { reason: Rejection::NotAvailable } => !available,
+                    _ => false,
+                };
+                valid.then_some(self.clone())
+            }
+            _ => unchanged,
+        }
+    }
+}
+
+// Prices pass through JSON parsing that may round the last digit, so a read
+// may differ from the price set by a unit in the last place
+fn same_price(read: f64, set: u64) -> bool {
+    let set = f64::from_bits(set);
+    (read - set).abs() <= set.abs() * 1e-12
+}
+
+// Units of each item in one buyer's cart
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+struct CartModel(BTreeMap<ItemId, i32>);
+
+impl Model for CartModel {
+    fn step(&self, op: &Op, outcome: &Outcome) -> Option<Self> {
+        if let Outcome::Rejected { .. } = outcome {
+            return Some(self.clone());
+        }
+        let mut cart = self.clone();
+        match op {
+            Op::AddToCart { item, quantity } => *cart.0.entry(*item).or_insert(0) += quantity,
+            Op::RemoveFromCart { item, quantity } => {
+                if let Some(held) = cart.0.get_mut(item) {
+                    if *held <= *quantity {
+                        cart.0.remove(item);
+                    } else {
+                        *held -= quantity;
+                    }
+                }
+            }
+            Op::ClearCart => cart.0.clear(),
+            Op::DisplayCart => {
+                return match outcome {
+                    Outcome::Cart { items } if *items == self.0 => Some(cart),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+        Some(cart)
+    }
+}
+
+enum Checked {
+    Linearizable,
+    Violation(Vec<Entry>),
+    Inconclusive,
+}
+
+fn check_object<M: Model>(initial: M, ops: Vec<&Entry>, budget: usize) -> Checked {
+    match search(&initial, &ops, budget) {
+        Search::Linearizable => Checked::Linearizable,
+        Search::Exhausted => Checked::Inconclusive,
+        Search::Stuck { frontier } => {
+            // The calls up to where the search got stuck usually already fail
+            // on their own, and are far fewer to shrink
+            let prefix: Vec<&Entry> = ops.iter().copied().filter(|entry| entry.invoked_us <= frontier).collect();
+            let start = if prefix.len() < ops.len() && fails(&initial, &prefix, budget) { prefix } else { ops };
+            let minimal = shrink(&initial, start, budget);
+            Checked::Violation(minimal.into_iter().cloned().collect())
+        }
+    }
+}
+
+enum Search {
+    Linearizable,
+    // No order works. `frontier` is the completion time of the call the
+    // deepest attempt could not place.
+    Stuck { frontier: u64 },
+    Exhausted,
+}
+
+fn fails<M: Model>(initial: &M, ops: &[&Entry], budget: usize) -> bool {
+    matches!(search(initial, ops, budget), Search::Stuck { .. })
+}
+
+struct Frame<M> {
+    placed: Vec<u64>,
+    required_placed: usize,
+    model: M,
+    candidates: Vec<usize>,
+    next: usize,
+}
+
+// `ops` are in the order they were sent; `budget` caps the states explored
+fn search<M: Model>(initial: &M, ops: &[&Entry], budget: usize) -> Search {
+    let required = ops.iter().filter(|entry| entry.completed_us.is_some()).count();
+    let words = ops.len().div_ceil(64);
+    let mut visited: HashSet<(Vec<u64>, M)> = HashSet::new();
+    let mut deepest = (0, 0);
+
+    let placed = vec![0; words];
+    let (candidates, frontier) = next_candidates(ops, &placed);
+    deepest.1 = frontier;
+    let mut stack = vec![Frame {
+        placed,
+        required_placed: 0,
+        model: initial.clone(),
+        candidates,
+        next: 0,
+    }];
+
+    while let Some(frame) = stack.last_mut() {
+        if frame.required_placed == required {
+            return Search::Linearizable;
+        }
+        let Some(&index) = frame.candidates.get(frame.next) else {
+            stack.pop();
+            continue;
+        };
+        frame.next += 1;
+
+        let entry = ops[index];
+        let Some(model) = frame.model.step(&entry.op, &entry.outcome) else {
+            continue;
+        };
+        let mut placed = frame.placed.clone();
+        placed[index / 64] |= 1 << (index % 64);
+        let required_placed = frame.required_placed + usize::from(entry.completed_us.is_some());
+        if !visited.insert((placed.clone(), model.clone())) {
+            continue;
+        }
+        if visited.len() > budget {
+            return Search::Exhausted;
+        }
+
+        let (candidates, frontier) = next_candidates(ops, &placed);
+        if required_placed > deepest.0 {
+            deepest = (required_placed, frontier);
+        }
+        stack.push(Frame {
+            placed,
+            required_placed,
+            model,
+            candidates,
+            next: 0,
+        });
+    }
+    Search::Stuck { frontier: deepest.1 }
+}
+
+// Calls that can go next: those sent before every call still to be placed
+// was answered. Also returns the earliest such answer.
+fn next_candidates(ops: &[&Entry], placed: &[u64]) -> (Vec<usize>, u64) {
+    let unplaced = |index: usize| placed[index / 64] & (1 << (index % 64)) == 0;
+    let deadline = (0..ops.len())
+        .filter(|&index| unplaced(index))
+        .map(|index| ops[index].completed_or_never())
+        .min()
+        .unwrap_or(u64::MAX);
+    let candidates = (0..ops.len())
+        .take_while(|&index| ops[index].invoked_us <= deadline)
+        .filter(|&index| unplaced(index))
+        .collect();
+    (candidates, deadline)
+}
+
+// Drops calls, in halving chunks and then one at a time, as long as what is
+// left still fails. Registrations stay, since they set the starting state.
+fn shrink<'a, M: Model>(initial: &M, mut ops: Vec<&'a Entry>, budget: usize) -> Vec<&'a Entry> {
+    let pinned = |entry: &&Entry| matches!(entry.op, Op::Register { .. });
+    let mut chunk = (ops.len() / 2).max(1);
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start < ops.len() {
+            let end = (start + chunk).min(ops.len());
+            let candidate: Vec<&Entry> = ops[..start]
+                .iter()
+                .chain(ops[start..end].iter().filter(|entry| pinned(entry)))
+                .chain(ops[end..].iter())
+                .copied()
+                .collect();
+            if candidate.len() < ops.len() && fails(initial, &candidate, budget) {
+                ops = candidate;
+                removed = true;
+            } else {
+                start = end;
+            }
+        }
+        if chunk > 1 {
+            chunk /= 2;
+        } else if !removed {
+            return ops;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEM: ItemId = ItemId { category: 1, sequence: 1 };
+    const OTHER_ITEM: ItemId = ItemId { category: 1, sequence: 2 };
+
+    // A call answered at `completed` (in ms), or never if None
+    fn call(client: &str, invoked: u64, completed: Option<u64>, op: Op, outcome: Outcome) -> Entry {
+        Entry {
+            client: client.to_string(),
+            invoked_us: invoked * 1000,
+            completed_us: completed.map(|ms| ms * 1000),
+            op,
+            outcome,
+        }
+    }
+
+    fn register(invoked: u64, completed: u64, item: ItemId, price: f64, quantity: i32) -> Entry {
+        call("alice", invoked, Some(completed), Op::Register { item, price, quantity }, Outcome::Ok)
+    }
+
+    fn read(client: &str, invoked: u64, completed: u64, price: f64, quantity: i32) -> Entry {
+        let status = if quantity > 0 { ItemStatus::Active } else { ItemStatus::SoldOut };
+        call(client, invoked, Some(completed), Op::GetItem { item: ITEM }, Outcome::Item { price, quantity, status })
+    }
+
+    fn cart(items: &[(ItemId, i32)]) -> Outcome {
+        Outcome::Cart { items: items.iter().copied().collect() }
+    }
+
+    #[test]
+    fn concurrent_history_is_linearizable() {
+        let entries = vec![
+            register(0, 1, ITEM, 10.0, 3),
+            // The price change overlaps both reads, so one may see it and the
+            // other not, as long as the old price is not seen after the new one
+            call("alice", 2, Some(8), Op::SetPrice { item: ITEM, price: 12.0 }, Outcome::Ok),
+            read("carol", 3, 5, 10.0, 3),
+            read("dave", 4, 9, 12.0, 3),
+            call("carol", 6, Some(7), Op::AddToCart { item: ITEM, quantity: 2 }, Outcome::Ok),
+            call("dave", 6, Some(10), Op::AddToCart { item: ITEM, quantity: 4 }, Outcome::Rejected { reason: Rejection::InsufficientQuantity }),
+            call("carol", 11, Some(12), Op::RemoveFromCart { item: ITEM, quantity: 1 }, Outcome::Ok),
+            call("carol", 13, Some(14), Op::DisplayCart, cart(&[(ITEM, 1)])),
+            call("dave", 13, Some(14), Op::DisplayCart, cart(&[])),
+        ];
+
+        let result = check(&entries);
+        assert_eq!((result.calls, result.items, result.carts), (9, 1, 2));
+        assert!(result.violations.is_empty(), "{:?}", result.violations);
+        assert!(result.inconclusive.is_empty());
+    }
+
+    #[test]
+    fn adding_more_than_is_for_sale_is_a_violation() {
+        let entries = vec![
+            register(0, 1, ITEM, 10.0, 2),
+            call("alice", 2, Some(3), Op::SetQuantity { item: ITEM, quantity: 1 }, Outcome::Quantity { quantity: 1 }),
+            call("carol", 4, Some(5), Op::AddToCart { item: ITEM, quantity: 2 }, Outcome::Ok),
+        ];
+
+        let result = check(&entries);
+        assert_eq!(result.violations.len(), 1);
+        assert_eq!(result.violations[0].object, "item 1-1");
+        assert_eq!(result.violations[0].entries.len(), 3);
+    }
+
+    #[test]
+    fn stale_read_after_completed_write_is_a_violation() {
+        let entries = vec![
+            register(0, 1, ITEM, 10.0, 3),
+            call("alice", 2, Some(3), Op::SetPrice { item: ITEM, price: 12.0 }, Outcome::Ok),
+            read("carol", 4, 5, 10.0, 3),
+        ];
+        assert_eq!(check(&entries).violations.len(), 1);
+
+        // The same read overlapping the write may still see the old price
+        let entries = vec![
+            register(0, 1, ITEM, 10.0, 3),
+            call("alice", 2, Some(5), Op::SetPrice { item: ITEM, price: 12.0 }, Outcome::Ok),
+            read("carol", 4, 6, 10.0, 3),
+        ];
+        assert!(check(&entries).violations.is_empty());
+    }
+
+    #[test]
+    fn unanswered_call_may_or_may_not_take_effect() {
+        let unanswered = call("alice", 2, None, Op::SetPrice { item: ITEM, price: 12.0 }, Outcome::Unknown);
+
+        // Never taking effect explains reads of the old price
+        let entries = vec![register(0, 1, ITEM, 10.0, 3), unanswered.clone(), read("carol", 3, 4, 10.0, 3), read("carol", 20, 21, 10.0, 3)];
+        assert!(check(&entries).violations.is_empty());
+
+        // Taking effect late explains a change seen long after it was sent
+        let entries = vec![register(0, 1, ITEM, 10.0, 3), unanswered.clone(), read("carol", 3, 4, 10.0, 3), read("carol", 20, 21, 12.0, 3)];
+        assert!(check(&entries).violations.is_empty());
+
+        // But once seen, it cannot be undone
+        let entries = vec![
+            register(0, 1, ITEM, 10.0, 3),
+            unanswered.clone(),
+            read("carol", 3, 4, 12.0, 3),
+            read("carol", 20, 21, 10.0, 3),
+        ];
+        assert_eq!(check(&entries).violations.len(), 1);
+
+        // Nor can it take effect before it was sent
+        let entries = vec![register(0, 1, ITEM, 10.0, 3), read("carol", 1, 2, 12.0, 3), Entry { invoked_us: 5000, ..unanswered }];
+        assert_eq!(check(&entries).violations.len(), 1);
+    }
+
+    #[test]
+    fn violation_is_shrunk_to_the_calls_that_cause_it() {
+        let mut entries = vec![register(0, 1, ITEM, 10.0, 5), register(0, 1, OTHER_ITEM, 3.0, 5)];
+        for n in 0..10 {
+            entries.push(read("carol", 2 + n, 3 + n, 10.0, 5));
+            entries.push(call("dave", 2 + n, Some(3 + n), Op::AddToCart { item: OTHER_ITEM, quantity: 1 }, Outcome::Ok));
+        }
+        let write = call("alice", 20, Some(21), Op::SetPrice { item: ITEM, price: 12.0 }, Outcome::Ok);
+        let stale = read("carol", 30, 31, 10.0, 5);
+        entries.push(write.clone());
+        entries.push(call("alice", 25, Some(26), Op::SetPrice { item: OTHER_ITEM, price: 4.0 }, Outcome::Ok));
+        entries.push(stale.clone());
+        entries.push(read("carol", 32, 33, 12.0, 5));
+
+        let result = check(&entries);
+        assert_eq!(result.violations.len(), 1, "{:?}", result.violations);
+        let violation = &result.violations[0];
+        assert_eq!(violation.object, "item 1-1");
+        let kept: Vec<(u64, Op)> = violation.entries.iter().map(|entry| (entry.invoked_us, entry.op)).collect();
+        assert_eq!(kept, vec![(0, entries[0].op), (write.invoked_us, write.op), (stale.invoked_us, stale.op)]);
+    }
+
+    #[test]
+    fn search_gives_up_past_its_budget() {
+        // Each unanswered add may or may not have happened, so a final cart no
+        // subset explains can only be ruled out by trying them all
+        let mut entries = Vec::new();
+        for n in 0..16 {
+            let item = ItemId { category: 1, sequence: n + 1 };
+            entries.push(call("carol", n, None, Op::AddToCart { item, quantity: 1 }, Outcome::Unknown));
+        }
+        entries.push(call("carol", 100, Some(101), Op::DisplayCart, cart(&[(ITEM, 5)])));
+        let ops: Vec<&Entry> = entries.iter().collect();
+
+        assert!(matches!(check_object(CartModel::default(), ops.clone(), 1_000), Checked::Inconclusive));
+        assert!(matches!(check_object(CartModel::default(), ops, SEARCH_BUDGET), Checked::Violation(_)));
+    }
+
+    #[test]
+    fn items_changed_outside_the_model_are_not_checked() {
+        let entries = vec![
+            register(0, 1, ITEM, 10.0, 3),
+            register(0, 1, OTHER_ITEM, 10.0, 3),
+            // Pausing makes the item unavailable, which the model cannot know
+            call("alice", 2, Some(3), Op::Untracked { item: ITEM }, Outcome::Unknown),
+            call("carol", 4, Some(5), Op::AddToCart { item: ITEM, quantity: 1 }, Outcome::Rejected { reason: Rejection::NotAvailable }),
+            call("carol", 6, Some(7), Op::AddToCart { item: OTHER_ITEM, quantity: 1 }, Outcome::Ok),
+        ];
+
+        let result = check(&entries);
+        assert!(result.violations.is_empty(), "{:?}", result.violations);
+        assert_eq!(result.unchecked, vec!["item 1-1".to_string()]);
+        assert_eq!(result.items, 2);
+    }
+}
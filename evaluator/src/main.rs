@@ -1,307 +1,380 @@
-use common::*;
-use tokio::io::AsyncWriteExt;
-use uuid::Uuid;
-use std::time::{Instant, Duration};
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
-use tokio::task;
+mod cluster;
+mod faults;
+mod history;
+mod latency;
+mod linearizability;
+mod open_loop;
+mod profile;
+mod report;
+mod results;
+mod workload;
 
-fn get_seller_server_addr() -> String {
-    std::env::var("SELLER_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:8082".to_string())
-}
+use clap::{Args, Parser, Subcommand};
+use cluster::{Cluster, Component};
+use faults::{CallCounts, FaultReport};
+use latency::ApiLatencies;
+use linearizability::HistoryCheck;
+use profile::Profile;
+use results::{Environment, Results, RunSummary, ScenarioResult};
+use std::path::{Path, PathBuf};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+use workload::{Frontends, RunOptions};
 
-fn get_buyer_server_addr() -> String {
-    std::env::var("BUYER_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:8083".to_string())
+#[derive(Parser)]
+#[command(name = "evaluator")]
+#[command(about = "Online Marketplace performance evaluator")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[command(flatten)]
+    run: RunArgs,
 }
 
-#[derive(Clone)]
-struct TestSession {
-    seller_id: Uuid,
-    seller_session: Uuid,
-    buyer_id: Uuid,
-    buyer_session: Uuid,
+#[derive(Subcommand)]
+enum Commands {
+    /// Render a results file as a Markdown performance report
+    Report {
+        /// results.json written by an evaluation
+        results: PathBuf,
+        /// Earlier results.json to compare against
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+        /// Percent change in throughput or latency that is flagged
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+        /// File to write the report to instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check a recorded history for linearizability
+    Check {
+        /// history_*.jsonl written by a run with --history
+        history: PathBuf,
+    },
 }
 
-async fn send_seller_request(request: SellerRequest) -> Result<SellerResponse, Box<dyn std::error::Error + Send + Sync>> {
-    use tokio::io::{AsyncBufReadExt, BufReader as TokioBufReader};
-    let addr = get_seller_server_addr();
-    let mut stream = tokio::net::TcpStream::connect(&addr).await?;
-    
-    let request_str = serde_json::to_string(&request)?;
-    stream.write_all(request_str.as_bytes()).await?;
-    stream.write_all(b"\n").await?;
-    
-    let mut response_str = String::new();
-    let (reader, _) = stream.split();
-    let mut buf_reader = TokioBufReader::new(reader);
-    buf_reader.read_line(&mut response_str).await?;
-    
-    let response: SellerResponse = serde_json::from_str(response_str.trim())?;
-    Ok(response)
+#[derive(Args)]
+struct RunArgs {
+    /// Workload profile (TOML); without one, runs the spec's three scenarios
+    #[arg(long)]
+    profile: Option<String>,
+    /// API calls each seller and each buyer makes per run, overriding the profile
+    #[arg(long)]
+    ops_per_client: Option<usize>,
+    /// Runs per scenario, overriding the profile
+    #[arg(long)]
+    runs: Option<usize>,
+    /// Directory for results.json and the CSV files
+    #[arg(long, default_value = "evaluator_results")]
+    output_dir: PathBuf,
+    /// Record item and cart calls of each run and check them for linearizability
+    #[arg(long)]
+    history: bool,
+    /// Start the four services on free ports instead of using running ones;
+    /// implied by profiles with faults
+    #[arg(long)]
+    launch: bool,
+    /// Where the service binaries are, for --launch; defaults to the evaluator's own directory
+    #[arg(long)]
+    bin_dir: Option<PathBuf>,
 }
 
-async fn send_buyer_request(request: BuyerRequest) -> Result<BuyerResponse, Box<dyn std::error::Error + Send + Sync>> {
-    use tokio::io::{AsyncBufReadExt, BufReader as TokioBufReader};
-    let addr = get_buyer_server_addr();
-    let mut stream = tokio::net::TcpStream::connect(&addr).await?;
-    
-    let request_str = serde_json::to_string(&request)?;
-    stream.write_all(request_str.as_bytes()).await?;
-    stream.write_all(b"\n").await?;
-    
-    let mut response_str = String::new();
-    let (reader, _) = stream.split();
-    let mut buf_reader = TokioBufReader::new(reader);
-    buf_reader.read_line(&mut response_str).await?;
-    
-    let response: BuyerResponse = serde_json::from_str(response_str.trim())?;
-    Ok(response)
-}
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Commands::Report { results, baseline, threshold, output }) => {
+            if let Err(e) = write_report(&results, baseline.as_deref(), threshold, output.as_deref()) {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+            return Ok(());
+        }
+        Some(Commands::Check { history }) => {
+            let entries = match history::load(&history) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            let check = linearizability::check(&entries);
+            print_history_check(&check);
+            if !check.violations.is_empty() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+    let cli = cli.run;
 
-async fn create_test_session(i: usize, run: usize) -> Result<TestSession, Box<dyn std::error::Error + Send + Sync>> {
-    let seller_name = format!("seller_{}_{}", run, i);
-    let buyer_name = format!("buyer_{}_{}", run, i);
-    let password = "password".to_string();
-    
-    // Create seller account
-    let seller_response = send_seller_request(SellerRequest::CreateAccount {
-        seller_name: seller_name.clone(),
-        password: password.clone(),
-    }).await?;
-    
-    let seller_id = match seller_response {
-        SellerResponse::CreateAccount(id) => id,
-        _ => return Err("Failed to create seller account".into()),
-    };
-    
-    // Login seller
-    let seller_login_response = send_seller_request(SellerRequest::Login {
-        seller_name,
-        password: password.clone(),
-    }).await?;
-    
-    let seller_session = match seller_login_response {
-        SellerResponse::Login(session) => session,
-        _ => return Err("Failed to login seller".into()),
+    let scenarios = match load_scenarios(&cli) {
+        Ok(scenarios) => scenarios,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
     };
-    
-    // Create buyer account
-    let buyer_response = send_buyer_request(BuyerRequest::CreateAccount {
-        buyer_name: buyer_name.clone(),
-        password: password.clone(),
-    }).await?;
-    
-    let buyer_id = match buyer_response {
-        BuyerResponse::CreateAccount(id) => id,
-        _ => return Err("Failed to create buyer account".into()),
+    // Keeps account names unique across evaluator invocations against the same servers
+    let invocation = &Uuid::new_v4().simple().to_string()[..8];
+
+    let mut cluster = None;
+    if cli.launch || scenarios.iter().any(|profile| !profile.faults.is_empty()) {
+        match launch(&cli).await {
+            Ok(launched) => cluster = Some(launched),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let frontends = match &cluster {
+        Some(cluster) => Frontends {
+            seller: cluster.addr(Component::SellerServer).to_string(),
+            buyer: cluster.addr(Component::BuyerServer).to_string(),
+        },
+        None => Frontends::from_env(),
     };
-    
-    // Login buyer
-    let buyer_login_response = send_buyer_request(BuyerRequest::Login {
-        buyer_name,
-        password,
-    }).await?;
-    
-    let buyer_session = match buyer_login_response {
-        BuyerResponse::Login(session) => session,
-        _ => return Err("Failed to login buyer".into()),
+    let environment = Environment::capture(&frontends);
+
+    println!("Starting Performance Evaluation...");
+
+    let mut summaries = Vec::new();
+    for (scenario, profile) in scenarios.iter().enumerate() {
+        println!("\n=== Scenario {}: {} ===", scenario + 1, profile.name);
+        println!("{} sellers, {} buyers, {}, {} runs",
+                profile.sellers, profile.buyers, profile.length(), profile.runs);
+        let mut latencies = ApiLatencies::default();
+        let mut runs = Vec::with_capacity(profile.runs);
+
+        for run in 0..profile.runs {
+            println!("Run {}...", run + 1);
+            let prefix = format!("{}_{}_{}", invocation, scenario + 1, run);
+            let with_faults = !profile.faults.is_empty();
+            let (measuring_tx, measuring_rx) = oneshot::channel();
+            let options = RunOptions {
+                frontends: frontends.clone(),
+                record_history: cli.history,
+                log_calls: with_faults,
+                measuring: Some(measuring_tx),
+            };
+            let (result, injected) = match cluster.as_mut().filter(|_| with_faults) {
+                Some(cluster) => {
+                    let (done_tx, done_rx) = oneshot::channel();
+                    let workload = async {
+                        let result = workload::run(profile, &prefix, options).await;
+                        let _ = done_tx.send(());
+                        result
+                    };
+                    let injector = async {
+                        let started = measuring_rx.await.ok()?;
+                        Some((started, faults::inject(cluster, &profile.faults, started, done_rx).await))
+                    };
+                    let (result, injected) = tokio::join!(workload, injector);
+                    (result?, injected)
+                }
+                None => (workload::run(profile, &prefix, options).await?, None),
+            };
+            let mut summary = RunSummary::new(run + 1, &result);
+
+            println!("  Window: {:.2?}, Calls: {} ({} errors, {} failed), Throughput: {:.2} ops/sec",
+                    result.window, result.latencies.completed(), result.latencies.rejected(),
+                    result.latencies.failed(), summary.throughput);
+            if !summary.steps.is_empty() {
+                print_steps(&summary);
+            }
+            if let Some(entries) = &result.history {
+                let path = cli.output_dir.join(format!("history_{}_{}.jsonl", scenario + 1, run + 1));
+                std::fs::create_dir_all(&cli.output_dir)?;
+                history::save(&path, entries)?;
+                println!("  History written to {}", path.display());
+                let check = linearizability::check(entries);
+                print_history_check(&check);
+                summary.history = Some(check);
+            }
+            if let (Some(cluster), Some((started, injected)), Some(calls)) = (&cluster, injected, &result.calls) {
+                let data_loss = match faults::audit(cluster, &result).await {
+                    Ok(data_loss) => Some(data_loss),
+                    Err(e) => {
+                        println!("  Data loss not checked: {}", e);
+                        None
+                    }
+                };
+                let report = FaultReport::new(&injected, started, calls, data_loss);
+                print_faults(&report);
+                summary.faults = Some(report);
+            }
+
+            latencies.merge(&result.latencies);
+            runs.push(summary);
+
+            // Add delay between runs to allow TIME_WAIT sockets to clear
+            if profile.sellers + profile.buyers >= 200 && run + 1 < profile.runs {
+                println!("  Waiting 5 seconds for connections to clear...");
+                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            }
+        }
+
+        let result = ScenarioResult::new(profile, &latencies, runs);
+        summaries.push((latencies, result));
+    }
+
+    // Print results
+    println!("\n=== Results Summary ===");
+    for (scenario, (latencies, result)) in summaries.iter().enumerate() {
+        println!("\nScenario {} ({}):", scenario + 1, result.profile.name);
+        println!("  Average Throughput: {:.2} ops/sec", result.avg_throughput);
+        println!("  Response times over all runs:");
+        latencies.print_table();
+    }
+
+    let results = Results {
+        environment,
+        scenarios: summaries.into_iter().map(|(_, result)| result).collect(),
     };
-    
-    Ok(TestSession {
-        seller_id,
-        seller_session,
-        buyer_id,
-        buyer_session,
-    })
+    results.write(&cli.output_dir)?;
+    println!("\nResults written to {}", cli.output_dir.display());
+
+    // Analysis
+    if cli.profile.is_none() {
+        println!("\n=== Performance Analysis ===");
+        println!("1. Scenario 1 shows baseline performance with minimal contention.");
+        println!("2. Scenario 2 demonstrates how the system handles moderate concurrency.");
+        println!("3. Scenario 3 tests system limits with high concurrency.");
+        println!("\nExpected observations:");
+        println!("- Response time increases with more concurrent users");
+        println!("- Throughput should increase from Scenario 1 to 2, but may plateau or decrease in Scenario 3");
+        println!("- The system should remain stable under all scenarios");
+    }
+
+    Ok(())
 }
 
-async fn run_single_test(run: usize) -> Result<(Duration, usize), Box<dyn std::error::Error + Send + Sync>> {
-    let session = create_test_session(0, run).await?;
-    let mut rng = StdRng::from_entropy();
-    
-    let start = Instant::now();
-    let mut operations = 0;
-    
-    // Seller operations
-    for i in 0..10 {
-        let response = send_seller_request(SellerRequest::RegisterItemForSale {
-            session_id: session.seller_session,
-            item_name: format!("Item_{}", i),
-            item_category: rng.gen_range(1..10),
-            keywords: vec!["test".to_string(), "item".to_string()],
-            condition: Condition::New,
-            sale_price: rng.gen_range(10.0..100.0),
-            quantity: rng.gen_range(1..100),
-        }).await?;
-        
-        if let SellerResponse::RegisterItemForSale(_) = response {
-            operations += 1;
+// The profile given on the command line, or the spec's scenarios, with the
+// command-line overrides applied
+fn load_scenarios(cli: &RunArgs) -> Result<Vec<Profile>, String> {
+    let mut scenarios = match &cli.profile {
+        Some(path) => vec![Profile::load(path)?],
+        None => Profile::scenarios(),
+    };
+    for profile in &mut scenarios {
+        if let Some(ops) = cli.ops_per_client {
+            profile.set_ops_per_client(ops);
         }
-    }
-    
-    // Buyer operations
-    for _ in 0..10 {
-        let response = send_buyer_request(BuyerRequest::SearchItemsForSale {
-            session_id: session.buyer_session,
-            category: None,
-            keywords: vec!["test".to_string()],
-        }).await?;
-        
-        if let BuyerResponse::SearchItemsForSale(_) = response {
-            operations += 1;
+        if let Some(runs) = cli.runs {
+            profile.runs = runs;
         }
+        profile.validate()?;
     }
-    
-    let duration = start.elapsed();
-    Ok((duration, operations))
+    Ok(scenarios)
 }
 
-async fn run_concurrent_test(num_users: usize, run: usize) -> Result<(Duration, usize), Box<dyn std::error::Error + Send + Sync>> {
-    let mut handles = vec![];
-    
-    for i in 0..num_users {
-        handles.push(task::spawn(async move {
-            let mut operations = 0;
-            let start = Instant::now();
-            
-            if let Ok(session) = create_test_session(i, run).await {
-                let mut rng = StdRng::from_entropy();
-                
-                // Perform operations
-                for _ in 0..10 {
-                    // Seller operation
-                    let _ = send_seller_request(SellerRequest::RegisterItemForSale {
-                        session_id: session.seller_session,
-                        item_name: format!("Item_{}_{}", i, rng.gen_range(0u32..=u32::MAX)),
-                        item_category: rng.gen_range(1..10),
-                        keywords: vec!["test".to_string()],
-                        condition: Condition::New,
-                        sale_price: rng.gen_range(10.0..100.0),
-                        quantity: rng.gen_range(1..100),
-                    }).await;
-                    operations += 1;
-                    
-                    // Buyer operation
-                    let _ = send_buyer_request(BuyerRequest::SearchItemsForSale {
-                        session_id: session.buyer_session,
-                        category: None,
-                        keywords: vec!["test".to_string()],
-                    }).await;
-                    operations += 1;
-                }
-            }
-            
-            (start.elapsed(), operations)
-        }));
+fn print_steps(run: &RunSummary) {
+    println!(
+        "  {:>10} {:>10} {:>10} {:>8} {:>9} {:>9} {:>9}",
+        "target/s", "offered/s", "achieved/s", "dropped", "p50 ms", "p99 ms", "max ms"
+    );
+    for step in &run.steps {
+        println!(
+            "  {:>10.1} {:>10.1} {:>10.1} {:>8} {:>9.3} {:>9.3} {:>9.3}{}",
+            step.target_rate,
+            step.offered_rate,
+            step.achieved_rate,
+            step.dropped,
+            step.overall.p50_ms,
+            step.overall.p99_ms,
+            step.overall.max_ms,
+            if step.sustained() { "" } else { "  saturated" }
+        );
     }
-    
-    let mut total_duration = Duration::new(0, 0);
-    let mut total_operations = 0;
-    
-    for handle in handles {
-        if let Ok((duration, ops)) = handle.await {
-            total_duration = total_duration.max(duration); // Max duration for concurrent test
-            total_operations += ops;
+    println!("  {}", run.describe_saturation());
+}
+
+// Starts the services on free ports
+async fn launch(cli: &RunArgs) -> Result<Cluster, String> {
+    let bin_dir = match &cli.bin_dir {
+        Some(dir) => dir.clone(),
+        None => std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            .ok_or("Failed to find the evaluator's directory; pass --bin-dir")?,
+    };
+    let dir = cli.output_dir.join("services");
+    let cluster = Cluster::launch(&bin_dir, &dir).await?;
+    println!("Launched services from {}; logs in {}", bin_dir.display(), dir.display());
+    for component in Component::ALL {
+        println!("  {} on {}", component, cluster.addr(component));
+    }
+    Ok(cluster)
+}
+
+fn print_faults(report: &FaultReport) {
+    for fault in &report.faults {
+        match (fault.recovery_secs, &fault.error) {
+            (Some(secs), _) => println!("  {} killed at {:.1}s, back after {:.2}s:", fault.component, fault.at_secs, secs),
+            (None, Some(e)) => println!("  {} killed at {:.1}s, did not come back: {}", fault.component, fault.at_secs, e),
+            (None, None) => println!("  {} killed at {:.1}s, did not come back:", fault.component, fault.at_secs),
         }
+        print_call_counts(&fault.calls);
+    }
+    for failed in &report.not_injected {
+        println!("  {} not killed at {:.1}s: {}", failed.component, failed.at_secs, failed.error);
+    }
+    println!("  Outside fault windows:");
+    print_call_counts(&report.outside);
+    if let Some(loss) = &report.data_loss {
+        println!(
+            "  Data lost: {} of {} sellers, {} of {} buyers, {} of {} items",
+            loss.sellers_lost, loss.sellers, loss.buyers_lost, loss.buyers, loss.items_lost, loss.items
+        );
     }
-    
-    Ok((total_duration, total_operations))
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("Starting Performance Evaluation...");
-    
-    // Scenario 1: 1 seller, 1 buyer
-    println!("\n=== Scenario 1: 1 seller, 1 buyer ===");
-    let mut scenario1_times = Vec::new();
-    let mut scenario1_throughputs = Vec::new();
-    
-    for run in 0..10 {
-        println!("Run {}...", run + 1);
-        let (duration, operations) = run_single_test(run).await?;
-        let throughput = operations as f64 / duration.as_secs_f64();
-        
-        scenario1_times.push(duration);
-        scenario1_throughputs.push(throughput);
-        
-        println!("  Duration: {:?}, Operations: {}, Throughput: {:.2} ops/sec", 
-                duration, operations, throughput);
+// Totals, then the most frequent errors
+fn print_call_counts(counts: &CallCounts) {
+    println!(
+        "    {} calls, {} errors ({} lost sessions), {} without a response",
+        counts.calls, counts.errors, counts.session_lost, counts.no_response
+    );
+    let mut errors: Vec<_> = counts.by_error.iter().collect();
+    errors.sort_by(|a, b| b.1.cmp(a.1));
+    for (error, count) in errors.into_iter().take(5) {
+        println!("    {:>8}  {}", count, error);
     }
-    
-    // Scenario 2: 10 concurrent sellers and buyers
-    println!("\n=== Scenario 2: 10 sellers, 10 buyers ===");
-    let mut scenario2_times = Vec::new();
-    let mut scenario2_throughputs = Vec::new();
-    
-    for run in 0..10 {
-        println!("Run {}...", run + 1);
-        let (duration, operations) = run_concurrent_test(10, run).await?;
-        let throughput = operations as f64 / duration.as_secs_f64();
-        
-        scenario2_times.push(duration);
-        scenario2_throughputs.push(throughput);
-        
-        println!("  Duration: {:?}, Operations: {}, Throughput: {:.2} ops/sec", 
-                duration, operations, throughput);
+}
+
+fn print_history_check(check: &HistoryCheck) {
+    println!("  History: {} calls on {} items and {} carts", check.calls, check.items, check.carts);
+    if check.violations.is_empty() {
+        println!("  Linearizable");
     }
-    
-    // Scenario 3: 100 concurrent sellers and buyers
-    println!("\n=== Scenario 3: 100 sellers, 100 buyers ===");
-    let mut scenario3_times = Vec::new();
-    let mut scenario3_throughputs = Vec::new();
-    
-    for run in 0..10 {
-        println!("Run {}...", run + 1);
-        let (duration, operations) = run_concurrent_test(100, run).await?;
-        let throughput = operations as f64 / duration.as_secs_f64();
-        
-        scenario3_times.push(duration);
-        scenario3_throughputs.push(throughput);
-        
-        println!("  Duration: {:?}, Operations: {}, Throughput: {:.2} ops/sec", 
-                duration, operations, throughput);
-        
-        // Add delay between runs to allow TIME_WAIT sockets to clear
-        if run < 9 {
-            println!("  Waiting 5 seconds for connections to clear...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+    for violation in &check.violations {
+        println!("  Not linearizable: {}", violation.object);
+        for entry in &violation.entries {
+            println!("    {}", entry);
         }
     }
-    
-    // Calculate averages
-    let avg_time1: Duration = scenario1_times.iter().sum::<Duration>() / scenario1_times.len() as u32;
-    let avg_throughput1: f64 = scenario1_throughputs.iter().sum::<f64>() / scenario1_throughputs.len() as f64;
-    
-    let avg_time2: Duration = scenario2_times.iter().sum::<Duration>() / scenario2_times.len() as u32;
-    let avg_throughput2: f64 = scenario2_throughputs.iter().sum::<f64>() / scenario2_throughputs.len() as f64;
-    
-    let avg_time3: Duration = scenario3_times.iter().sum::<Duration>() / scenario3_times.len() as u32;
-    let avg_throughput3: f64 = scenario3_throughputs.iter().sum::<f64>() / scenario3_throughputs.len() as f64;
-    
-    // Print results
-    println!("\n=== Results Summary ===");
-    println!("Scenario 1 (1x1):");
-    println!("  Average Response Time: {:?}", avg_time1);
-    println!("  Average Throughput: {:.2} ops/sec", avg_throughput1);
-    
-    println!("\nScenario 2 (10x10):");
-    println!("  Average Response Time: {:?}", avg_time2);
-    println!("  Average Throughput: {:.2} ops/sec", avg_throughput2);
-    
-    println!("\nScenario 3 (100x100):");
-    println!("  Average Response Time: {:?}", avg_time3);
-    println!("  Average Throughput: {:.2} ops/sec", avg_throughput3);
-    
-    // Analysis
-    println!("\n=== Performance Analysis ===");
-    println!("1. Scenario 1 shows baseline performance with minimal contention.");
-    println!("2. Scenario 2 demonstrates how the system handles moderate concurrency.");
-    println!("3. Scenario 3 tests system limits with high concurrency.");
-    println!("\nExpected observations:");
-    println!("- Response time increases with more concurrent users");
-    println!("- Throughput should increase from Scenario 1 to 2, but may plateau or decrease in Scenario 3");
-    println!("- The system should remain stable under all scenarios");
-    
-    Ok(())
-}
\ No newline at end of file
+    for object in &check.inconclusive {
+        println!("  Inconclusive: {} (search budget exhausted)", object);
+    }
+    for object in &check.unchecked {
+        println!("  Not checked: {} (changed by calls the model does not cover)", object);
+    }
+}
+
+fn write_report(results: &Path, baseline: Option<&Path>, threshold: f64, output: Option<&Path>) -> Result<(), String> {
+    let results = Results::load(results)?;
+    let baseline = baseline.map(Results::load).transpose()?;
+    let report = report::render(&results, baseline.as_ref(), threshold);
+    match output {
+        Some(path) => std::fs::write(path, report).map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+        None => {
+            print!("{}", report);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Send times for open-loop runs. The target rate is split evenly over the
+// clients, so the sum of their schedules hits the rate of the current step.
+// Each response is timed from its intended send time rather than from when
+// it was actually sent, so a backlog in the evaluator or the servers counts
+// against the response times instead of hiding in fewer calls.
+
+use crate::latency::ApiLatencies;
+use crate::profile::{Schedule, Step};
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Exp};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub struct OpenLoopRun {
+    schedule: Schedule,
+    steps: Vec<Step>,
+    // When each step starts; the last entry is the end of the run
+    boundaries: Vec<Instant>,
+    clients: usize,
+    max_in_flight: usize,
+    in_flight: AtomicUsize,
+}
+
+// What happened to the calls intended for one step
+#[derive(Default)]
+pub struct StepStats {
+    // Send times that fell in the step
+    pub scheduled: u64,
+    // Of those, calls not sent because too many were unanswered
+    pub dropped: u64,
+    // Responses to scheduled calls that arrived during the step, whenever
+    // the calls were due
+    pub completed: u64,
+    // Calls intended for the step, timed from their intended send time
+    pub latencies: ApiLatencies,
+}
+
+impl StepStats {
+    pub fn merge(&mut self, other: &StepStats) {
+        self.scheduled += other.scheduled;
+        self.dropped += other.dropped;
+        self.completed += other.completed;
+        self.latencies.merge(&other.latencies);
+    }
+}
+
+impl OpenLoopRun {
+    pub fn new(schedule: Schedule, steps: Vec<Step>, clients: usize, max_in_flight: usize, started: Instant) -> Self {
+        let mut boundaries = vec![started];
+        for step in &steps {
+            let last = *boundaries.last().expect("starts with the start time");
+            boundaries.push(last + step.duration);
+        }
+        OpenLoopRun {
+            schedule,
+            steps,
+            boundaries,
+            clients: clients.max(1),
+            max_in_flight,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn new_stats(&self) -> Vec<StepStats> {
+        self.steps.iter().map(|_| StepStats::default()).collect()
+    }
+
+    // The step running at `at`, if the run has not ended
+    pub fn step_during(&self, at: Instant) -> Option<usize> {
+        if at < self.boundaries[0] {
+            return Some(0);
+        }
+        self.boundaries.windows(2).position(|bounds| at < bounds[1])
+    }
+
+    // The step an intended send time belongs to; times past the end count
+    // toward the last step
+    pub fn step_of(&self, at: Instant) -> usize {
+        self.step_during(at).unwrap_or(self.steps.len().saturating_sub(1))
+    }
+
+    // The first send time of the client in `slot`. Constant schedules stagger
+    // the clients so their sends interleave evenly.
+    pub fn first(&self, slot: usize, rng: &mut StdRng) -> Option<Instant> {
+        let start = self.boundaries[0];
+        let first = match self.schedule {
+            Schedule::Constant => start + self.gap(0, rng).mul_f64(slot as f64 / self.clients as f64),
+            Schedule::Poisson => start + self.gap(0, rng),
+        };
+        self.within_run(first)
+    }
+
+    // The send time after `previous`, None once past the end of the run
+    pub fn after(&self, previous: Instant, rng: &mut StdRng) -> Option<Instant> {
+        let step = self.step_during(previous)?;
+        let mut next = previous + self.gap(step, rng);
+        // The part of a gap that runs into the next step is shortened or
+        // stretched to that step's rate
+        let boundary = self.boundaries[step + 1];
+        if next > boundary && step + 1 < self.steps.len() {
+            next = boundary + (next - boundary).mul_f64(self.steps[step].rate / self.steps[step + 1].rate);
+        }
+        self.within_run(next)
+    }
+
+    fn within_run(&self, at: Instant) -> Option<Instant> {
+        let end = *self.boundaries.last().expect("starts with the start time");
+        (at < end).then_some(at)
+    }
+
+    // Time between two sends of one client during `step`
+    fn gap(&self, step: usize, rng: &mut StdRng) -> Duration {
+        let rate = self.steps[step].rate / self.clients as f64;
+        match self.schedule {
+            Schedule::Constant => Duration::from_secs_f64(1.0 / rate),
+            // The profile's rate is validated positive, so `Exp::new` succeeds
+            Schedule::Poisson => Duration::from_secs_f64(Exp::new(rate).map_or(1.0 / rate, |exp| exp.sample(rng))),
+        }
+    }
+
+    // Claims a slot for one more unanswered call, false if all are taken
+    pub fn try_send(&self) -> bool {
+        self.in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| (n < self.max_in_flight).then_some(n + 1))
+            .is_ok()
+    }
+
+    pub fn answered(&self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,316 @@
+// Workload profiles: how many sellers and buyers run, which calls they make
+// and how often, which items they pick, how long they pause between calls and
+// how long a run lasts. Profiles are read from TOML files; without one the
+// evaluator runs the three scenarios from the spec with the built-in mix.
+
+use crate::cluster::Component;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// Calls a seller can make. Named after the API, except `Relogin`, which logs
+// out and back in.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SellerOp {
+    RegisterItemForSale,
+    ChangeItemPrice,
+    UpdateUnitsForSale,
+    DisplayItemsForSale,
+    GetSellerRating,
+    Relogin,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuyerOp {
+    SearchItemsForSale,
+    GetItem,
+    AddItemToCart,
+    RemoveItemFromCart,
+    DisplayCart,
+    SaveCart,
+    ClearCart,
+    ProvideFeedback,
+    GetSellerRating,
+    GetBuyerPurchases,
+    Relogin,
+}
+
+// How clients choose which item to act on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(tag = "distribution", rename_all = "lowercase", deny_unknown_fields)]
+pub enum KeyDistribution {
+    #[default]
+    Uniform,
+    // Item ranks follow Zipf's law, the earliest registered items being the
+    // most popular
+    Zipf { exponent: f64 },
+}
+
+// Pause after each call, drawn uniformly from [min, max] milliseconds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThinkTime {
+    pub min: u64,
+    pub max: u64,
+}
+
+// How open-loop send times are spaced
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Schedule {
+    // Exponential gaps, as from many independent users
+    #[default]
+    Poisson,
+    // Evenly spaced
+    Constant,
+}
+
+// Sends requests at a target rate whether or not earlier ones have been
+// answered, so queueing in the servers shows up in the response times
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct OpenLoop {
+    #[serde(default)]
+    pub schedule: Schedule,
+    // Requests per second over all clients
+    pub rate: f64,
+    // Raises the rate by `ramp_step` every `step_secs` until it reaches `ramp_to`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ramp_to: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ramp_step: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_secs: Option<u64>,
+    // Requests past this many unanswered ones are dropped and counted as failed
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+// Kills a launched component `at_secs` into the measured window and starts it
+// again `down_secs` later
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Fault {
+    pub component: Component,
+    pub at_secs: f64,
+    #[serde(default)]
+    pub down_secs: f64,
+}
+
+// One stretch of an open-loop run at a fixed target rate
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    pub rate: f64,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RunLength {
+    // Calls per seller and per buyer
+    Ops(usize),
+    Duration(Duration),
+}
+
+impl std::fmt::Display for RunLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunLength::Ops(ops) => write!(f, "{} calls per client", ops),
+            // Timed runs share one clock, and an open loop's rate is shared by
+            // all clients, so the length is the run's rather than a client's
+            RunLength::Duration(duration) => write!(f, "{}s per run", duration.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    pub sellers: usize,
+    pub buyers: usize,
+    // At most one of these; a run is 1000 calls per client if neither is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ops_per_client: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_secs: Option<u64>,
+    #[serde(default = "default_runs")]
+    pub runs: usize,
+    #[serde(default)]
+    pub think_time_ms: ThinkTime,
+    #[serde(default)]
+    pub keys: KeyDistribution,
+    // Registered by each seller before the measured window opens
+    #[serde(default = "default_seed_items")]
+    pub seed_items_per_seller: usize,
+    #[serde(default)]
+    pub seller_mix: BTreeMap<SellerOp, u32>,
+    #[serde(default)]
+    pub buyer_mix: BTreeMap<BuyerOp, u32>,
+    // Closed-loop when unset: each client waits for a response before its next call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_loop: Option<OpenLoop>,
+    // Faults to inject in every run, in order of `at_secs`; needs the
+    // evaluator to launch the components itself
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faults: Vec<Fault>,
+}
+
+fn default_runs() -> usize {
+    10
+}
+
+fn default_seed_items() -> usize {
+    10
+}
+
+fn default_max_in_flight() -> usize {
+    2000
+}
+
+impl Profile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let profile: Profile = toml::from_str(&text).map_err(|e| format!("Invalid profile {}: {}", path, e))?;
+        profile.validate().map_err(|e| format!("Invalid profile {}: {}", path, e))?;
+        Ok(profile)
+    }
+
+    // The spec's scenarios: 1, 10 and 100 seller/buyer pairs, sellers only
+    // registering items and buyers only searching, so half the calls are each.
+    // profiles/mixed.toml runs the other APIs as well.
+    pub fn scenarios() -> Vec<Profile> {
+        [1, 10, 100]
+            .into_iter()
+            .map(|pairs| Profile {
+                name: format!("{}x{}", pairs, pairs),
+                sellers: pairs,
+                buyers: pairs,
+                ops_per_client: None,
+                duration_secs: None,
+                runs: default_runs(),
+                think_time_ms: ThinkTime::default(),
+                keys: KeyDistribution::Uniform,
+                seed_items_per_seller: default_seed_items(),
+                seller_mix: BTreeMap::from([(SellerOp::RegisterItemForSale, 1)]),
+                buyer_mix: BTreeMap::from([(BuyerOp::SearchItemsForSale, 1)]),
+                open_loop: None,
+                faults: Vec::new(),
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sellers == 0 {
+            return Err("at least one seller is needed to list items".to_string());
+        }
+        if self.ops_per_client.is_some() && self.duration_secs.is_some() {
+            return Err("set ops_per_client or duration_secs, not both".to_string());
+        }
+        if self.runs == 0 {
+            return Err("runs must be at least 1".to_string());
+        }
+        if self.think_time_ms.min > self.think_time_ms.max {
+            return Err("think_time_ms.min is above think_time_ms.max".to_string());
+        }
+        if let KeyDistribution::Zipf { exponent } = self.keys {
+            if exponent.is_nan() || exponent <= 0.0 {
+                return Err("the zipf exponent must be positive".to_string());
+            }
+        }
+        if self.seller_mix.values().sum::<u32>() == 0 {
+            return Err("seller_mix needs at least one positive weight".to_string());
+        }
+        if self.buyers > 0 && self.buyer_mix.values().sum::<u32>() == 0 {
+            return Err("buyer_mix needs at least one positive weight".to_string());
+        }
+        if let Some(open_loop) = &self.open_loop {
+            self.validate_open_loop(open_loop)?;
+        }
+        for fault in &self.faults {
+            if !fault.at_secs.is_finite() || fault.at_secs < 0.0 || !fault.down_secs.is_finite() || fault.down_secs < 0.0 {
+                return Err(format!("fault on {}: at_secs and down_secs must be zero or more", fault.component));
+            }
+            if let RunLength::Duration(duration) = self.length() {
+                if fault.at_secs >= duration.as_secs_f64() {
+                    return Err(format!("fault on {} at {}s is past the end of the run", fault.component, fault.at_secs));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_open_loop(&self, open_loop: &OpenLoop) -> Result<(), String> {
+        if open_loop.rate.is_nan() || open_loop.rate <= 0.0 {
+            return Err("open_loop.rate must be positive".to_string());
+        }
+        if open_loop.max_in_flight == 0 {
+            return Err("open_loop.max_in_flight must be at least 1".to_string());
+        }
+        if self.ops_per_client.is_some() {
+            return Err("open-loop runs are timed; use duration_secs or a ramp instead of ops_per_client".to_string());
+        }
+        if self.think_time_ms.max > 0 {
+            return Err("think_time_ms does not apply to open-loop runs".to_string());
+        }
+        match (open_loop.ramp_to, open_loop.ramp_step, open_loop.step_secs) {
+            (None, None, None) => {
+                if self.duration_secs.is_none() {
+                    return Err("an open-loop run without a ramp needs duration_secs".to_string());
+                }
+            }
+            (Some(ramp_to), Some(ramp_step), Some(step_secs)) => {
+                if self.duration_secs.is_some() {
+                    return Err("the ramp sets the run length; remove duration_secs".to_string());
+                }
+                if ramp_to.is_nan() || ramp_to < open_loop.rate {
+                    return Err("open_loop.ramp_to must be at least open_loop.rate".to_string());
+                }
+                if ramp_step.is_nan() || ramp_step <= 0.0 || step_secs == 0 {
+                    return Err("open_loop.ramp_step and open_loop.step_secs must be positive".to_string());
+                }
+            }
+            _ => return Err("a ramp needs open_loop.ramp_to, ramp_step and step_secs together".to_string()),
+        }
+        Ok(())
+    }
+
+    // The target rate over time for an open-loop run, None for a closed-loop one
+    pub fn open_loop_steps(&self) -> Option<Vec<Step>> {
+        let open_loop = self.open_loop?;
+        let Some(ramp_to) = open_loop.ramp_to else {
+            return Some(vec![Step {
+                rate: open_loop.rate,
+                duration: Duration::from_secs(self.duration_secs.unwrap_or(0)),
+            }]);
+        };
+        let ramp_step = open_loop.ramp_step.unwrap_or(ramp_to);
+        let duration = Duration::from_secs(open_loop.step_secs.unwrap_or(0));
+        let mut steps = Vec::new();
+        let mut rate = open_loop.rate;
+        loop {
+            steps.push(Step { rate: rate.min(ramp_to), duration });
+            if rate >= ramp_to {
+                break;
+            }
+            rate += ramp_step;
+        }
+        Some(steps)
+    }
+
+    pub fn length(&self) -> RunLength {
+        if let Some(steps) = self.open_loop_steps() {
+            return RunLength::Duration(steps.iter().map(|step| step.duration).sum());
+        }
+        match (self.ops_per_client, self.duration_secs) {
+            (_, Some(secs)) => RunLength::Duration(Duration::from_secs(secs)),
+            (Some(ops), None) => RunLength::Ops(ops),
+            (None, None) => RunLength::Ops(1000),
+        }
+    }
+
+    // Command-line overrides; a call count replaces a duration
+    pub fn set_ops_per_client(&mut self, ops: usize) {
+        self.ops_per_client = Some(ops);
+        self.duration_secs = None;
+    }
+}
@@ -0,0 +1,355 @@
+// Renders a results file as a Markdown performance report, optionally
+// comparing it against a baseline results file. Changes beyond the threshold
+// are flagged: lower throughput and higher latency are regressions.
+
+use crate::faults::FaultReport;
+use crate::latency::LatencySummary;
+use crate::linearizability::HistoryCheck;
+use crate::profile::{KeyDistribution, Profile};
+use crate::results::{Environment, Results, RunSummary, ScenarioResult};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+// Which direction of change is bad for a metric
+#[derive(Clone, Copy)]
+enum Better {
+    Higher,
+    Lower,
+}
+
+struct Change {
+    scenario: String,
+    metric: String,
+    baseline: f64,
+    current: f64,
+    percent: Option<f64>,
+}
+
+pub fn render(results: &Results, baseline: Option<&Results>, threshold: f64) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Performance Report\n");
+    setup(&mut out, &results.environment);
+
+    let _ = writeln!(out, "## Measured Results\n");
+    for (index, scenario) in results.scenarios.iter().enumerate() {
+        measured(&mut out, index + 1, scenario);
+    }
+
+    if let Some(baseline) = baseline {
+        comparison(&mut out, results, baseline, threshold);
+    }
+    out
+}
+
+fn setup(out: &mut String, env: &Environment) {
+    let _ = writeln!(out, "## Experimental Setup");
+    let _ = writeln!(out, "- Host: {} ({}/{}, {} CPUs)", env.host, env.os, env.arch, env.cpus);
+    let _ = writeln!(out, "- Commit: {}", describe_commit(env));
+    let _ = writeln!(out, "- Build: {} evaluator {}", env.build, env.evaluator_version);
+    let _ = writeln!(out, "- Servers: `seller_server` at {}, `buyer_server` at {}", env.seller_server, env.buyer_server);
+    let _ = writeln!(out, "- Started: {}", env.started_at);
+    let _ = writeln!(out, "- Command: `{}`\n", env.command_line.join(" "));
+}
+
+fn describe_commit(env: &Environment) -> String {
+    match (&env.git_commit, env.git_dirty) {
+        (Some(commit), Some(true)) => format!("`{}` with uncommitted changes", commit),
+        (Some(commit), _) => format!("`{}`", commit),
+        (None, _) => "unknown".to_string(),
+    }
+}
+
+fn describe_profile(profile: &Profile) -> String {
+    let mut description = String::new();
+    if let Some(open_loop) = &profile.open_loop {
+        let _ = write!(description, "Open loop, {:?} schedule: ", open_loop.schedule);
+    }
+    let _ = write!(
+        description,
+        "{} sellers, {} buyers, {}, {} runs, {} items seeded per seller",
+        profile.sellers, profile.buyers, profile.length(), profile.runs, profile.seed_items_per_seller
+    );
+    if let Some(steps) = profile.open_loop_steps() {
+        let first = steps.first().map_or(0.0, |step| step.rate);
+        let last = steps.last().map_or(0.0, |step| step.rate);
+        if steps.len() > 1 {
+            let _ = write!(description, ", {:.1} to {:.1} req/s in {} steps", first, last, steps.len());
+        } else {
+            let _ = write!(description, ", {:.1} req/s", first);
+        }
+    }
+    if profile.think_time_ms.max > 0 {
+        let _ = write!(
+            description,
+            ", {}-{} ms think time",
+            profile.think_time_ms.min, profile.think_time_ms.max
+        );
+    }
+    if let KeyDistribution::Zipf { exponent } = profile.keys {
+        let _ = write!(description, ", Zipf item popularity (exponent {})", exponent);
+    }
+    description
+}
+
+fn measured(out: &mut String, number: usize, scenario: &ScenarioResult) {
+    let overall = &scenario.overall;
+    let _ = writeln!(out, "### Scenario {}: {}", number, scenario.profile.name);
+    let _ = writeln!(out, "{}\n", describe_profile(&scenario.profile));
+    let _ = writeln!(out, "- Average Throughput: {:.2} ops/sec", scenario.avg_throughput);
+    let _ = writeln!(
+        out,
+        "- Response Time: mean {:.3} ms, p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+        overall.mean_ms, overall.p50_ms, overall.p90_ms, overall.p99_ms, overall.max_ms
+    );
+    let _ = writeln!(
+        out,
+        "- Calls: {} ({} answered with an error, {} without a response)\n",
+        overall.calls, overall.errors, overall.failed
+    );
+
+    let _ = writeln!(out, "| API | Calls | Errors | Failed | p50 ms | p90 ms | p99 ms | Max ms |");
+    let _ = writeln!(out, "|-----|------:|-------:|-------:|-------:|-------:|-------:|-------:|");
+    for (api, summary) in &scenario.apis {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {:.3} | {:.3} | {:.3} | {:.3} |",
+            api, summary.calls, summary.errors, summary.failed,
+            summary.p50_ms, summary.p90_ms, summary.p99_ms, summary.max_ms
+        );
+    }
+
+    let _ = writeln!(out, "\n#### Per-Run Measurements\n");
+    let _ = writeln!(out, "| Run | Window s | Calls | Errors | Failed | Throughput ops/sec | p50 ms | p99 ms |");
+    let _ = writeln!(out, "|----:|---------:|------:|-------:|-------:|-------------------:|-------:|-------:|");
+    for run in &scenario.runs {
+        let _ = writeln!(
+            out,
+            "| {} | {:.3} | {} | {} | {} | {:.2} | {:.3} | {:.3} |",
+            run.run, run.window_secs, run.overall.calls, run.overall.errors, run.overall.failed,
+            run.throughput, run.overall.p50_ms, run.overall.p99_ms
+        );
+    }
+    let _ = writeln!(out);
+
+    for run in scenario.runs.iter().filter(|run| !run.steps.is_empty()) {
+        open_loop_steps(out, run);
+    }
+    for run in &scenario.runs {
+        if let Some(check) = &run.history {
+            history_check(out, run.run, check);
+        }
+        if let Some(report) = &run.faults {
+            fault_report(out, run.run, report);
+        }
+    }
+}
+
+fn fault_report(out: &mut String, run: usize, report: &FaultReport) {
+    let _ = writeln!(out, "#### Run {} Faults\n", run);
+    let _ = writeln!(out, "| Component | Killed at s | Down s | Back after s | Calls | Errors | Lost sessions | No response |");
+    let _ = writeln!(out, "|-----------|------------:|-------:|-------------:|------:|-------:|--------------:|------------:|");
+    for fault in &report.faults {
+        let back = match fault.recovery_secs {
+            Some(secs) => format!("{:.2}", secs),
+            None => "did not come back".to_string(),
+        };
+        let calls = &fault.calls;
+        let _ = writeln!(
+            out,
+            "| {} | {:.1} | {:.1} | {} | {} | {} | {} | {} |",
+            fault.component, fault.at_secs, fault.down_secs, back,
+            calls.calls, calls.errors, calls.session_lost, calls.no_response
+        );
+    }
+    let outside = &report.outside;
+    let _ = writeln!(
+        out,
+        "| outside fault windows | | | | {} | {} | {} | {} |\n",
+        outside.calls, outside.errors, outside.session_lost, outside.no_response
+    );
+    for failed in &report.not_injected {
+        let _ = writeln!(
+            out,
+            "{} was not killed at {:.1}s: {}.\n",
+            failed.component, failed.at_secs, failed.error
+        );
+    }
+    if let Some(loss) = &report.data_loss {
+        let _ = writeln!(
+            out,
+            "Data lost: {} of {} sellers, {} of {} buyers, {} of {} items.\n",
+            loss.sellers_lost, loss.sellers, loss.buyers_lost, loss.buyers, loss.items_lost, loss.items
+        );
+    }
+
+    let mut errors: BTreeMap<&str, u64> = BTreeMap::new();
+    for counts in report.faults.iter().map(|fault| &fault.calls).chain([outside]) {
+        for (error, count) in &counts.by_error {
+            *errors.entry(error).or_insert(0) += count;
+        }
+    }
+    if !errors.is_empty() {
+        let _ = writeln!(out, "| Error | Calls |");
+        let _ = writeln!(out, "|-------|------:|");
+        for (error, count) in errors {
+            let _ = writeln!(out, "| {} | {} |", error.replace('|', "\\|"), count);
+        }
+        let _ = writeln!(out);
+    }
+}
+
+fn history_check(out: &mut String, run: usize, check: &HistoryCheck) {
+    let _ = writeln!(out, "#### Run {} Linearizability\n", run);
+    let _ = writeln!(
+        out,
+        "{} item and cart calls on {} items and {} carts: {}.\n",
+        check.calls,
+        check.items,
+        check.carts,
+        if check.violations.is_empty() { "linearizable" } else { "NOT linearizable" }
+    );
+    for violation in &check.violations {
+        let _ = writeln!(out, "Smallest failing history of {}:\n", violation.object);
+        let _ = writeln!(out, "```");
+        for entry in &violation.entries {
+            let _ = writeln!(out, "{}", entry);
+        }
+        let _ = writeln!(out, "```\n");
+    }
+    if !check.inconclusive.is_empty() {
+        let _ = writeln!(out, "Not decided within the search budget: {}.\n", check.inconclusive.join(", "));
+    }
+    if !check.unchecked.is_empty() {
+        let _ = writeln!(out, "Not checked, changed by calls the model does not cover: {}.\n", check.unchecked.join(", "));
+    }
+}
+
+fn open_loop_steps(out: &mut String, run: &RunSummary) {
+    let _ = writeln!(out, "#### Run {} Open-Loop Steps\n", run.run);
+    let _ = writeln!(out, "{}.\n", run.describe_saturation());
+    let _ = writeln!(out, "| Target req/s | Offered req/s | Achieved req/s | Dropped | p50 ms | p99 ms | Max ms | |");
+    let _ = writeln!(out, "|-------------:|--------------:|---------------:|--------:|-------:|-------:|-------:|-|");
+    for step in &run.steps {
+        let _ = writeln!(
+            out,
+            "| {:.1} | {:.1} | {:.1} | {} | {:.3} | {:.3} | {:.3} | {} |",
+            step.target_rate, step.offered_rate, step.achieved_rate, step.dropped,
+            step.overall.p50_ms, step.overall.p99_ms, step.overall.max_ms,
+            if step.sustained() { "" } else { "saturated" }
+        );
+    }
+    let _ = writeln!(out);
+}
+
+fn comparison(out: &mut String, results: &Results, baseline: &Results, threshold: f64) {
+    let _ = writeln!(out, "## Comparison with Baseline\n");
+    let _ = writeln!(
+        out,
+        "Baseline: commit {}, started {} on {}. Changes beyond {}% are flagged.\n",
+        describe_commit(&baseline.environment),
+        baseline.environment.started_at,
+        baseline.environment.host,
+        threshold
+    );
+
+    let mut tables = String::new();
+    let mut regressions = Vec::new();
+    for scenario in &results.scenarios {
+        let name = &scenario.profile.name;
+        let _ = writeln!(tables, "### {}\n", name);
+        let Some(base) = baseline.scenarios.iter().find(|base| &base.profile.name == name) else {
+            let _ = writeln!(tables, "No scenario named {} in the baseline.\n", name);
+            continue;
+        };
+
+        let _ = writeln!(tables, "| Metric | Baseline | Current | Change | |");
+        let _ = writeln!(tables, "|--------|---------:|--------:|-------:|-|");
+        let mut row = |metric: String, baseline: f64, current: f64, better: Better| {
+            let percent = percent_change(baseline, current);
+            let verdict = verdict(percent, better, threshold);
+            let _ = writeln!(
+                tables,
+                "| {} | {:.3} | {:.3} | {} | {} |",
+                metric, baseline, current, describe_percent(percent), verdict
+            );
+            if verdict == "regression" {
+                regressions.push(Change {
+                    scenario: name.clone(),
+                    metric,
+                    baseline,
+                    current,
+                    percent,
+                });
+            }
+        };
+
+        row("Throughput ops/sec".to_string(), base.avg_throughput, scenario.avg_throughput, Better::Higher);
+        latency_rows(&mut row, "All APIs", &base.overall, &scenario.overall);
+        for (api, summary) in &scenario.apis {
+            if let Some(base_summary) = base.apis.get(api) {
+                latency_rows(&mut row, api, base_summary, summary);
+            }
+        }
+        let _ = writeln!(tables);
+    }
+
+    if regressions.is_empty() {
+        let _ = writeln!(out, "No regressions beyond {}%.\n", threshold);
+    } else {
+        let _ = writeln!(out, "**{} regression(s):**\n", regressions.len());
+        for change in &regressions {
+            let _ = writeln!(
+                out,
+                "- {}: {} went from {:.3} to {:.3} ({})",
+                change.scenario,
+                change.metric,
+                change.baseline,
+                change.current,
+                describe_percent(change.percent)
+            );
+        }
+        let _ = writeln!(out);
+    }
+    out.push_str(&tables);
+}
+
+fn latency_rows(
+    row: &mut impl FnMut(String, f64, f64, Better),
+    label: &str,
+    baseline: &LatencySummary,
+    current: &LatencySummary,
+) {
+    row(format!("{} p50 ms", label), baseline.p50_ms, current.p50_ms, Better::Lower);
+    row(format!("{} p99 ms", label), baseline.p99_ms, current.p99_ms, Better::Lower);
+}
+
+// None when the baseline is zero and the current value is not
+fn percent_change(baseline: f64, current: f64) -> Option<f64> {
+    if baseline == 0.0 {
+        return if current == 0.0 { Some(0.0) } else { None };
+    }
+    Some((current - baseline) / baseline * 100.0)
+}
+
+fn describe_percent(percent: Option<f64>) -> String {
+    match percent {
+        Some(percent) => format!("{:+.1}%", percent),
+        None => "new".to_string(),
+    }
+}
+
+fn verdict(percent: Option<f64>, better: Better, threshold: f64) -> &'static str {
+    let Some(percent) = percent else {
+        return match better {
+            Better::Higher => "improvement",
+            Better::Lower => "regression",
+        };
+    };
+    if percent.abs() <= threshold {
+        return "";
+    }
+    match (better, percent > 0.0) {
+        (Better::Higher, true) | (Better::Lower, false) => "improvement",
+        (Better::Higher, false) | (Better::Lower, true) => "regression",
+    }
+}
@@ -0,0 +1,386 @@
+// Evaluation results as written to disk: one JSON file with everything, and
+// CSV files with per-run, per-scenario and per-API rows for spreadsheets.
+// `evaluator report` reads the JSON file back.
+
+use crate::faults::FaultReport;
+use crate::latency::{ApiLatencies, LatencySummary};
+use crate::linearizability::HistoryCheck;
+use crate::open_loop::StepStats;
+use crate::profile::{Profile, Step};
+use crate::workload::{Frontends, RunResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Results {
+    pub environment: Environment,
+    pub scenarios: Vec<ScenarioResult>,
+}
+
+// Where and how the evaluation ran
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Environment {
+    pub started_at: String,
+    // None when not run from a git checkout
+    pub git_commit: Option<String>,
+    pub git_dirty: Option<bool>,
+    pub host: String,
+    pub os: String,
+    pub arch: String,
+    pub cpus: usize,
+    pub build: String,
+    pub evaluator_version: String,
+    pub command_line: Vec<String>,
+    pub seller_server: String,
+    pub buyer_server: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScenarioResult {
+    // The profile as run, command-line overrides applied
+    pub profile: Profile,
+    pub avg_throughput: f64,
+    // All runs together
+    pub overall: LatencySummary,
+    pub apis: BTreeMap<String, LatencySummary>,
+    pub runs: Vec<RunSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub run: usize,
+    pub window_secs: f64,
+    pub throughput: f64,
+    pub overall: LatencySummary,
+    pub apis: BTreeMap<String, LatencySummary>,
+    // Open-loop runs only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepSummary>,
+    // Runs with --history only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryCheck>,
+    // Runs of profiles with faults only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub faults: Option<FaultReport>,
+}
+
+// One step of an open-loop run. Rates are per second over the step.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StepSummary {
+    pub target_rate: f64,
+    pub duration_secs: f64,
+    // Calls due to be sent during the step
+    pub scheduled: u64,
+    // Of those, calls not sent because too many were unanswered
+    pub dropped: u64,
+    // Responses to scheduled calls that arrived during the step
+    pub completed: u64,
+    pub offered_rate: f64,
+    pub achieved_rate: f64,
+    // Calls due during the step, timed from when they were due
+    pub overall: LatencySummary,
+}
+
+// A step keeps up when it answers at least this share of what it was offered
+const SUSTAINED_RATIO: f64 = 0.9;
+
+impl StepSummary {
+    fn new(step: &Step, stats: &StepStats) -> Self {
+        let secs = step.duration.as_secs_f64();
+        StepSummary {
+            target_rate: step.rate,
+            duration_secs: secs,
+            scheduled: stats.scheduled,
+            dropped: stats.dropped,
+            completed: stats.completed,
+            offered_rate: stats.scheduled as f64 / secs,
+            achieved_rate: stats.completed as f64 / secs,
+            overall: stats.latencies.overall(),
+        }
+    }
+
+    pub fn sustained(&self) -> bool {
+        self.dropped == 0 && self.achieved_rate >= self.offered_rate * SUSTAINED_RATIO
+    }
+}
+
+impl Environment {
+    pub fn capture(frontends: &Frontends) -> Self {
+        let git_commit = git(&["rev-parse", "HEAD"]);
+        let git_dirty = git_commit
+            .as_ref()
+            .and_then(|_| git(&["status", "--porcelain", "--untracked-files=no"]))
+            .map(|status| !status.is_empty());
+        Environment {
+            started_at: chrono::Utc::now().to_rfc3339(),
+            git_commit,
+            git_dirty,
+            host: hostname(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpus: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            build: if cfg!(debug_assertions) { "debug" } else { "release" }.to_string(),
+            evaluator_version: env!("CARGO_PKG_VERSION").to_string(),
+            command_line: std::env::args().collect(),
+            seller_server: frontends.seller.clone(),
+            buyer_server: frontends.buyer.clone(),
+        }
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            let output = Command::new("hostname").output().ok()?;
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl RunSummary {
+    pub fn new(run: usize, result: &RunResult) -> Self {
+        RunSummary {
+            run,
+            window_secs: result.window.as_secs_f64(),
+            throughput: result.throughput(),
+            overall: result.latencies.overall(),
+            apis: result.latencies.summaries(),
+            steps: result.steps.iter().map(|(step, stats)| StepSummary::new(step, stats)).collect(),
+            history: None,
+            faults: None,
+        }
+    }
+
+    // The first step that could not keep up with its offered rate
+    pub fn saturation(&self) -> Option<&StepSummary> {
+        self.steps.iter().find(|step| !step.sustained())
+    }
+
+    // Where an open-loop run stopped keeping up with its offered rate
+    pub fn describe_saturation(&self) -> String {
+        let Some(saturated) = self.saturation() else {
+            let top = self.steps.iter().map(|step| step.target_rate).fold(0.0, f64::max);
+            return format!("Kept up with every step, up to {:.1} req/s", top);
+        };
+        match self.steps.iter().take_while(|step| step.sustained()).last() {
+            Some(sustained) => format!(
+                "Saturated at {:.1} req/s (achieved {:.1} req/s); last sustained step {:.1} req/s",
+                saturated.target_rate, saturated.achieved_rate, sustained.target_rate
+            ),
+            None => format!(
+                "Saturated from the first step at {:.1} req/s (achieved {:.1} req/s)",
+                saturated.target_rate, saturated.achieved_rate
+            ),
+        }
+    }
+}
+
+impl ScenarioResult {
+    pub fn new(profile: &Profile, latencies: &ApiLatencies, runs: Vec<RunSummary>) -> Self {
+        let avg_throughput = runs.iter().map(|run| run.throughput).sum::<f64>() / runs.len() as f64;
+        ScenarioResult {
+            profile: profile.clone(),
+            avg_throughput,
+            overall: latencies.overall(),
+            apis: latencies.summaries(),
+            runs,
+        }
+    }
+}
+
+impl Results {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Invalid results file {}: {}", path.display(), e))
+    }
+
+    // Writes results.json, scenarios.csv, runs.csv and apis.csv into `dir`,
+    // plus steps.csv for open-loop runs and faults.csv for runs with faults
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("results.json"), serde_json::to_string_pretty(self)? + "\n")?;
+        std::fs::write(dir.join("scenarios.csv"), self.scenarios_csv())?;
+        std::fs::write(dir.join("runs.csv"), self.runs_csv())?;
+        std::fs::write(dir.join("apis.csv"), self.apis_csv())?;
+        if self.scenarios.iter().flat_map(|scenario| &scenario.runs).any(|run| !run.steps.is_empty()) {
+            std::fs::write(dir.join("steps.csv"), self.steps_csv())?;
+        }
+        if self.scenarios.iter().flat_map(|scenario| &scenario.runs).any(|run| run.faults.is_some()) {
+            std::fs::write(dir.join("faults.csv"), self.faults_csv())?;
+        }
+        Ok(())
+    }
+
+    fn scenarios_csv(&self) -> String {
+        let mut csv = format!("scenario,name,sellers,buyers,runs,avg_throughput,{}\n", LATENCY_COLUMNS);
+        for (index, scenario) in self.scenarios.iter().enumerate() {
+            let profile = &scenario.profile;
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{:.2},{}",
+                index + 1,
+                csv_field(&profile.name),
+                profile.sellers,
+                profile.buyers,
+                profile.runs,
+                scenario.avg_throughput,
+                latency_fields(&scenario.overall)
+            );
+        }
+        csv
+    }
+
+    fn runs_csv(&self) -> String {
+        let mut csv = format!("scenario,name,run,window_secs,throughput,{}\n", LATENCY_COLUMNS);
+        for (index, scenario) in self.scenarios.iter().enumerate() {
+            for run in &scenario.runs {
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{:.3},{:.2},{}",
+                    index + 1,
+                    csv_field(&scenario.profile.name),
+                    run.run,
+                    run.window_secs,
+                    run.throughput,
+                    latency_fields(&run.overall)
+                );
+            }
+        }
+        csv
+    }
+
+    // Per-API figures over all runs of each scenario
+    fn apis_csv(&self) -> String {
+        let mut csv = format!("scenario,name,api,{}\n", LATENCY_COLUMNS);
+        for (index, scenario) in self.scenarios.iter().enumerate() {
+            for (api, summary) in &scenario.apis {
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{}",
+                    index + 1,
+                    csv_field(&scenario.profile.name),
+                    api,
+                    latency_fields(summary)
+                );
+            }
+        }
+        csv
+    }
+
+    // One row per step of each open-loop run
+    fn steps_csv(&self) -> String {
+        let mut csv = format!(
+            "scenario,name,run,step,target_rate,duration_secs,scheduled,dropped,completed,offered_rate,achieved_rate,sustained,{}\n",
+            LATENCY_COLUMNS
+        );
+        for (index, scenario) in self.scenarios.iter().enumerate() {
+            for run in &scenario.runs {
+                for (number, step) in run.steps.iter().enumerate() {
+                    let _ = writeln!(
+                        csv,
+                        "{},{},{},{},{:.2},{:.3},{},{},{},{:.2},{:.2},{},{}",
+                        index + 1,
+                        csv_field(&scenario.profile.name),
+                        run.run,
+                        number + 1,
+                        step.target_rate,
+                        step.duration_secs,
+                        step.scheduled,
+                        step.dropped,
+                        step.completed,
+                        step.offered_rate,
+                        step.achieved_rate,
+                        step.sustained(),
+                        latency_fields(&step.overall)
+                    );
+                }
+            }
+        }
+        csv
+    }
+
+    // One row per injected fault, plus one per run for the calls outside
+    // every fault window
+    fn faults_csv(&self) -> String {
+        let mut csv = "scenario,name,run,component,at_secs,down_secs,recovery_secs,calls,errors,session_lost,no_response\n".to_string();
+        for (index, scenario) in self.scenarios.iter().enumerate() {
+            for run in &scenario.runs {
+                let Some(report) = &run.faults else {
+                    continue;
+                };
+                let name = csv_field(&scenario.profile.name);
+                for fault in &report.faults {
+                    let calls = &fault.calls;
+                    let _ = writeln!(
+                        csv,
+                        "{},{},{},{},{:.3},{:.3},{},{},{},{},{}",
+                        index + 1,
+                        name,
+                        run.run,
+                        fault.component,
+                        fault.at_secs,
+                        fault.down_secs,
+                        fault.recovery_secs.map(|secs| format!("{:.3}", secs)).unwrap_or_default(),
+                        calls.calls,
+                        calls.errors,
+                        calls.session_lost,
+                        calls.no_response
+                    );
+                }
+                let outside = &report.outside;
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},none,,,,{},{},{},{}",
+                    index + 1,
+                    name,
+                    run.run,
+                    outside.calls,
+                    outside.errors,
+                    outside.session_lost,
+                    outside.no_response
+                );
+            }
+        }
+        csv
+    }
+}
+
+const LATENCY_COLUMNS: &str = "calls,errors,failed,mean_ms,p50_ms,p90_ms,p99_ms,max_ms";
+
+fn latency_fields(summary: &LatencySummary) -> String {
+    format!(
+        "{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3}",
+        summary.calls,
+        summary.errors,
+        summary.failed,
+        summary.mean_ms,
+        summary.p50_ms,
+        summary.p90_ms,
+        summary.p99_ms,
+        summary.max_ms
+    )
+}
+
+// Quotes a field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
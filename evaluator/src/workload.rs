@@ -0,0 +1,754 @@
+// Runs one profile against the frontends: sets up accounts and a seed
+// catalog, then has every seller and buyer make calls from its mix until the
+// run's call count or duration is reached, timing each call. Closed-loop
+// clients wait for each response; open-loop ones send on a schedule.
+
+use crate::faults::{CallLog, CallResult};
+use crate::history::{self, Entry, Op, Recorder};
+use crate::latency::ApiLatencies;
+use crate::open_loop::{OpenLoopRun, StepStats};
+use crate::profile::{BuyerOp, KeyDistribution, Profile, RunLength, SellerOp, Step};
+use common::*;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::Zipf;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task;
+use uuid::Uuid;
+
+const PASSWORD: &str = "password";
+
+// Where the seller and buyer frontends listen
+#[derive(Clone, Debug)]
+pub struct Frontends {
+    pub seller: String,
+    pub buyer: String,
+}
+
+impl Frontends {
+    // SELLER_SERVER_ADDR and BUYER_SERVER_ADDR, or the frontends' default ports
+    pub fn from_env() -> Self {
+        Frontends {
+            seller: std::env::var("SELLER_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:8082".to_string()),
+            buyer: std::env::var("BUYER_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:8083".to_string()),
+        }
+    }
+}
+
+pub struct RunResult {
+    // From the first measured call to the last response
+    pub window: Duration,
+    pub latencies: ApiLatencies,
+    // Open-loop runs only: each step's target rate and what happened in it
+    pub steps: Vec<(Step, StepStats)>,
+    // Item and cart calls, setup included, if the history was recorded
+    pub history: Option<Vec<Entry>>,
+    // Every measured call's outcome, if calls were logged
+    pub calls: Option<CallLog>,
+    // The run's account names and every item registered in it
+    pub sellers: Vec<String>,
+    pub buyers: Vec<String>,
+    pub catalog: Vec<ItemId>,
+}
+
+// Where to send the run's calls and what to collect besides response times
+pub struct RunOptions {
+    pub frontends: Frontends,
+    pub record_history: bool,
+    pub log_calls: bool,
+    // Told when the measured window opens
+    pub measuring: Option<oneshot::Sender<Instant>>,
+}
+
+impl RunResult {
+    pub fn throughput(&self) -> f64 {
+        self.latencies.completed() as f64 / self.window.as_secs_f64()
+    }
+}
+
+// What every client in a run can see
+struct Shared {
+    profile: Profile,
+    started: Instant,
+    // Every item registered in the run, oldest first
+    catalog: RwLock<Vec<ItemId>>,
+    seller_ids: Vec<Uuid>,
+    // The keyword each seller tags its items with, by seller index
+    keywords: Vec<String>,
+    history: Option<Recorder>,
+    calls: Option<CallLog>,
+    frontends: Frontends,
+}
+
+impl Shared {
+    fn keep_going(&self, calls: usize) -> bool {
+        match self.profile.length() {
+            RunLength::Ops(ops) => calls < ops,
+            RunLength::Duration(duration) => self.started.elapsed() < duration,
+        }
+    }
+
+    async fn think(&self, rng: &mut StdRng) {
+        let think_time = self.profile.think_time_ms;
+        if think_time.max > 0 {
+            let millis = rng.gen_range(think_time.min..=think_time.max);
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+        }
+    }
+}
+
+// Weighted choice of the next call
+struct Mix<T> {
+    ops: Vec<T>,
+    weights: WeightedIndex<u32>,
+}
+
+impl<T: Copy> Mix<T> {
+    fn new(mix: &BTreeMap<T, u32>) -> Self {
+        let (ops, weights): (Vec<T>, Vec<u32>) = mix.iter().filter(|(_, weight)| **weight > 0).map(|(op, weight)| (*op, *weight)).unzip();
+        Mix {
+            ops,
+            weights: WeightedIndex::new(weights).expect("profile validated to have a positive weight"),
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> T {
+        self.ops[self.weights.sample(rng)]
+    }
+}
+
+// Picks the key to act on; under zipf the first keys are the most popular
+fn pick<T: Copy>(keys: KeyDistribution, candidates: &[T], rng: &mut StdRng) -> Option<T> {
+    if candidates.is_empty() {
+        return None;
+    }
+    let index = match keys {
+        KeyDistribution::Uniform => rng.gen_range(0..candidates.len()),
+        KeyDistribution::Zipf { exponent } => match Zipf::new(candidates.len() as u64, exponent) {
+            Ok(zipf) => (zipf.sample(rng) as usize).saturating_sub(1),
+            Err(_) => 0,
+        },
+    };
+    candidates.get(index).copied()
+}
+
+pub fn session_lost(msg: &str) -> bool {
+    msg == "Session expired" || msg == "Session not found"
+}
+
+// Sets up accounts and the seed catalog, then runs every client at once.
+// Only the calls made after setup are measured.
+pub async fn run(profile: &Profile, prefix: &str, options: RunOptions) -> Result<RunResult, Box<dyn std::error::Error + Send + Sync>> {
+    let frontends = options.frontends;
+    let history = options.record_history.then(Recorder::new);
+    let mut rng = StdRng::from_entropy();
+    let mut sellers = Vec::with_capacity(profile.sellers);
+    let mut keywords = Vec::with_capacity(profile.sellers);
+    let mut catalog = Vec::new();
+    for i in 0..profile.sellers {
+        let mut seller = SellerClient::create(&frontends.seller, format!("seller_{}_{}", prefix, i), i, profile).await?;
+        let keyword = format!("k{:07x}", rng.gen_range(0..0x1000_0000u32));
+        for _ in 0..profile.seed_items_per_seller {
+            let request = seller.register_request(&keyword);
+            let sent_at = Instant::now();
+            let response: SellerResponse = send_and_receive(&frontends.seller, &request).await?;
+            if let Some(history) = &history {
+                history.record(&seller.name, sent_at, Instant::now(), history::seller_call(&request, Some(&response)));
+            }
+            if let SellerResponse::RegisterItemForSale(item_id) = response {
+                seller.items.push(item_id);
+                catalog.push(item_id);
+            }
+        }
+        keywords.push(keyword);
+        sellers.push(seller);
+    }
+    let mut buyers = Vec::with_capacity(profile.buyers);
+    for i in 0..profile.buyers {
+        buyers.push(BuyerClient::create(&frontends.buyer, format!("buyer_{}_{}", prefix, i), i, profile).await?);
+    }
+
+    let names = (
+        sellers.iter().map(|seller| seller.name.clone()).collect(),
+        buyers.iter().map(|buyer| buyer.name.clone()).collect(),
+    );
+    let shared = Arc::new(Shared {
+        profile: profile.clone(),
+        started: Instant::now(),
+        catalog: RwLock::new(catalog),
+        seller_ids: sellers.iter().map(|seller| seller.id).collect(),
+        keywords,
+        history,
+        calls: options.log_calls.then(CallLog::default),
+        frontends,
+    });
+    if let Some(measuring) = options.measuring {
+        let _ = measuring.send(shared.started);
+    }
+    let open_loop = match (profile.open_loop, profile.open_loop_steps()) {
+        (Some(open_loop), Some(steps)) => Arc::new(OpenLoopRun::new(
+            open_loop.schedule,
+            steps,
+            sellers.len() + buyers.len(),
+            open_loop.max_in_flight,
+            shared.started,
+        )),
+        _ => return run_closed_loop(shared, sellers, buyers, names).await,
+    };
+
+    let mut handles = Vec::with_capacity(sellers.len() + buyers.len());
+    for seller in sellers {
+        let slot = handles.len();
+        handles.push(task::spawn(run_open(seller, shared.clone(), open_loop.clone(), slot)));
+    }
+    for buyer in buyers {
+        let slot = handles.len();
+        handles.push(task::spawn(run_open(buyer, shared.clone(), open_loop.clone(), slot)));
+    }
+
+    let mut stats = open_loop.new_stats();
+    for handle in handles {
+        for (ours, theirs) in stats.iter_mut().zip(handle.await?) {
+            ours.merge(&theirs);
+        }
+    }
+    let mut latencies = ApiLatencies::default();
+    for step in &stats {
+        latencies.merge(&step.latencies);
+    }
+
+    let steps = open_loop.steps().iter().copied().zip(stats).collect();
+    Ok(finish(shared, latencies, steps, names))
+}
+
+async fn run_closed_loop(
+    shared: Arc<Shared>,
+    sellers: Vec<SellerClient>,
+    buyers: Vec<BuyerClient>,
+    names: (Vec<String>, Vec<String>),
+) -> Result<RunResult, Box<dyn std::error::Error + Send + Sync>> {
+    let mut handles = Vec::with_capacity(sellers.len() + buyers.len());
+    for seller in sellers {
+        handles.push(task::spawn(run_closed(seller, shared.clone())));
+    }
+    for buyer in buyers {
+        handles.push(task::spawn(run_closed(buyer, shared.clone())));
+    }
+
+    let mut latencies = ApiLatencies::default();
+    for handle in handles {
+        latencies.merge(&handle.await?);
+    }
+
+    Ok(finish(shared, latencies, Vec::new(), names))
+}
+
+// Called once every client task has ended
+fn finish(
+    shared: Arc<Shared>,
+    latencies: ApiLatencies,
+    steps: Vec<(Step, StepStats)>,
+    (sellers, buyers): (Vec<String>, Vec<String>),
+) -> RunResult {
+    let window = shared.started.elapsed();
+    let shared = Arc::into_inner(shared).expect("client tasks have ended");
+    RunResult {
+        window,
+        latencies,
+        steps,
+        history: shared.history.as_ref().map(Recorder::take),
+        calls: shared.calls,
+        sellers,
+        buyers,
+        catalog: shared.catalog.into_inner().unwrap_or_else(|e| e.into_inner()),
+    }
+}
+
+// What a request needs to remember for handling its response
+#[derive(Clone, Copy, Default)]
+struct Sent {
+    logout: bool,
+    cart_item: Option<ItemId>,
+}
+
+// A seller or buyer as the run loops drive it
+trait Client: Send + 'static {
+    type Request: serde::Serialize + Send + Sync + 'static;
+    type Response: for<'de> serde::Deserialize<'de> + Send + Sync + 'static;
+
+    fn addr(shared: &Shared) -> &str;
+    fn api(request: &Self::Request) -> &'static str;
+    // The error a response carries, if it is one
+    fn error(response: &Self::Response) -> Option<&str>;
+    // What a call did, for the history; None if it is not recorded
+    fn observe(request: &Self::Request, response: Option<&Self::Response>) -> Option<(Op, history::Outcome)>;
+    fn name(&self) -> &str;
+    // The next call from the client's mix
+    fn next_request(&mut self, shared: &Shared) -> (Self::Request, Sent);
+    fn login_request(&self) -> Self::Request;
+    // Updates the client from a response, None if none came back. Returns
+    // true if the client has to log in again.
+    fn handle(&mut self, shared: &Shared, sent: Sent, response: Option<Self::Response>) -> bool;
+}
+
+// Each call waits for the previous one's response
+async fn run_closed<C: Client>(mut client: C, shared: Arc<Shared>) -> ApiLatencies {
+    let mut rng = StdRng::from_entropy();
+    let mut latencies = ApiLatencies::default();
+    let mut calls = 0;
+
+    while shared.keep_going(calls) {
+        let (request, sent) = client.next_request(&shared);
+        calls += 1;
+        let outcome = send_timed::<C>(C::addr(&shared), request, Instant::now()).await;
+        record(&shared, &client, &mut latencies, &outcome);
+        if client.handle(&shared, sent, outcome.response) {
+            calls += 1;
+            let outcome = send_timed::<C>(C::addr(&shared), client.login_request(), Instant::now()).await;
+            record(&shared, &client, &mut latencies, &outcome);
+            client.handle(&shared, Sent::default(), outcome.response);
+        }
+        shared.think(&mut rng).await;
+    }
+
+    latencies
+}
+
+// Calls go out on the client's schedule whether or not earlier ones have been
+// answered. Responses come back over a channel, so the client's state is only
+// touched from this task.
+async fn run_open<C: Client>(mut client: C, shared: Arc<Shared>, open_loop: Arc<OpenLoopRun>, slot: usize) -> Vec<StepStats> {
+    let mut rng = StdRng::from_entropy();
+    let mut stats = open_loop.new_stats();
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+    let mut next = open_loop.first(slot, &mut rng);
+    let mut in_flight = 0usize;
+    let mut logging_in = false;
+
+    while next.is_some() || in_flight > 0 {
+        let wake = tokio::time::Instant::from_std(next.unwrap_or_else(Instant::now));
+        tokio::select! {
+            _ = tokio::time::sleep_until(wake), if next.is_some() => {
+                let intended = wake.into_std();
+                next = open_loop.after(intended, &mut rng);
+                let step = &mut stats[open_loop.step_of(intended)];
+                let (request, sent) = client.next_request(&shared);
+                step.scheduled += 1;
+                if !open_loop.try_send() {
+                    step.dropped += 1;
+                    step.latencies.record_failure(C::api(&request));
+                    continue;
+                }
+                in_flight += 1;
+                spawn_send::<C>(&done_tx, C::addr(&shared), request, sent, intended, false);
+            }
+            Some(done) = done_rx.recv() => {
+                in_flight -= 1;
+                open_loop.answered();
+                // Logins are not on the schedule, so they stay out of the achieved rate
+                if let (Some(step), Some(_), false) = (open_loop.step_during(Instant::now()), &done.outcome.response, done.login) {
+                    stats[step].completed += 1;
+                }
+                record(&shared, &client, &mut stats[open_loop.step_of(done.intended)].latencies, &done.outcome);
+                if done.login {
+                    logging_in = false;
+                }
+                // One login at a time, however many calls found the session gone
+                if client.handle(&shared, done.sent, done.outcome.response) && !logging_in && open_loop.try_send() {
+                    logging_in = true;
+                    in_flight += 1;
+                    spawn_send::<C>(&done_tx, C::addr(&shared), client.login_request(), Sent::default(), Instant::now(), true);
+                }
+            }
+        }
+    }
+
+    stats
+}
+
+// A finished open-loop call on its way back to its client
+struct Done<C: Client> {
+    intended: Instant,
+    sent: Sent,
+    login: bool,
+    outcome: Outcome<C>,
+}
+
+fn spawn_send<C: Client>(
+    done_tx: &mpsc::UnboundedSender<Done<C>>,
+    addr: &str,
+    request: C::Request,
+    sent: Sent,
+    intended: Instant,
+    login: bool,
+) {
+    let done_tx = done_tx.clone();
+    let addr = addr.to_string();
+    task::spawn(async move {
+        let outcome = send_timed::<C>(&addr, request, intended).await;
+        let _ = done_tx.send(Done { intended, sent, login, outcome });
+    });
+}
+
+struct Outcome<C: Client> {
+    api: &'static str,
+    // From the intended send time to the response
+    elapsed: Duration,
+    // When the call actually went out and came back
+    sent_at: Instant,
+    received_at: Instant,
+    // False if the frontend could not be reached, so the call had no effect
+    connected: bool,
+    request: C::Request,
+    // None if no response came back
+    response: Option<C::Response>,
+}
+
+async fn send_timed<C: Client>(addr: &str, request: C::Request, intended: Instant) -> Outcome<C> {
+    let sent_at = Instant::now();
+    let (connected, response) = match tokio::net::TcpStream::connect(addr).await {
+        Ok(stream) => (true, exchange(stream, &request).await.ok()),
+        Err(_) => (false, None),
+    };
+    Outcome {
+        api: C::api(&request),
+        elapsed: intended.elapsed(),
+        sent_at,
+        received_at: Instant::now(),
+        connected,
+        request,
+        response,
+    }
+}
+
+// Adds a call to the latencies, and to the run's history and call log if
+// they are kept
+fn record<C: Client>(shared: &Shared, client: &C, latencies: &mut ApiLatencies, outcome: &Outcome<C>) {
+    let error = outcome.response.as_ref().and_then(C::error);
+    match &outcome.response {
+        Some(_) => latencies.record(outcome.api, outcome.elapsed, error.is_some()),
+        None => latencies.record_failure(outcome.api),
+    }
+    if let Some(calls) = &shared.calls {
+        let result = match (&outcome.response, error) {
+            (None, _) => CallResult::NoResponse,
+            (Some(_), Some(msg)) => CallResult::Error(msg.to_string()),
+            (Some(_), None) => CallResult::Ok,
+        };
+        calls.record(outcome.sent_at, outcome.api, result);
+    }
+    if let (Some(history), true) = (&shared.history, outcome.connected) {
+        let call = C::observe(&outcome.request, outcome.response.as_ref());
+        history.record(client.name(), outcome.sent_at, outcome.received_at, call);
+    }
+}
+
+struct SellerClient {
+    id: Uuid,
+    name: String,
+    session: Uuid,
+    index: usize,
+    // This seller's items, oldest first
+    items: Vec<ItemId>,
+    mix: Mix<SellerOp>,
+    rng: StdRng,
+}
+
+impl SellerClient {
+    async fn create(addr: &str, name: String, index: usize, profile: &Profile) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let id = match send_and_receive(addr, SellerRequest::CreateAccount {
+            seller_name: name.clone(),
+            password: PASSWORD.to_string(),
+        }).await? {
+            SellerResponse::CreateAccount(id) => id,
+            _ => return Err("Failed to create seller account".into()),
+        };
+        let session = match send_and_receive(addr, SellerRequest::Login {
+            seller_name: name.clone(),
+            password: PASSWORD.to_string(),
+        }).await? {
+            SellerResponse::Login(session) => session,
+            _ => return Err("Failed to login seller".into()),
+        };
+
+        Ok(SellerClient {
+            id,
+            name,
+            session,
+            index,
+            items: Vec::new(),
+            mix: Mix::new(&profile.seller_mix),
+            rng: StdRng::from_entropy(),
+        })
+    }
+
+    fn register_request(&mut self, keyword: &str) -> SellerRequest {
+        SellerRequest::RegisterItemForSale {
+            session_id: self.session,
+            item_name: format!("Item_{}", self.rng.gen_range(0u32..=u32::MAX)),
+            item_category: self.rng.gen_range(1..10),
+            keywords: vec!["test".to_string(), keyword.to_string()],
+            condition: Condition::New,
+            sale_price: self.rng.gen_range(10.0..100.0),
+            quantity: self.rng.gen_range(1..100),
+            draft: false,
+        }
+    }
+}
+
+impl Client for SellerClient {
+    type Request = SellerRequest;
+    type Response = SellerResponse;
+
+    fn addr(shared: &Shared) -> &str {
+        &shared.frontends.seller
+    }
+
+    fn api(request: &SellerRequest) -> &'static str {
+        request.into()
+    }
+
+    fn error(response: &SellerResponse) -> Option<&str> {
+        match response {
+            SellerResponse::Error(msg) => Some(msg),
+            _ => None,
+        }
+    }
+
+    fn observe(request: &SellerRequest, response: Option<&SellerResponse>) -> Option<(Op, history::Outcome)> {
+        history::seller_call(request, response)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_request(&mut self, shared: &Shared) -> (SellerRequest, Sent) {
+        let op = self.mix.sample(&mut self.rng);
+        let item = pick(shared.profile.keys, &self.items, &mut self.rng);
+        let session_id = self.session;
+        let request = match (op, item) {
+            // Logs back in once the logout is answered
+            (SellerOp::Relogin, _) => {
+                return (SellerRequest::Logout { session_id }, Sent { logout: true, ..Sent::default() });
+            }
+            (SellerOp::ChangeItemPrice, Some(item_id)) => SellerRequest::ChangeItemPrice {
+                session_id,
+                item_id,
+                new_price: self.rng.gen_range(10.0..100.0),
+            },
+            (SellerOp::UpdateUnitsForSale, Some(item_id)) => SellerRequest::UpdateUnitsForSale {
+                session_id,
+                item_id,
+                update: UnitsUpdate::SetTo(self.rng.gen_range(1..100)),
+            },
+            (SellerOp::DisplayItemsForSale, _) => SellerRequest::DisplayItemsForSale { session_id },
+            (SellerOp::GetSellerRating, _) => SellerRequest::GetSellerRating { session_id },
+            // Nothing to manage until the first item is registered
+            (SellerOp::RegisterItemForSale, _) | (_, None) => {
+                let keyword = &shared.keywords[self.index];
+                self.register_request(keyword)
+            }
+        };
+        (request, Sent::default())
+    }
+
+    fn login_request(&self) -> SellerRequest {
+        SellerRequest::Login {
+            seller_name: self.name.clone(),
+            password: PASSWORD.to_string(),
+        }
+    }
+
+    fn handle(&mut self, shared: &Shared, sent: Sent, response: Option<SellerResponse>) -> bool {
+        match response {
+            Some(SellerResponse::RegisterItemForSale(item_id)) => {
+                self.items.push(item_id);
+                shared.catalog.write().unwrap_or_else(|e| e.into_inner()).push(item_id);
+            }
+            Some(SellerResponse::Login(session)) => self.session = session,
+            Some(SellerResponse::Error(msg)) if session_lost(&msg) => return true,
+            _ => {}
+        }
+        sent.logout
+    }
+}
+
+struct BuyerClient {
+    name: String,
+    session: Uuid,
+    index: usize,
+    // Units added to the cart, one entry per unit
+    cart: Vec<ItemId>,
+    mix: Mix<BuyerOp>,
+    rng: StdRng,
+}
+
+impl BuyerClient {
+    async fn create(addr: &str, name: String, index: usize, profile: &Profile) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match send_and_receive(addr, BuyerRequest::CreateAccount {
+            buyer_name: name.clone(),
+            password: PASSWORD.to_string(),
+        }).await? {
+            BuyerResponse::CreateAccount(_) => {}
+            _ => return Err("Failed to create buyer account".into()),
+        }
+        let session = match send_and_receive(addr, BuyerRequest::Login {
+            buyer_name: name.clone(),
+            password: PASSWORD.to_string(),
+        }).await? {
+            BuyerResponse::Login(session) => session,
+            _ => return Err("Failed to login buyer".into()),
+        };
+
+        Ok(BuyerClient {
+            name,
+            session,
+            index,
+            cart: Vec::new(),
+            mix: Mix::new(&profile.buyer_mix),
+            rng: StdRng::from_entropy(),
+        })
+    }
+}
+
+impl Client for BuyerClient {
+    type Request = BuyerRequest;
+    type Response = BuyerResponse;
+
+    fn addr(shared: &Shared) -> &str {
+        &shared.frontends.buyer
+    }
+
+    fn api(request: &BuyerRequest) -> &'static str {
+        request.into()
+    }
+
+    fn error(response: &BuyerResponse) -> Option<&str> {
+        match response {
+            BuyerResponse::Error(msg) => Some(msg),
+            _ => None,
+        }
+    }
+
+    fn observe(request: &BuyerRequest, response: Option<&BuyerResponse>) -> Option<(Op, history::Outcome)> {
+        history::buyer_call(request, response)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_request(&mut self, shared: &Shared) -> (BuyerRequest, Sent) {
+        let op = self.mix.sample(&mut self.rng);
+        let item = {
+            let catalog = shared.catalog.read().unwrap_or_else(|e| e.into_inner());
+            pick(shared.profile.keys, &catalog, &mut self.rng)
+        };
+        // Searches go to one seller's items so results stay small
+        let seller = self.index % shared.keywords.len();
+        let session_id = self.session;
+        let request = match (op, item) {
+            // Logs back in once the logout is answered
+            (BuyerOp::Relogin, _) => {
+                return (BuyerRequest::Logout { session_id }, Sent { logout: true, ..Sent::default() });
+            }
+            (BuyerOp::GetItem, Some(item_id)) => BuyerRequest::GetItem { session_id, item_id },
+            (BuyerOp::AddItemToCart, Some(item_id)) => BuyerRequest::AddItemToCart { session_id, item_id, quantity: 1 },
+            (BuyerOp::RemoveItemFromCart, _) if !self.cart.is_empty() => {
+                let index = self.rng.gen_range(0..self.cart.len());
+                BuyerRequest::RemoveItemFromCart {
+                    session_id,
+                    item_id: self.cart[index],
+                    quantity: 1,
+                }
+            }
+            // With an empty cart there is nothing to remove, so fill it instead
+            (BuyerOp::RemoveItemFromCart, Some(item_id)) => BuyerRequest::AddItemToCart { session_id, item_id, quantity: 1 },
+            (BuyerOp::DisplayCart, _) => BuyerRequest::DisplayCart { session_id },
+            (BuyerOp::SaveCart, _) => BuyerRequest::SaveCart { session_id },
+            (BuyerOp::ClearCart, _) => BuyerRequest::ClearCart { session_id },
+            (BuyerOp::ProvideFeedback, Some(item_id)) => BuyerRequest::ProvideFeedback {
+                session_id,
+                item_id,
+                thumbs_up: self.rng.gen_bool(0.8),
+            },
+            (BuyerOp::GetSellerRating, _) => BuyerRequest::GetSellerRating {
+                session_id,
+                seller_id: shared.seller_ids[self.rng.gen_range(0..shared.seller_ids.len())],
+            },
+            (BuyerOp::GetBuyerPurchases, _) => BuyerRequest::GetBuyerPurchases { session_id },
+            // Search whenever the catalog is still empty
+            (BuyerOp::SearchItemsForSale, _) | (_, None) => BuyerRequest::SearchItemsForSale {
+                session_id,
+                category: None,
+                keywords: vec![shared.keywords[seller].clone()],
+                match_mode: KeywordMatch::Exact,
+                filters: SearchFilters::default(),
+                sort: SortOrder::Relevance,
+            },
+        };
+
+        let cart_item = match &request {
+            BuyerRequest::AddItemToCart { item_id, .. } | BuyerRequest::RemoveItemFromCart { item_id, .. } => Some(*item_id),
+            _ => None,
+        };
+        (request, Sent { cart_item, ..Sent::default() })
+    }
+
+    fn login_request(&self) -> BuyerRequest {
+        BuyerRequest::Login {
+            buyer_name: self.name.clone(),
+            password: PASSWORD.to_string(),
+        }
+    }
+
+    fn handle(&mut self, _shared: &Shared, sent: Sent, response: Option<BuyerResponse>) -> bool {
+        match response {
+            Some(BuyerResponse::AddItemToCart) => self.cart.extend(sent.cart_item),
+            Some(BuyerResponse::RemoveItemFromCart) => {
+                if let Some(item_id) = sent.cart_item {
+                    if let Some(index) = self.cart.iter().position(|id| *id == item_id) {
+                        self.cart.swap_remove(index);
+                    }
+                }
+            }
+            Some(BuyerResponse::ClearCart) => self.cart.clear(),
+            Some(BuyerResponse::Login(session)) => self.session = session,
+            Some(BuyerResponse::Error(msg)) if session_lost(&msg) => return true,
+            _ => {}
+        }
+        sent.logout
+    }
+}
+
+pub async fn send_and_receive<T, U>(addr: &str, request: T) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    exchange(tokio::net::TcpStream::connect(addr).await?, request).await
+}
+
+async fn exchange<T, U>(mut stream: tokio::net::TcpStream, request: T) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let request_str = serde_json::to_string(&request)?;
+    stream.write_all(request_str.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+
+    let mut response_str = String::new();
+    let (reader, _) = stream.split();
+    let mut buf_reader = BufReader::new(reader);
+    buf_reader.read_line(&mut response_str).await?;
+
+    let response: U = serde_json::from_str(response_str.trim())?;
+    Ok(response)
+}
@@ -0,0 +1,96 @@
+// Compares a linear keyword scan against SearchItems, which looks keywords up
+// in the inverted index, on a large synthetic catalog.
+//
+//   cargo run --release -p product_db --example search_scale -- [items] [queries]
+
+use common::*;
+use product_db::Store;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const VOCABULARY: usize = 10_000;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let num_items: usize = args.next().and_then(|a| a.parse().ok()).unwrap_or(1_000_000);
+    let num_queries: usize = args.next().and_then(|a| a.parse().ok()).unwrap_or(100);
+    
+    let mut rng = StdRng::seed_from_u64(42);
+    let vocabulary: Vec<String> = (0..VOCABULARY).map(|i| format!("kw{}", i)).collect();
+    
+    let store = Store::open(None).expect("an in-memory store always opens");
+    // Every item's keywords, for the scan
+    let mut catalog: Vec<Vec<String>> = Vec::with_capacity(num_items);
+    
+    let start = Instant::now();
+    for _ in 0..num_items {
+        let item_category = rng.gen_range(1..10);
+        let num_keywords = rng.gen_range(1..=5);
+        let keywords: Vec<String> = (0..num_keywords)
+            .map(|_| vocabulary[rng.gen_range(0..VOCABULARY)].clone())
+            .collect();
+        let item = Item {
+            item_id: ItemId::default(),
+            item_name: "item".to_string(),
+            item_category,
+            keywords: keywords.clone(),
+            condition: Condition::New,
+            sale_price: 1.0,
+            quantity: 1,
+            feedback: Feedback { thumbs_up: 0, thumbs_down: 0 },
+            seller_id: Uuid::nil(),
+            listed_at: 0,
+            version: 0,
+            status: ItemStatus::Active,
+        };
+        match store.handle(ProductDbRequest::CreateItem { item }).await {
+            ProductDbResponse::ItemCreated(_) => catalog.push(keywords),
+            other => panic!("CreateItem failed: {:?}", other),
+        }
+    }
+    println!("Built catalog of {} items in {:?}", num_items, start.elapsed());
+    
+    for query_len in [1, 2] {
+        let queries: Vec<Vec<String>> = (0..num_queries)
+            .map(|_| (0..query_len).map(|_| vocabulary[rng.gen_range(0..VOCABULARY)].clone()).collect())
+            .collect();
+        
+        let mut scan_time = Duration::ZERO;
+        let mut index_time = Duration::ZERO;
+        for keywords in &queries {
+            let start = Instant::now();
+            let scanned = catalog.iter()
+                .filter(|item_keywords| keywords.iter().any(|kw| item_keywords.contains(kw)))
+                .count();
+            scan_time += start.elapsed();
+            
+            let start = Instant::now();
+            let indexed = match store.handle(ProductDbRequest::SearchItems {
+                category: None,
+                keywords: keywords.clone(),
+                match_mode: KeywordMatch::Exact,
+                filters: SearchFilters::default(),
+                sort: SortOrder::Relevance,
+            }).await {
+                ProductDbResponse::SearchResults(results) => results.len(),
+                other => panic!("SearchItems failed: {:?}", other),
+            };
+            index_time += start.elapsed();
+            
+            assert_eq!(scanned, indexed);
+        }
+        
+        let scan_avg = scan_time / num_queries as u32;
+        let index_avg = index_time / num_queries as u32;
+        println!(
+            "{}-keyword queries: scan {:?}/query, SearchItems {:?}/query ({:.0}x)",
+            query_len,
+            scan_avg,
+            index_avg,
+            scan_avg.as_secs_f64() / index_avg.as_secs_f64().max(1e-9),
+        );
+    }
+}
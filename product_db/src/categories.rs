@@ -0,0 +1,205 @@
+use crate::state_file;
+use common::Category;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{RwLock, RwLockReadGuard};
+
+// Top-level categories keep the ids items were already registered under
+const DEFAULT_CATEGORIES: &[(i32, &str, Option<i32>)] = &[
+    (1, "Electronics", None),
+    (2, "Home & Garden", None),
+    (3, "Fashion", None),
+    (4, "Books", None),
+    (5, "Sports", None),
+    (6, "Toys", None),
+    (7, "Automotive", None),
+    (8, "Health & Beauty", None),
+    (9, "Collectibles", None),
+    (10, "Computers", Some(1)),
+    (11, "Phones", Some(1)),
+    (12, "Audio", Some(1)),
+    (13, "Furniture", Some(2)),
+    (14, "Kitchen", Some(2)),
+    (15, "Clothing", Some(3)),
+    (16, "Shoes", Some(3)),
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Tree {
+    // Ids are never handed out twice, even once their category is deleted,
+    // since item ids and client caches still carry them
+    next_id: i32,
+    categories: BTreeMap<i32, Category>,
+}
+
+// Category tree. Structural changes are rare admin operations, so a single
+// lock keeps parent links acyclic without per-entry coordination. Every change
+// is saved to the state file before it takes effect, like the item id
+// reservations, so a restart keeps the tree and its id counter.
+pub struct Taxonomy {
+    path: Option<PathBuf>,
+    tree: RwLock<Tree>,
+}
+
+impl Taxonomy {
+    // Resumes from the tree saved in `path`, or starts from the default
+    // categories; with no path changes only last until restart
+    pub fn open(path: Option<PathBuf>) -> io::Result<Self> {
+        let saved = match &path {
+            Some(path) => state_file::load(path)?,
+            None => None,
+        };
+        let tree = saved.unwrap_or_else(|| {
+            let categories: BTreeMap<i32, Category> = DEFAULT_CATEGORIES.iter()
+                .map(|&(category_id, name, parent_id)| {
+                    (category_id, Category { category_id, name: name.to_string(), parent_id })
+                })
+                .collect();
+            let next_id = categories.keys().next_back().map_or(1, |id| id + 1);
+            Tree { next_id, categories }
+        });
+        Ok(Taxonomy { path, tree: RwLock::new(tree) })
+    }
+    
+    pub fn list(&self) -> Vec<Category> {
+        self.tree.read().unwrap().categories.values().cloned().collect()
+    }
+    
+    // Holds off deletes until the returned view is dropped. Item writes keep it
+    // from the category check until the item is indexed, so an item is never
+    // filed under a category deleted in between.
+    pub fn read(&self) -> CategoriesView<'_> {
+        CategoriesView(self.tree.read().unwrap())
+    }
+    
+    // The category and every category below it
+    pub fn with_descendants(&self, category_id: i32) -> HashSet<i32> {
+        let categories = &self.tree.read().unwrap().categories;
+        let mut found = HashSet::from([category_id]);
+        let mut frontier = vec![category_id];
+        while let Some(parent) = frontier.pop() {
+            for child in categories.values().filter(|c| c.parent_id == Some(parent)) {
+                if found.insert(child.category_id) {
+                    frontier.push(child.category_id);
+                }
+            }
+        }
+        found
+    }
+    
+    pub fn create(&self, name: String, parent_id: Option<i32>) -> Result<i32, String> {
+        let mut tree = self.tree.write().unwrap();
+        let name = validate_name(&tree.categories, &name, parent_id, None)?;
+        let category_id = tree.next_id;
+        let mut changed = tree.clone();
+        changed.next_id += 1;
+        changed.categories.insert(category_id, Category { category_id, name, parent_id });
+        self.commit(&mut tree, changed)?;
+        Ok(category_id)
+    }
+    
+    pub fn rename(&self, category_id: i32, name: String) -> Result<(), String> {
+        let mut tree = self.tree.write().unwrap();
+        let parent_id = match tree.categories.get(&category_id) {
+            Some(category) => category.parent_id,
+            None => return Err("Category not found".to_string()),
+        };
+        let name = validate_name(&tree.categories, &name, parent_id, Some(category_id))?;
+        let mut changed = tree.clone();
+        if let Some(category) = changed.categories.get_mut(&category_id) {
+            category.name = name;
+        }
+        self.commit(&mut tree, changed)
+    }
+    
+    pub fn reparent(&self, category_id: i32, parent_id: Option<i32>) -> Result<(), String> {
+        let mut tree = self.tree.write().unwrap();
+        let categories = &tree.categories;
+        let name = match categories.get(&category_id) {
+            Some(category) => category.name.clone(),
+            None => return Err("Category not found".to_string()),
+        };
+        
+        // Walk up from the new parent; reaching the category itself means a cycle
+        let mut ancestor = parent_id;
+        while let Some(id) = ancestor {
+            if id == category_id {
+                return Err("A category cannot be moved below itself".to_string());
+            }
+            ancestor = categories.get(&id).and_then(|c| c.parent_id);
+        }
+        
+        validate_name(categories, &name, parent_id, Some(category_id))?;
+        let mut changed = tree.clone();
+        if let Some(category) = changed.categories.get_mut(&category_id) {
+            category.parent_id = parent_id;
+        }
+        self.commit(&mut tree, changed)
+    }
+    
+    // `has_items` reports whether any item is still filed under the category.
+    // It runs under the write lock, so no item can be filed there until the
+    // category is gone.
+    pub fn delete(&self, category_id: i32, has_items: impl Fn(i32) -> bool) -> Result<(), String> {
+        let mut tree = self.tree.write().unwrap();
+        if !tree.categories.contains_key(&category_id) {
+            return Err("Category not found".to_string());
+        }
+        if tree.categories.values().any(|c| c.parent_id == Some(category_id)) {
+            return Err("Category has subcategories".to_string());
+        }
+        if has_items(category_id) {
+            return Err("Category still has items".to_string());
+        }
+        let mut changed = tree.clone();
+        changed.categories.remove(&category_id);
+        self.commit(&mut tree, changed)
+    }
+    
+    // Saves `changed`, then makes it the current tree. A failed save leaves
+    // the tree as it was.
+    fn commit(&self, tree: &mut Tree, changed: Tree) -> Result<(), String> {
+        if let Some(path) = &self.path {
+            state_file::save(path, &changed).map_err(|e| format!("Failed to save categories: {}", e))?;
+        }
+        *tree = changed;
+        Ok(())
+    }
+}
+
+pub struct CategoriesView<'a>(RwLockReadGuard<'a, Tree>);
+
+impl CategoriesView<'_> {
+    pub fn exists(&self, category_id: i32) -> bool {
+        self.0.categories.contains_key(&category_id)
+    }
+}
+
+// Names are trimmed, non-empty and unique among siblings (case-insensitive)
+fn validate_name(
+    categories: &BTreeMap<i32, Category>,
+    name: &str,
+    parent_id: Option<i32>,
+    exclude: Option<i32>,
+) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Category name cannot be empty".to_string());
+    }
+    if let Some(parent) = parent_id {
+        if !categories.contains_key(&parent) {
+            return Err("Parent category not found".to_string());
+        }
+    }
+    let duplicate = categories.values().any(|c| {
+        Some(c.category_id) != exclude
+            && c.parent_id == parent_id
+            && c.name.eq_ignore_ascii_case(name)
+    });
+    if duplicate {
+        return Err(format!("Category '{}' already exists here", name));
+    }
+    Ok(name.to_string())
+}
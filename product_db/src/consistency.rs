@@ -0,0 +1,62 @@
+// Index consistency check: recomputes every secondary index from the items
+// and repairs the live indexes in place, counting what it had to fix. Entries
+// are re-checked against the live item before they are changed, so requests
+// served during the check are not undone. On a busy store the report can
+// still include drift caused by changes in flight.
+
+use crate::Indexes;
+use common::{IndexDrift, IndexReport, Item, ItemId};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub fn verify_and_repair(items: &DashMap<ItemId, Item>, indexes: &Indexes) -> IndexReport {
+    IndexReport {
+        items_checked: items.len(),
+        seller_index: repair_list_index(items, &indexes.seller_items, |item| item.seller_id),
+        category_index: repair_list_index(items, &indexes.category_items, |item| item.item_category),
+        text_index: indexes.text.repair(items),
+    }
+}
+
+fn repair_list_index<K: Eq + Hash + Copy>(
+    items: &DashMap<ItemId, Item>,
+    index: &DashMap<K, Vec<ItemId>>,
+    key_of: impl Fn(&Item) -> K,
+) -> IndexDrift {
+    let mut drift = IndexDrift::default();
+    
+    for item in items.iter() {
+        let mut list = index.entry(key_of(&item)).or_default();
+        if !list.contains(&item.item_id) {
+            list.push(item.item_id);
+            drift.missing += 1;
+        }
+    }
+    
+    let keys: Vec<K> = index.iter().map(|entry| *entry.key()).collect();
+    for key in keys {
+        let listed: Vec<ItemId> = match index.get(&key) {
+            Some(list) => list.clone(),
+            None => continue,
+        };
+        
+        let mut seen: HashMap<ItemId, usize> = HashMap::new();
+        for item_id in listed {
+            let occurrence = seen.entry(item_id).or_insert(0);
+            *occurrence += 1;
+            let belongs = items.get(&item_id).is_some_and(|item| key_of(&item) == key);
+            if !belongs || *occurrence > 1 {
+                if let Some(mut list) = index.get_mut(&key) {
+                    if let Some(position) = list.iter().rposition(|id| *id == item_id) {
+                        list.remove(position);
+                        drift.stale += 1;
+                    }
+                }
+            }
+        }
+        index.remove_if(&key, |_, list| list.is_empty());
+    }
+    
+    drift
+}
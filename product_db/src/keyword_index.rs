@@ -0,0 +1,67 @@
+use common::ItemId;
+use dashmap::DashMap;
+use std::collections::HashSet;
+
+// Inverted index: term -> ids of the items containing that term
+#[derive(Default)]
+pub struct KeywordIndex {
+    postings: DashMap<String, HashSet<ItemId>>,
+}
+
+impl KeywordIndex {
+    pub fn insert(&self, item_id: ItemId, terms: &[String]) {
+        for term in terms {
+            self.postings.entry(term.clone())
+                .or_default()
+                .insert(item_id);
+        }
+    }
+    
+    pub fn remove(&self, item_id: ItemId, terms: &[String]) {
+        for term in terms {
+            if let Some(mut posting) = self.postings.get_mut(term) {
+                posting.remove(&item_id);
+            }
+            // Drop empty postings so removed terms don't accumulate
+            self.postings.remove_if(term, |_, posting| posting.is_empty());
+        }
+    }
+    
+    // Number of items containing the term (its document frequency)
+    pub fn frequency(&self, term: &str) -> usize {
+        self.postings.get(term).map(|posting| posting.len()).unwrap_or(0)
+    }
+    
+    pub fn contains(&self, term: &str, item_id: ItemId) -> bool {
+        self.postings.get(term).is_some_and(|posting| posting.contains(&item_id))
+    }
+    
+    // Snapshot of every (term, item) entry
+    pub fn entries(&self) -> Vec<(String, ItemId)> {
+        self.postings.iter()
+            .flat_map(|entry| {
+                let term = entry.key().clone();
+                entry.value().iter().map(move |item_id| (term.clone(), *item_id)).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+    
+    pub fn for_each_item(&self, term: &str, mut f: impl FnMut(ItemId)) {
+        if let Some(posting) = self.postings.get(term) {
+            for item_id in posting.iter() {
+                f(*item_id);
+            }
+        }
+    }
+    
+    // Indexed terms accepted by the predicate, used to expand prefix and fuzzy
+    // queries. This visits every distinct term, so it costs time in the size
+    // of the vocabulary rather than the catalog: the terms are not kept in an
+    // order a prefix range or edit distance could be looked up in.
+    pub fn terms_where(&self, mut predicate: impl FnMut(&str) -> bool) -> Vec<String> {
+        self.postings.iter()
+            .filter(|entry| predicate(entry.key()))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+}
@@ -0,0 +1,728 @@
+// The product database: items, carts, feedback and categories, kept in
+// memory with secondary indexes for search. `serve` runs the server loop on a
+// listener the caller has bound, so the binary and in-process tests start it
+// the same way.
+
+mod categories;
+mod consistency;
+mod keyword_index;
+mod lifecycle;
+mod search;
+mod sequences;
+mod state_file;
+
+use categories::{CategoriesView, Taxonomy};
+use chrono::Utc;
+use common::metrics::{self, ErrorKind};
+use common::{status, telemetry};
+use common::*;
+use dashmap::DashMap;
+use search::SearchIndex;
+use sequences::SequenceAllocator;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+// Listing limits from the spec
+const MAX_NAME_LEN: usize = 32;
+const MAX_KEYWORDS: usize = 5;
+const MAX_KEYWORD_LEN: usize = 8;
+
+// Secondary indexes for faster search
+#[derive(Default)]
+struct Indexes {
+    seller_items: DashMap<Uuid, Vec<ItemId>>,
+    category_items: DashMap<i32, Vec<ItemId>>,
+    // Keyword and item name terms
+    text: SearchIndex,
+}
+
+// All product DB state, shared by every connection
+pub struct Store {
+    // In-memory storage
+    items: DashMap<ItemId, Item>,
+    carts: DashMap<Uuid, Vec<CartItem>>,
+    purchase_history: DashMap<Uuid, Vec<ItemId>>,
+    // (buyer_id, item_id) -> thumbs_up
+    feedback_votes: DashMap<(Uuid, ItemId), bool>,
+    // Each seller's rating: the votes on all their items, changed together
+    // with the item's counters
+    seller_feedback: DashMap<Uuid, Feedback>,
+    // Units of each item held in buyers' carts, kept in step with `carts`
+    held_in_carts: DashMap<ItemId, i32>,
+    indexes: Indexes,
+    taxonomy: Taxonomy,
+    item_ids: SequenceAllocator,
+}
+
+impl Store {
+    // No items, and the categories and item id reservations saved in
+    // `data_dir` or the default categories. With a data directory category
+    // and item ids stay unique across restarts; without one nothing is saved.
+    pub fn open(data_dir: Option<PathBuf>) -> std::io::Result<Self> {
+        if let Some(dir) = &data_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let state_file = |name: &str| data_dir.as_ref().map(|dir| dir.join(name));
+        Ok(Store {
+            items: DashMap::new(),
+            carts: DashMap::new(),
+            purchase_history: DashMap::new(),
+            feedback_votes: DashMap::new(),
+            seller_feedback: DashMap::new(),
+            held_in_carts: DashMap::new(),
+            indexes: Indexes::default(),
+            taxonomy: Taxonomy::open(state_file("product_db_categories.json"))?,
+            item_ids: SequenceAllocator::open(state_file("product_db_sequences.json"))?,
+        })
+    }
+    
+    // Carries out one request directly, without a connection, e.g. for
+    // benchmarks that would otherwise measure the network
+    pub async fn handle(&self, request: ProductDbRequest) -> ProductDbResponse {
+        handle_request(request, self).await
+    }
+
+    // Publishes the store sizes as metrics
+    pub fn record_entries(&self) {
+        metrics::set_store_entries("items", self.items.len());
+        metrics::set_store_entries("carts", self.carts.len());
+        metrics::set_store_entries("purchase_history", self.purchase_history.len());
+        metrics::set_store_entries("feedback_votes", self.feedback_votes.len());
+    }
+
+    // Indexes the item under another seller, category, name and keywords than
+    // it has, the drift `VerifyIndexes` exists to find. Returns false if there
+    // is no such item.
+    #[cfg(feature = "test-hooks")]
+    pub fn misindex(&self, item_id: ItemId) -> bool {
+        let Some(item) = self.items.get(&item_id) else {
+            return false;
+        };
+        let drifted = Item {
+            seller_id: Uuid::new_v4(),
+            item_category: item.item_category + 1,
+            item_name: format!("not{}", item.item_name),
+            keywords: item.keywords.iter().map(|kw| format!("not{}", kw)).collect(),
+            ..item.clone()
+        };
+        reindex(&self.indexes, &item, &drifted);
+        true
+    }
+}
+
+// Serves requests from `listener` until accepting a connection fails
+pub async fn serve(listener: TcpListener, store: Arc<Store>) -> std::io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let store = store.clone();
+        
+        tokio::spawn(async move {
+            handle_connection(socket, store).await;
+        });
+    }
+}
+
+async fn handle_connection(socket: TcpStream, store: Arc<Store>) {
+    let _connection = status::connection_opened();
+    let (read_half, mut write_half) = socket.into_split();
+    let reader = BufReader::new(read_half);
+    let mut lines = reader.lines();
+    
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Envelope { trace_id, parent_span_id, request } = match serde_json::from_str::<Envelope<ProductDbRequest>>(&line) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!(error = %e, "invalid request");
+                metrics::count_error(ErrorKind::InvalidRequest, "unknown");
+                let response = ProductDbResponse::Error(format!("Invalid request: {}", e));
+                let _ = send_response(&mut write_half, response).await;
+                continue;
+            }
+        };
+        
+        let name: &'static str = (&request).into();
+        let (span, trace_id) = telemetry::request_span(name, Some(trace_id), parent_span_id);
+        let response = telemetry::traced(span, trace_id, async {
+            let started = Instant::now();
+            let response = handle_request(request, &store).await;
+            let elapsed = started.elapsed();
+            metrics::observe_request(name, elapsed);
+            if let ProductDbResponse::Error(msg) = &response {
+                metrics::count_error(ErrorKind::Rejected, name);
+                info!(error = %msg, elapsed_us = elapsed.as_micros() as u64, "request rejected");
+            } else {
+                debug!(elapsed_us = elapsed.as_micros() as u64, "request handled");
+            }
+            response
+        }).await;
+        if let Err(e) = send_response(&mut write_half, response).await {
+            warn!(%trace_id, request = name, error = %e, "failed to send response");
+            metrics::count_error(ErrorKind::Connection, name);
+        }
+    }
+}
+
+async fn handle_request(request: ProductDbRequest, store: &Store) -> ProductDbResponse {
+    let Store {
+        items,
+        carts,
+        purchase_history,
+        feedback_votes,
+        seller_feedback,
+        held_in_carts,
+        indexes,
+        taxonomy,
+        item_ids,
+    } = store;
+    
+    match request {
+        ProductDbRequest::CreateItem { mut item } => {
+            // Held until the item is indexed, see `Taxonomy::read`
+            let categories = taxonomy.read();
+            if let Err(msg) = validate_item(&item, &categories) {
+                return ProductDbResponse::Error(msg);
+            }
+            
+            let item_id = match item_ids.next(item.item_category) {
+                Ok(item_id) => item_id,
+                Err(e) => return ProductDbResponse::Error(format!("Failed to allocate item ID: {}", e)),
+            };
+            item.item_id = item_id;
+            item.listed_at = Utc::now().timestamp();
+            item.version = 1;
+            if item.status != ItemStatus::Draft {
+                item.status = lifecycle::settle(ItemStatus::Active, item.quantity);
+            }
+            
+            // Insert item
+            items.insert(item_id, item.clone());
+            
+            // Update indexes
+            indexes.seller_items.entry(item.seller_id)
+                .or_default()
+                .push(item_id);
+            
+            indexes.category_items.entry(item.item_category)
+                .or_default()
+                .push(item_id);
+            
+            indexes.text.insert(&item);
+            
+            ProductDbResponse::ItemCreated(item_id)
+        }
+        
+        ProductDbRequest::UpdateItem { item_id, seller_id, patch, expected_version } => {
+            let categories = taxonomy.read();
+            mutate_item(items, indexes, item_id, seller_id, Some(expected_version), |item| {
+                apply_patch(item, patch, held_in_carts, &categories)
+            })
+        }
+        
+        ProductDbRequest::SetPrice { item_id, seller_id, price } => {
+            if !price.is_finite() || price < 0.0 {
+                return ProductDbResponse::Error("Price must be a non-negative number".to_string());
+            }
+            mutate_item(items, indexes, item_id, seller_id, None, |item| {
+                item.sale_price = price;
+                Ok(())
+            })
+        }
+        
+        ProductDbRequest::AdjustQuantity { item_id, seller_id, delta, min_result } => {
+            // AddToCart holds the item while it updates the cart, so the held
+            // count cannot grow between this check and the write
+            mutate_item(items, indexes, item_id, seller_id, None, |item| {
+                let quantity = match item.quantity.checked_add(delta) {
+                    Some(quantity) if quantity >= min_result => quantity,
+                    _ => return Err(format!(
+                        "Quantity {} cannot change by {}: would drop below {}",
+                        item.quantity, delta, min_result
+                    )),
+                };
+                let held = held_in_carts.get(&item_id).map(|held| *held).unwrap_or(0);
+                if delta < 0 && quantity < held {
+                    return Err(format!(
+                        "Cannot remove {} units: {} of the {} units are held in buyers' carts",
+                        -delta, held, item.quantity
+                    ));
+                }
+                item.quantity = quantity;
+                Ok(())
+            })
+        }
+        
+        ProductDbRequest::PatchItem { item_id, seller_id, patch } => {
+            let categories = taxonomy.read();
+            mutate_item(items, indexes, item_id, seller_id, None, |item| {
+                apply_patch(item, patch, held_in_carts, &categories)
+            })
+        }
+        
+        ProductDbRequest::ChangeStatus { item_id, seller_id, action } => {
+            let response = mutate_item(items, indexes, item_id, seller_id, None, |item| {
+                item.status = lifecycle::apply(item.status, action, item.quantity)?;
+                Ok(())
+            });
+            if action == StatusAction::Delist && matches!(response, ProductDbResponse::ItemChanged(_)) {
+                drop_from_carts(carts, held_in_carts, item_id);
+            }
+            response
+        }
+        
+        ProductDbRequest::DeleteItem { item_id, seller_id } => {
+            let owned = |_: &ItemId, item: &Item| match seller_id {
+                Some(seller_id) => item.seller_id == seller_id,
+                None => true,
+            };
+            match items.remove_if(&item_id, owned) {
+                Some((_, item)) => {
+                    // Votes and purchase history keep the ID; IDs are never reused
+                    unindex(indexes, &item);
+                    drop_from_carts(carts, held_in_carts, item_id);
+                    ProductDbResponse::ItemDeleted
+                }
+                None if items.contains_key(&item_id) => ProductDbResponse::Error("Not your item".to_string()),
+                None => ProductDbResponse::Error("Item not found".to_string()),
+            }
+        }
+        
+        ProductDbRequest::GetItem { item_id } => {
+            let item = items.get(&item_id).map(|i| i.clone());
+            ProductDbResponse::Item(item)
+        }
+        
+        ProductDbRequest::GetItemsBySeller { seller_id } => {
+            let seller_items_list = indexes.seller_items.get(&seller_id)
+                .map(|list| list.clone())
+                .unwrap_or_default();
+            
+            let mut items_list = Vec::new();
+            for item_id in seller_items_list {
+                if let Some(item) = items.get(&item_id) {
+                    items_list.push(item.clone());
+                }
+            }
+            
+            ProductDbResponse::Items(items_list)
+        }
+        
+        ProductDbRequest::SearchItems { category, keywords, match_mode, filters, sort } => {
+            let mut results = Vec::new();
+            
+            let categories = category.map(|cat| taxonomy.with_descendants(cat));
+            
+            if keywords.iter().any(|kw| !kw.trim().is_empty()) {
+                let scores = indexes.text.search(&keywords, match_mode, items.len());
+                for (item_id, score) in scores {
+                    if let Some(item) = items.get(&item_id) {
+                        if categories.as_ref().is_some_and(|cats| !cats.contains(&item.item_category)) {
+                            continue;
+                        }
+                        if !search::passes_filters(&item, &filters) {
+                            continue;
+                        }
+                        results.push(SearchResult {
+                            item: item.clone(),
+                            matched_keywords: score.matched_keywords,
+                            score: score.weight,
+                        });
+                    }
+                }
+            } else {
+                let mut add_unranked = |item: &Item| {
+                    if search::passes_filters(item, &filters) {
+                        results.push(SearchResult {
+                            item: item.clone(),
+                            matched_keywords: 0,
+                            score: 0.0,
+                        });
+                    }
+                };
+                // The id lists are copied out first: edits lock an item and
+                // then its index lists, so looking items up while holding a
+                // list would lock in the opposite order and could deadlock
+                if let Some(categories) = &categories {
+                    for cat in categories {
+                        let item_ids = indexes.category_items.get(cat)
+                            .map(|ids| ids.clone())
+                            .unwrap_or_default();
+                        for item_id in item_ids {
+                            if let Some(item) = items.get(&item_id) {
+                                add_unranked(&item);
+                            }
+                        }
+                    }
+                } else if let Some(seller_id) = filters.seller_id {
+                    let item_ids = indexes.seller_items.get(&seller_id)
+                        .map(|ids| ids.clone())
+                        .unwrap_or_default();
+                    for item_id in item_ids {
+                        if let Some(item) = items.get(&item_id) {
+                            add_unranked(&item);
+                        }
+                    }
+                } else {
+                    // No category or seller to narrow by: check every item
+                    for item in items.iter() {
+                        add_unranked(&item);
+                    }
+                }
+            }
+            
+            search::sort_results(&mut results, sort);
+            
+            ProductDbResponse::SearchResults(results)
+        }
+        
+        ProductDbRequest::AddToCart { buyer_id, item_id, quantity } => {
+            // A negative quantity would pass the stock check and lower the
+            // held count the stock rules rely on
+            if quantity <= 0 {
+                return ProductDbResponse::Error("Quantity must be positive".to_string());
+            }
+            // The item stays locked until the cart is updated, so a delist
+            // cannot slip in between the status check and the insert
+            if let Some(item) = items.get(&item_id) {
+                if item.status != ItemStatus::Active {
+                    return ProductDbResponse::Error(format!("Item is not available ({})", item.status));
+                }
+                if item.quantity < quantity {
+                    return ProductDbResponse::Error("Insufficient quantity".to_string());
+                }
+                
+                let mut cart = carts.entry(buyer_id).or_default();
+                
+                if let Some(cart_item) = cart.iter_mut().find(|ci| ci.item_id == item_id) {
+                    cart_item.quantity += quantity;
+                } else {
+                    cart.push(CartItem { item_id, quantity });
+                }
+                hold_units(held_in_carts, item_id, quantity);
+                
+                ProductDbResponse::CartSaved
+            } else {
+                ProductDbResponse::Error("Item not found".to_string())
+            }
+        }
+        
+        ProductDbRequest::RemoveFromCart { buyer_id, item_id, quantity } => {
+            if quantity <= 0 {
+                return ProductDbResponse::Error("Quantity must be positive".to_string());
+            }
+            if let Some(mut cart) = carts.get_mut(&buyer_id) {
+                if let Some(index) = cart.iter().position(|ci| ci.item_id == item_id) {
+                    let removed = cart[index].quantity.min(quantity);
+                    if cart[index].quantity <= quantity {
+                        cart.remove(index);
+                    } else {
+                        cart[index].quantity -= quantity;
+                    }
+                    hold_units(held_in_carts, item_id, -removed);
+                }
+            }
+            
+            ProductDbResponse::CartSaved
+        }
+        
+        ProductDbRequest::GetCart { buyer_id } => {
+            let cart = carts.get(&buyer_id)
+                .map(|c| c.clone())
+                .unwrap_or_default();
+            ProductDbResponse::Cart(cart)
+        }
+        
+        ProductDbRequest::SaveCart { buyer_id, cart } => {
+            let mut stored = carts.entry(buyer_id).or_default();
+            for cart_item in stored.iter() {
+                hold_units(held_in_carts, cart_item.item_id, -cart_item.quantity);
+            }
+            for cart_item in cart.iter() {
+                hold_units(held_in_carts, cart_item.item_id, cart_item.quantity);
+            }
+            *stored = cart;
+            ProductDbResponse::CartSaved
+        }
+        
+        ProductDbRequest::ClearCart { buyer_id } => {
+            if let Some((_, cart)) = carts.remove(&buyer_id) {
+                for cart_item in cart {
+                    hold_units(held_in_carts, cart_item.item_id, -cart_item.quantity);
+                }
+            }
+            ProductDbResponse::CartCleared
+        }
+        
+        ProductDbRequest::AddPurchaseHistory { buyer_id, item_id } => {
+            purchase_history.entry(buyer_id)
+                .or_default()
+                .push(item_id);
+            ProductDbResponse::PurchaseRecorded
+        }
+        
+        ProductDbRequest::GetPurchaseHistory { buyer_id } => {
+            let history = purchase_history.get(&buyer_id)
+                .map(|h| h.clone())
+                .unwrap_or_default();
+            ProductDbResponse::PurchaseHistory(history)
+        }
+        
+        ProductDbRequest::RecordFeedback { buyer_id, item_id, vote } => {
+            // Holding the item entry serializes all votes on this item, so the
+            // vote record, the item's counters and the seller's rating always
+            // change together
+            let mut item = match items.get_mut(&item_id) {
+                Some(item) => item,
+                None => return ProductDbResponse::Error("Item not found".to_string()),
+            };
+            
+            if vote.is_some() {
+                let purchased = purchase_history.get(&buyer_id)
+                    .map(|h| h.contains(&item_id))
+                    .unwrap_or(false);
+                if !purchased {
+                    return ProductDbResponse::Error("Only buyers who purchased this item can rate it".to_string());
+                }
+            }
+            
+            let previous = match vote {
+                Some(thumbs_up) => feedback_votes.insert((buyer_id, item_id), thumbs_up),
+                None => feedback_votes.remove(&(buyer_id, item_id)).map(|(_, v)| v),
+            };
+            
+            let (up_delta, down_delta) = Feedback::vote_delta(previous, vote);
+            if up_delta != 0 || down_delta != 0 {
+                item.feedback.thumbs_up += up_delta;
+                item.feedback.thumbs_down += down_delta;
+                item.version += 1;
+                let mut seller = seller_feedback.entry(item.seller_id).or_default();
+                seller.thumbs_up += up_delta;
+                seller.thumbs_down += down_delta;
+            }
+            
+            ProductDbResponse::FeedbackRecorded
+        }
+        
+        ProductDbRequest::GetSellerFeedback { seller_id } => {
+            let feedback = seller_feedback.get(&seller_id)
+                .map(|f| f.clone())
+                .unwrap_or_default();
+            ProductDbResponse::SellerFeedback(feedback)
+        }
+        
+        ProductDbRequest::ListCategories => {
+            ProductDbResponse::Categories(taxonomy.list())
+        }
+        
+        ProductDbRequest::CreateCategory { name, parent_id } => {
+            match taxonomy.create(name, parent_id) {
+                Ok(category_id) => ProductDbResponse::CategoryCreated(category_id),
+                Err(msg) => ProductDbResponse::Error(msg),
+            }
+        }
+        
+        ProductDbRequest::RenameCategory { category_id, name } => {
+            match taxonomy.rename(category_id, name) {
+                Ok(()) => ProductDbResponse::CategoryUpdated,
+                Err(msg) => ProductDbResponse::Error(msg),
+            }
+        }
+        
+        ProductDbRequest::MoveCategory { category_id, parent_id } => {
+            match taxonomy.reparent(category_id, parent_id) {
+                Ok(()) => ProductDbResponse::CategoryUpdated,
+                Err(msg) => ProductDbResponse::Error(msg),
+            }
+        }
+        
+        ProductDbRequest::DeleteCategory { category_id } => {
+            let has_items = |cat: i32| {
+                indexes.category_items.get(&cat).is_some_and(|ids| !ids.is_empty())
+            };
+            match taxonomy.delete(category_id, has_items) {
+                Ok(()) => ProductDbResponse::CategoryDeleted,
+                Err(msg) => ProductDbResponse::Error(msg),
+            }
+        }
+        
+        ProductDbRequest::VerifyIndexes => {
+            ProductDbResponse::IndexReport(consistency::verify_and_repair(items, indexes))
+        }
+        
+        ProductDbRequest::Health => ProductDbResponse::Health,
+        
+        ProductDbRequest::Ready => ProductDbResponse::Ready(Readiness::of(Vec::new())),
+        
+        ProductDbRequest::Stats => {
+            let entries = BTreeMap::from([
+                ("items".to_string(), items.len() as u64),
+                ("carts".to_string(), carts.len() as u64),
+            ]);
+            ProductDbResponse::Stats(status::stats(entries))
+        }
+    }
+}
+
+// Applies a change to one item while holding its entry, so concurrent
+// mutations of the same item never interleave. A change that fails leaves the
+// item untouched, and so does any change when the item is no longer at
+// `expected_version`.
+fn mutate_item(
+    items: &DashMap<ItemId, Item>,
+    indexes: &Indexes,
+    item_id: ItemId,
+    seller_id: Option<Uuid>,
+    expected_version: Option<u64>,
+    change: impl FnOnce(&mut Item) -> Result<(), String>,
+) -> ProductDbResponse {
+    let mut stored = match items.get_mut(&item_id) {
+        Some(stored) => stored,
+        None => return ProductDbResponse::Error("Item not found".to_string()),
+    };
+    if seller_id.is_some_and(|seller_id| stored.seller_id != seller_id) {
+        return ProductDbResponse::Error("Not your item".to_string());
+    }
+    if stored.status == ItemStatus::Delisted {
+        return ProductDbResponse::Error("Item has been delisted".to_string());
+    }
+    if expected_version.is_some_and(|version| stored.version != version) {
+        return ProductDbResponse::VersionConflict(stored.clone());
+    }
+    
+    let mut item = stored.clone();
+    if let Err(msg) = change(&mut item) {
+        return ProductDbResponse::Error(msg);
+    }
+    item.version += 1;
+    item.status = lifecycle::settle(item.status, item.quantity);
+    reindex(indexes, &stored, &item);
+    *stored = item.clone();
+    ProductDbResponse::ItemChanged(item)
+}
+
+// Sets the fields a patch gives, under the listing rules and without cutting
+// stock below the units buyers hold in their carts (as AdjustQuantity does)
+fn apply_patch(
+    item: &mut Item,
+    patch: ItemPatch,
+    held_in_carts: &DashMap<ItemId, i32>,
+    categories: &CategoriesView,
+) -> Result<(), String> {
+    let ItemPatch { item_name, item_category, keywords, condition, sale_price, quantity } = patch;
+    if let Some(item_name) = item_name {
+        item.item_name = item_name;
+    }
+    if let Some(item_category) = item_category {
+        item.item_category = item_category;
+    }
+    if let Some(keywords) = keywords {
+        item.keywords = keywords;
+    }
+    if let Some(condition) = condition {
+        item.condition = condition;
+    }
+    if let Some(sale_price) = sale_price {
+        item.sale_price = sale_price;
+    }
+    if let Some(quantity) = quantity {
+        // A negative quantity is left for validate_item to reject as such
+        let held = held_in_carts.get(&item.item_id).map(|held| *held).unwrap_or(0);
+        if quantity >= 0 && quantity < item.quantity && quantity < held {
+            return Err(format!(
+                "Cannot set quantity to {}: {} of the {} units are held in buyers' carts",
+                quantity, held, item.quantity
+            ));
+        }
+        item.quantity = quantity;
+    }
+    validate_item(item, categories)
+}
+
+// Brings the indexes in line with an item that changed from `old` to `new`
+fn reindex(indexes: &Indexes, old: &Item, new: &Item) {
+    if old.seller_id != new.seller_id {
+        remove_from_list(&indexes.seller_items, old.seller_id, old.item_id);
+        indexes.seller_items.entry(new.seller_id).or_default().push(new.item_id);
+    }
+    if old.item_category != new.item_category {
+        remove_from_list(&indexes.category_items, old.item_category, old.item_id);
+        indexes.category_items.entry(new.item_category).or_default().push(new.item_id);
+    }
+    if old.keywords != new.keywords || old.item_name != new.item_name {
+        indexes.text.remove(old);
+        indexes.text.insert(new);
+    }
+}
+
+// Listing rules every stored item must satisfy, checked on registration and
+// on every edit
+fn validate_item(item: &Item, categories: &CategoriesView) -> Result<(), String> {
+    let name_len = item.item_name.trim().chars().count();
+    if name_len == 0 || name_len > MAX_NAME_LEN {
+        return Err(format!("Item name must be 1 to {} characters", MAX_NAME_LEN));
+    }
+    if item.keywords.len() > MAX_KEYWORDS {
+        return Err(format!("At most {} keywords are allowed", MAX_KEYWORDS));
+    }
+    if item.keywords.iter().any(|k| k.trim().is_empty() || k.chars().count() > MAX_KEYWORD_LEN) {
+        return Err(format!("Keywords must be 1 to {} characters", MAX_KEYWORD_LEN));
+    }
+    if !item.sale_price.is_finite() || item.sale_price < 0.0 {
+        return Err("Price must be a non-negative number".to_string());
+    }
+    if item.quantity < 0 {
+        return Err("Quantity cannot be negative".to_string());
+    }
+    if !categories.exists(item.item_category) {
+        return Err("Unknown category".to_string());
+    }
+    Ok(())
+}
+
+// Removes an item that can no longer be bought from every cart
+fn drop_from_carts(carts: &DashMap<Uuid, Vec<CartItem>>, held_in_carts: &DashMap<ItemId, i32>, item_id: ItemId) {
+    for mut cart in carts.iter_mut() {
+        cart.retain(|cart_item| cart_item.item_id != item_id);
+    }
+    held_in_carts.remove(&item_id);
+}
+
+// Records units entering (positive) or leaving (negative) a cart. Callers
+// hold the cart's entry, so the count always matches the carts.
+fn hold_units(held_in_carts: &DashMap<ItemId, i32>, item_id: ItemId, delta: i32) {
+    if delta == 0 {
+        return;
+    }
+    *held_in_carts.entry(item_id).or_default() += delta;
+    held_in_carts.remove_if(&item_id, |_, held| *held <= 0);
+}
+
+// Drops every index entry for an item that has been removed
+fn unindex(indexes: &Indexes, item: &Item) {
+    remove_from_list(&indexes.seller_items, item.seller_id, item.item_id);
+    remove_from_list(&indexes.category_items, item.item_category, item.item_id);
+    indexes.text.remove(item);
+}
+
+fn remove_from_list<K: Eq + Hash>(index: &DashMap<K, Vec<ItemId>>, key: K, item_id: ItemId) {
+    if let Some(mut list) = index.get_mut(&key) {
+        list.retain(|id| *id != item_id);
+    }
+    index.remove_if(&key, |_, list| list.is_empty());
+}
+
+async fn send_response(
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    response: ProductDbResponse,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response_str = serde_json::to_string(&response)?;
+    writer.write_all(response_str.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
\ No newline at end of file
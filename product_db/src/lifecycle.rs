@@ -0,0 +1,45 @@
+// Listing status transitions.
+//
+//   draft --publish--> active <--pause/resume--> paused
+//                        ^ |
+//      restocked         | | quantity hits 0
+//                        | v
+//                      sold out
+//
+// Any status except delisted can be delisted, and delisting is final. Sold
+// out is never requested directly: `settle` derives it from the quantity
+// after every change, so it follows stock in both directions.
+
+use common::{ItemStatus, StatusAction};
+
+pub fn apply(status: ItemStatus, action: StatusAction, quantity: i32) -> Result<ItemStatus, String> {
+    let next = match (status, action) {
+        (ItemStatus::Delisted, _) => return Err("Item has been delisted".to_string()),
+        (_, StatusAction::Delist) => ItemStatus::Delisted,
+        (ItemStatus::Draft, StatusAction::Publish) => ItemStatus::Active,
+        (ItemStatus::Active | ItemStatus::SoldOut, StatusAction::Pause) => ItemStatus::Paused,
+        (ItemStatus::Paused, StatusAction::Resume) => ItemStatus::Active,
+        (status, action) => {
+            return Err(format!("Cannot {} an item that is {}", verb(action), status));
+        }
+    };
+    Ok(settle(next, quantity))
+}
+
+// Moves listed items between active and sold out to match their stock
+pub fn settle(status: ItemStatus, quantity: i32) -> ItemStatus {
+    match status {
+        ItemStatus::Active if quantity <= 0 => ItemStatus::SoldOut,
+        ItemStatus::SoldOut if quantity > 0 => ItemStatus::Active,
+        status => status,
+    }
+}
+
+fn verb(action: StatusAction) -> &'static str {
+    match action {
+        StatusAction::Publish => "publish",
+        StatusAction::Pause => "pause",
+        StatusAction::Resume => "resume",
+        StatusAction::Delist => "delist",
+    }
+}
@@ -0,0 +1,347 @@
+// Ranked keyword search
+//
+// Query keywords and item text are lowercased. Each query keyword is matched
+// against two fields: the item's keywords and the tokens of its name. An item
+// is a result if it matches at least one query keyword in either field.
+//
+// For every query keyword the item gets the best weight among the terms it
+// matched in each field, summed over the two fields:
+//
+//     weight = idf(term) * field_weight * match_quality
+//     idf(term) = ln(1 + (N - df + 0.5) / (df + 0.5))     (BM25 idf, N = catalog size)
+//
+// field_weight is 1.0 for keywords and 0.5 for name tokens. match_quality is
+// 1.0 for an exact term, 0.75 for a term the keyword is a prefix of, and 0.5
+// for a term one edit away (typos). Prefix terms are only considered in
+// `Prefix` and `Fuzzy` mode, one-edit terms only in `Fuzzy` mode.
+//
+// With the default `Relevance` order, results are sorted by the number of
+// query keywords matched, then by the summed weight. The other sort orders use
+// relevance to break their own ties, and the item id breaks any remaining tie
+// so equal results come back in a stable order.
+//
+// Exact matches are single posting lookups. Prefix and one-edit terms are
+// found by running through every distinct term of the field
+// (`KeywordIndex::terms_where`), so `Prefix` and `Fuzzy` queries cost time in
+// the vocabulary size per query keyword. Listings reuse most of their words,
+// so the vocabulary stays far smaller than the catalog; a sorted term list or
+// a trie would be the next step if it stops doing so.
+
+use crate::keyword_index::KeywordIndex;
+use common::{IndexDrift, Item, ItemId, ItemStatus, KeywordMatch, SearchFilters, SearchResult, SortOrder};
+use dashmap::DashMap;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Extracts the terms one field contributes to the index
+type TermsOf = fn(&Item) -> Vec<String>;
+
+const KEYWORD_WEIGHT: f64 = 1.0;
+const NAME_WEIGHT: f64 = 0.5;
+const PREFIX_QUALITY: f64 = 0.75;
+const FUZZY_QUALITY: f64 = 0.5;
+// Shorter keywords have too many one-edit neighbours to be useful
+const FUZZY_MIN_LEN: usize = 4;
+
+#[derive(Default)]
+pub struct SearchIndex {
+    keywords: KeywordIndex,
+    names: KeywordIndex,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Score {
+    pub matched_keywords: usize,
+    pub weight: f64,
+}
+
+impl SearchIndex {
+    pub fn insert(&self, item: &Item) {
+        self.keywords.insert(item.item_id, &keyword_terms(item));
+        self.names.insert(item.item_id, &name_terms(item));
+    }
+    
+    pub fn remove(&self, item: &Item) {
+        self.keywords.remove(item.item_id, &keyword_terms(item));
+        self.names.remove(item.item_id, &name_terms(item));
+    }
+    
+    // Adds entries missing for any item and drops entries whose item is gone
+    // or no longer has the term. Each stale entry is re-checked against the
+    // live item before removal, so changes made during the check are kept.
+    pub fn repair(&self, items: &DashMap<ItemId, Item>) -> IndexDrift {
+        let mut drift = IndexDrift::default();
+        let fields: [(&KeywordIndex, TermsOf); 2] = [(&self.keywords, keyword_terms), (&self.names, name_terms)];
+        
+        for (field, terms_of) in fields {
+            for item in items.iter() {
+                for term in terms_of(&item) {
+                    if !field.contains(&term, item.item_id) {
+                        field.insert(item.item_id, std::slice::from_ref(&term));
+                        drift.missing += 1;
+                    }
+                }
+            }
+            
+            for (term, item_id) in field.entries() {
+                let current = items.get(&item_id).is_some_and(|item| terms_of(&item).contains(&term));
+                if !current {
+                    field.remove(item_id, std::slice::from_ref(&term));
+                    drift.stale += 1;
+                }
+            }
+        }
+        
+        drift
+    }
+    
+    // Scores every item matching at least one keyword. `catalog_size` is N in the idf.
+    pub fn search(&self, keywords: &[String], mode: KeywordMatch, catalog_size: usize) -> HashMap<ItemId, Score> {
+        let mut query: Vec<String> = keywords.iter()
+            .map(|kw| kw.trim().to_lowercase())
+            .filter(|kw| !kw.is_empty())
+            .collect();
+        query.sort();
+        query.dedup();
+        
+        let mut scores: HashMap<ItemId, Score> = HashMap::new();
+        for keyword in &query {
+            // Best weight per item for this keyword, per field
+            let mut best: HashMap<ItemId, [f64; 2]> = HashMap::new();
+            let fields = [(&self.keywords, KEYWORD_WEIGHT), (&self.names, NAME_WEIGHT)];
+            for (slot, (field, field_weight)) in fields.into_iter().enumerate() {
+                for (term, quality) in expand(field, keyword, mode) {
+                    let weight = idf(field.frequency(&term), catalog_size) * field_weight * quality;
+                    field.for_each_item(&term, |item_id| {
+                        let entry = best.entry(item_id).or_default();
+                        entry[slot] = entry[slot].max(weight);
+                    });
+                }
+            }
+            
+            for (item_id, [keyword_weight, name_weight]) in best {
+                let score = scores.entry(item_id).or_default();
+                score.matched_keywords += 1;
+                score.weight += keyword_weight + name_weight;
+            }
+        }
+        
+        scores
+    }
+}
+
+// Only active listings are searchable; the filters narrow those down further
+pub fn passes_filters(item: &Item, filters: &SearchFilters) -> bool {
+    if item.status != ItemStatus::Active {
+        return false;
+    }
+    if filters.min_price.is_some_and(|min| item.sale_price < min) {
+        return false;
+    }
+    if filters.max_price.is_some_and(|max| item.sale_price > max) {
+        return false;
+    }
+    if filters.condition.is_some_and(|condition| item.condition != condition) {
+        return false;
+    }
+    if filters.seller_id.is_some_and(|seller_id| item.seller_id != seller_id) {
+        return false;
+    }
+    if let Some(min_ratio) = filters.min_feedback_ratio {
+        if !item.feedback.ratio().is_some_and(|ratio| ratio >= min_ratio) {
+            return false;
+        }
+    }
+    if filters.in_stock_only && item.quantity <= 0 {
+        return false;
+    }
+    true
+}
+
+pub fn sort_results(results: &mut [SearchResult], order: SortOrder) {
+    results.sort_by(|a, b| {
+        let primary = match order {
+            SortOrder::Relevance => Ordering::Equal,
+            SortOrder::PriceAscending => a.item.sale_price.total_cmp(&b.item.sale_price),
+            SortOrder::PriceDescending => b.item.sale_price.total_cmp(&a.item.sale_price),
+            SortOrder::Newest => b.item.listed_at.cmp(&a.item.listed_at),
+            SortOrder::BestRated => compare_rating(b, a),
+        };
+        primary
+            .then(b.matched_keywords.cmp(&a.matched_keywords))
+            .then(b.score.total_cmp(&a.score))
+            .then(a.item.item_id.cmp(&b.item.item_id))
+    });
+}
+
+// Unrated items order below every rated one
+fn compare_rating(a: &SearchResult, b: &SearchResult) -> Ordering {
+    let votes = |r: &SearchResult| r.item.feedback.thumbs_up + r.item.feedback.thumbs_down;
+    match (a.item.feedback.ratio(), b.item.feedback.ratio()) {
+        (Some(x), Some(y)) => x.total_cmp(&y).then(votes(a).cmp(&votes(b))),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn keyword_terms(item: &Item) -> Vec<String> {
+    let mut terms: Vec<String> = item.keywords.iter()
+        .map(|kw| kw.trim().to_lowercase())
+        .filter(|kw| !kw.is_empty())
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+fn name_terms(item: &Item) -> Vec<String> {
+    let mut terms: Vec<String> = item.item_name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+// Indexed terms a query keyword matches, with their match quality
+fn expand(field: &KeywordIndex, keyword: &str, mode: KeywordMatch) -> Vec<(String, f64)> {
+    let mut terms = vec![(keyword.to_string(), 1.0)];
+    
+    if matches!(mode, KeywordMatch::Prefix | KeywordMatch::Fuzzy) {
+        for term in field.terms_where(|t| t.len() > keyword.len() && t.starts_with(keyword)) {
+            terms.push((term, PREFIX_QUALITY));
+        }
+    }
+    
+    if mode == KeywordMatch::Fuzzy && keyword.chars().count() >= FUZZY_MIN_LEN {
+        for term in field.terms_where(|t| t != keyword && !t.starts_with(keyword) && within_one_edit(t, keyword)) {
+            terms.push((term, FUZZY_QUALITY));
+        }
+    }
+    
+    terms
+}
+
+fn idf(frequency: usize, catalog_size: usize) -> f64 {
+    let n = catalog_size.max(frequency) as f64;
+    let df = frequency as f64;
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+}
+
+// True if one insertion, deletion or substitution turns `a` into `b`
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (shorter, longer) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+    if longer.len() - shorter.len() > 1 {
+        return false;
+    }
+    
+    let prefix = shorter.iter().zip(longer.iter()).take_while(|(x, y)| x == y).count();
+    if prefix == shorter.len() {
+        return true;
+    }
+    if shorter.len() == longer.len() {
+        shorter[prefix + 1..] == longer[prefix + 1..]
+    } else {
+        shorter[prefix..] == longer[prefix + 1..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Condition, Feedback};
+    use uuid::Uuid;
+
+    fn item(sequence: u64, name: &str, keywords: &[&str]) -> Item {
+        Item {
+            item_id: ItemId { category: 1, sequence },
+            item_name: name.to_string(),
+            item_category: 1,
+            keywords: keywords.iter().map(|kw| kw.to_string()).collect(),
+            condition: Condition::New,
+            sale_price: 10.0,
+            quantity: 1,
+            feedback: Feedback { thumbs_up: 0, thumbs_down: 0 },
+            seller_id: Uuid::nil(),
+            listed_at: 0,
+            version: 1,
+            status: ItemStatus::Active,
+        }
+    }
+
+    // Item sequence numbers in ranked order
+    fn ranked(items: &[Item], keywords: &[&str], mode: KeywordMatch) -> Vec<u64> {
+        let index = SearchIndex::default();
+        for item in items {
+            index.insert(item);
+        }
+        let keywords: Vec<String> = keywords.iter().map(|kw| kw.to_string()).collect();
+        let scores = index.search(&keywords, mode, items.len());
+        let mut results: Vec<SearchResult> = items
+            .iter()
+            .filter_map(|item| {
+                scores.get(&item.item_id).map(|score| SearchResult {
+                    item: item.clone(),
+                    matched_keywords: score.matched_keywords,
+                    score: score.weight,
+                })
+            })
+            .collect();
+        sort_results(&mut results, SortOrder::Relevance);
+        results.iter().map(|result| result.item.item_id.sequence).collect()
+    }
+
+    #[test]
+    fn one_edit_apart() {
+        assert!(within_one_edit("lamp", "lamp"));
+        assert!(within_one_edit("lamp", "lump"));
+        assert!(within_one_edit("lamp", "lamps"));
+        assert!(within_one_edit("lamps", "lamp"));
+        assert!(within_one_edit("lamp", "clamp"));
+        assert!(within_one_edit("lamp", "lmp"));
+        assert!(within_one_edit("über", "uber"));
+        assert!(!within_one_edit("lamp", "lmap"));
+        assert!(!within_one_edit("lamp", "lampshade"));
+        assert!(!within_one_edit("lamp", "camps"));
+        assert!(!within_one_edit("lamp", "desk"));
+    }
+
+    #[test]
+    fn more_matched_keywords_rank_first() {
+        let items = [
+            item(1, "desk", &["desk"]),
+            item(2, "lamp", &["lamp", "desk"]),
+            item(3, "chair", &["chair"]),
+        ];
+        assert_eq!(ranked(&items, &["lamp", "desk"], KeywordMatch::Exact), vec![2, 1]);
+    }
+
+    #[test]
+    fn keywords_outweigh_names_and_rare_terms_outweigh_common_ones() {
+        let items = [item(1, "lamp", &["light"]), item(2, "light", &["lamp"])];
+        assert_eq!(ranked(&items, &["lamp"], KeywordMatch::Exact), vec![2, 1]);
+
+        let items = [item(1, "a", &["common"]), item(2, "b", &["common"]), item(3, "c", &["rare"])];
+        assert_eq!(ranked(&items, &["common", "rare"], KeywordMatch::Exact), vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn exact_beats_prefix_beats_typo() {
+        let items = [
+            item(1, "a", &["lampy"]),
+            item(2, "b", &["lamp"]),
+            item(3, "c", &["lamb"]),
+            item(4, "d", &["desk"]),
+        ];
+        assert_eq!(ranked(&items, &["lamp"], KeywordMatch::Exact), vec![2]);
+        assert_eq!(ranked(&items, &["lamp"], KeywordMatch::Prefix), vec![2, 1]);
+        assert_eq!(ranked(&items, &["lamp"], KeywordMatch::Fuzzy), vec![2, 1, 3]);
+        // Too short to have its typos matched
+        assert_eq!(ranked(&items, &["lmp"], KeywordMatch::Fuzzy), Vec::<u64>::new());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::state_file;
+use common::ItemId;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Sequence numbers are reserved in the state file a block at a time, so only
+// one allocation per block touches the disk. After a restart numbering resumes
+// above everything reserved before: the unused rest of a block is skipped, but
+// an id is never handed out twice.
+const RESERVATION_BLOCK: u64 = 1000;
+
+struct CategorySequence {
+    next: u64,
+    reserved: u64,
+}
+
+// Per-category monotonic allocator for item ids
+pub struct SequenceAllocator {
+    path: Option<PathBuf>,
+    sequences: Mutex<HashMap<i32, CategorySequence>>,
+}
+
+impl SequenceAllocator {
+    // Resumes from the reservations in `path`; with no path ids only stay unique until restart
+    pub fn open(path: Option<PathBuf>) -> io::Result<Self> {
+        let mut sequences = HashMap::new();
+        if let Some(path) = &path {
+            let reserved: HashMap<i32, u64> = state_file::load(path)?.unwrap_or_default();
+            for (category, reserved) in reserved {
+                sequences.insert(category, CategorySequence { next: reserved + 1, reserved });
+            }
+        }
+        Ok(SequenceAllocator { path, sequences: Mutex::new(sequences) })
+    }
+    
+    pub fn next(&self, category: i32) -> io::Result<ItemId> {
+        let mut sequences = self.sequences.lock().unwrap();
+        let (next, reserved) = sequences.get(&category)
+            .map(|s| (s.next, s.reserved))
+            .unwrap_or((1, 0));
+        
+        if next > reserved {
+            // Persist the new block before using any of it
+            let new_reserved = next + RESERVATION_BLOCK - 1;
+            if let Some(path) = &self.path {
+                let mut snapshot: HashMap<i32, u64> = sequences.iter()
+                    .map(|(category, s)| (*category, s.reserved))
+                    .collect();
+                snapshot.insert(category, new_reserved);
+                state_file::save(path, &snapshot)?;
+            }
+            sequences.insert(category, CategorySequence { next, reserved: new_reserved });
+        }
+        
+        let sequence = sequences.get_mut(&category).expect("sequence exists after reservation");
+        sequence.next += 1;
+        Ok(ItemId { category, sequence: next })
+    }
+}
@@ -0,0 +1,24 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+
+// The state saved in `path`, or None if nothing has been saved there yet
+pub fn load<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Write-then-rename so a crash mid-write never leaves a truncated file
+pub fn save<T: Serialize>(path: &Path, state: &T) -> io::Result<()> {
+    let contents = serde_json::to_string(state)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
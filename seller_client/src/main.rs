@@ -1,6 +1,8 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use common::telemetry;
 use common::*;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::Instrument;
 use uuid::Uuid;
 
 fn get_seller_server_addr() -> String {
@@ -45,18 +47,11 @@ enum Commands {
     RegisterItem {
         #[arg(short, long)]
         session_id: String,
-        #[arg(short, long)]
-        name: String,
-        #[arg(short, long)]
-        category: i32,
-        #[arg(short, long, num_args = 1..=5, value_delimiter = ',')]
-        keywords: Vec<String>,
-        #[arg(short, long)]
-        condition: String,
-        #[arg(short, long)]
-        price: f64,
-        #[arg(short, long)]
-        quantity: i32,
+        #[command(flatten)]
+        item: ItemArgs,
+        /// Keep the listing as a draft until it is published
+        #[arg(long)]
+        draft: bool,
     },
     /// Change item price
     ChangePrice {
@@ -67,25 +62,99 @@ enum Commands {
         #[arg(short, long)]
         new_price: f64,
     },
-    /// Update units for sale
+    /// Update units for sale: set the quantity, or remove some units
     UpdateUnits {
         #[arg(short, long)]
         session_id: String,
         #[arg(short, long)]
         item_id: String,
+        /// Set the quantity for sale to this value
+        #[arg(short, long, conflicts_with = "remove", required_unless_present = "remove")]
+        quantity: Option<i32>,
+        /// Take this many units off sale
         #[arg(short, long)]
-        quantity: i32,
+        remove: Option<i32>,
     },
     /// Display items for sale
     DisplayItems {
         #[arg(short, long)]
         session_id: String,
     },
+    /// Edit an item's listing; only the given fields change
+    EditItem {
+        #[arg(short, long)]
+        session_id: String,
+        #[arg(short, long)]
+        item_id: String,
+        #[arg(short, long)]
+        name: Option<String>,
+        #[arg(short, long)]
+        category: Option<i32>,
+        /// Replaces all keywords
+        #[arg(short, long, num_args = 1..=5, value_delimiter = ',')]
+        keywords: Option<Vec<String>>,
+        /// new or used
+        #[arg(short = 'o', long)]
+        condition: Option<String>,
+        #[arg(short, long)]
+        price: Option<f64>,
+    },
+    /// Publish a draft listing
+    PublishItem {
+        #[arg(short, long)]
+        session_id: String,
+        #[arg(short, long)]
+        item_id: String,
+    },
+    /// Hide a listing from buyers for now
+    PauseItem {
+        #[arg(short, long)]
+        session_id: String,
+        #[arg(short, long)]
+        item_id: String,
+    },
+    /// Make a paused listing visible again
+    ResumeItem {
+        #[arg(short, long)]
+        session_id: String,
+        #[arg(short, long)]
+        item_id: String,
+    },
+    /// Remove a listing for good; it is also taken out of buyers' carts
+    DelistItem {
+        #[arg(short, long)]
+        session_id: String,
+        #[arg(short, long)]
+        item_id: String,
+    },
+    /// List item categories as a tree
+    ListCategories {
+        #[arg(short, long)]
+        session_id: String,
+    },
+}
+
+#[derive(Args)]
+struct ItemArgs {
+    #[arg(short, long)]
+    name: String,
+    #[arg(short, long)]
+    category: i32,
+    #[arg(short, long, num_args = 1..=5, value_delimiter = ',')]
+    keywords: Vec<String>,
+    /// new or used
+    #[arg(short = 'o', long)]
+    condition: String,
+    #[arg(short, long)]
+    price: f64,
+    #[arg(short, long)]
+    quantity: i32,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    let _telemetry = telemetry::init_client("seller_client");
     
     match cli.command {
         Commands::CreateAccount { name, password } => {
@@ -100,24 +169,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::GetRating { session_id } => {
             get_rating(session_id).await?;
         }
-        Commands::RegisterItem {
-            session_id,
-            name,
-            category,
-            keywords,
-            condition,
-            price,
-            quantity,
-        } => {
-            register_item(
-                session_id,
-                name,
-                category,
-                keywords,
-                condition,
-                price,
-                quantity,
-            ).await?;
+        Commands::RegisterItem { session_id, item, draft } => {
+            register_item(session_id, item, draft).await?;
         }
         Commands::ChangePrice {
             session_id,
@@ -130,12 +183,52 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             session_id,
             item_id,
             quantity,
+            remove,
         } => {
-            update_units(session_id, item_id, quantity).await?;
+            let update = match (quantity, remove) {
+                (Some(quantity), _) => UnitsUpdate::SetTo(quantity),
+                (None, Some(units)) => UnitsUpdate::Remove(units),
+                (None, None) => unreachable!("clap requires one of --quantity and --remove"),
+            };
+            update_units(session_id, item_id, update).await?;
         }
         Commands::DisplayItems { session_id } => {
             display_items(session_id).await?;
         }
+        Commands::EditItem {
+            session_id,
+            item_id,
+            name,
+            category,
+            keywords,
+            condition,
+            price,
+        } => {
+            let changes = ItemPatch {
+                item_name: name,
+                item_category: category,
+                keywords: keywords.map(normalize_keywords),
+                condition: condition.as_deref().map(parse_condition).transpose()?,
+                sale_price: price,
+                quantity: None,
+            };
+            edit_item(session_id, item_id, changes).await?;
+        }
+        Commands::PublishItem { session_id, item_id } => {
+            change_status(session_id, item_id, StatusAction::Publish).await?;
+        }
+        Commands::PauseItem { session_id, item_id } => {
+            change_status(session_id, item_id, StatusAction::Pause).await?;
+        }
+        Commands::ResumeItem { session_id, item_id } => {
+            change_status(session_id, item_id, StatusAction::Resume).await?;
+        }
+        Commands::DelistItem { session_id, item_id } => {
+            change_status(session_id, item_id, StatusAction::Delist).await?;
+        }
+        Commands::ListCategories { session_id } => {
+            list_categories(session_id).await?;
+        }
     }
     
     Ok(())
@@ -143,18 +236,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 async fn send_request(request: SellerRequest) -> Result<SellerResponse, Box<dyn std::error::Error>> {
     let addr = get_seller_server_addr();
-    let mut stream = tokio::net::TcpStream::connect(&addr).await?;
-    
-    let request_str = serde_json::to_string(&request)?;
-    stream.write_all(request_str.as_bytes()).await?;
-    stream.write_all(b"\n").await?;
+    // Each operation is the root of its trace
+    let name: &'static str = (&request).into();
+    let span = telemetry::call_span("seller_server", name);
+    let envelope = telemetry::envelope(&span, request);
     
-    let mut response_str = String::new();
-    let mut reader = BufReader::new(stream);
-    reader.read_line(&mut response_str).await?;
-    
-    let response: SellerResponse = serde_json::from_str(&response_str)?;
-    Ok(response)
+    async {
+        let mut stream = tokio::net::TcpStream::connect(&addr).await?;
+        
+        let request_str = serde_json::to_string(&envelope)?;
+        stream.write_all(request_str.as_bytes()).await?;
+        stream.write_all(b"\n").await?;
+        
+        let mut response_str = String::new();
+        let mut reader = BufReader::new(stream);
+        reader.read_line(&mut response_str).await?;
+        
+        let response: SellerResponse = serde_json::from_str(&response_str)?;
+        Ok(response)
+    }
+    .instrument(span)
+    .await
 }
 
 async fn create_account(name: String, password: String) -> Result<(), Box<dyn std::error::Error>> {
@@ -255,33 +357,14 @@ async fn get_rating(session_id_str: String) -> Result<(), Box<dyn std::error::Er
 
 async fn register_item(
     session_id_str: String,
-    name: String,
-    category: i32,
-    keywords: Vec<String>,
-    condition_str: String,
-    price: f64,
-    quantity: i32,
+    item: ItemArgs,
+    draft: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
+    let ItemArgs { name, category, keywords, condition, price, quantity } = item;
     
-    let condition = match condition_str.to_lowercase().as_str() {
-        "new" => Condition::New,
-        "used" => Condition::Used,
-        _ => return Err("Condition must be 'new' or 'used'".into()),
-    };
-    
-    // Validate keywords
-    let keywords: Vec<String> = keywords.into_iter()
-        .map(|k| {
-            let k = k.trim().to_string();
-            if k.len() > 8 {
-                k[..8].to_string()
-            } else {
-                k
-            }
-        })
-        .take(5)
-        .collect();
+    let condition = parse_condition(&condition)?;
+    let keywords = normalize_keywords(keywords);
     
     let request = SellerRequest::RegisterItemForSale {
         session_id,
@@ -291,12 +374,16 @@ async fn register_item(
         condition,
         sale_price: price,
         quantity,
+        draft,
     };
     
     match send_request(request).await? {
         SellerResponse::RegisterItemForSale(item_id) => {
             println!("Item registered successfully!");
             println!("Item ID: {}", item_id);
+            if draft {
+                println!("Saved as a draft; run publish-item to list it");
+            }
             Ok(())
         }
         SellerResponse::Error(msg) => {
@@ -316,7 +403,7 @@ async fn change_price(
     new_price: f64,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
-    let item_id = Uuid::parse_str(&item_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
     
     let request = SellerRequest::ChangeItemPrice {
         session_id,
@@ -343,20 +430,21 @@ async fn change_price(
 async fn update_units(
     session_id_str: String,
     item_id_str: String,
-    quantity: i32,
+    update: UnitsUpdate,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::parse_str(&session_id_str)?;
-    let item_id = Uuid::parse_str(&item_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
     
     let request = SellerRequest::UpdateUnitsForSale {
         session_id,
         item_id,
-        quantity,
+        update,
     };
     
     match send_request(request).await? {
-        SellerResponse::UpdateUnitsForSale => {
+        SellerResponse::UpdateUnitsForSale(quantity) => {
             println!("Units updated successfully!");
+            println!("Units for sale: {}", quantity);
             Ok(())
         }
         SellerResponse::Error(msg) => {
@@ -385,14 +473,7 @@ async fn display_items(session_id_str: String) -> Result<(), Box<dyn std::error:
             println!("Your Items for Sale:");
             println!("{:-<80}", "");
             for item in items {
-                println!("Item ID: {}", item.item_id);
-                println!("  Name: {}", item.item_name);
-                println!("  Category: {}", item.item_category);
-                println!("  Keywords: {}", item.keywords.join(", "));
-                println!("  Condition: {:?}", item.condition);
-                println!("  Price: ${:.2}", item.sale_price);
-                println!("  Quantity: {}", item.quantity);
-                println!("  Feedback: ↑{} ↓{}", item.feedback.thumbs_up, item.feedback.thumbs_down);
+                print_item(&item);
                 println!("{:-<80}", "");
             }
             Ok(())
@@ -406,4 +487,154 @@ async fn display_items(session_id_str: String) -> Result<(), Box<dyn std::error:
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+async fn edit_item(
+    session_id_str: String,
+    item_id_str: String,
+    changes: ItemPatch,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let session_id = Uuid::parse_str(&session_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
+    
+    let ItemPatch { item_name, item_category, keywords, condition, sale_price, quantity } = &changes;
+    if item_name.is_none() && item_category.is_none() && keywords.is_none()
+        && condition.is_none() && sale_price.is_none() && quantity.is_none()
+    {
+        return Err("Nothing to change; give at least one field to edit".into());
+    }
+    
+    let request = SellerRequest::EditItem {
+        session_id,
+        item_id,
+        changes,
+    };
+    
+    match send_request(request).await? {
+        SellerResponse::EditItem(item) => {
+            println!("Item updated successfully!");
+            print_item(&item);
+            Ok(())
+        }
+        SellerResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn change_status(
+    session_id_str: String,
+    item_id_str: String,
+    action: StatusAction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let session_id = Uuid::parse_str(&session_id_str)?;
+    let item_id: ItemId = item_id_str.parse()?;
+    
+    let request = match action {
+        StatusAction::Publish => SellerRequest::PublishItem { session_id, item_id },
+        StatusAction::Pause => SellerRequest::PauseItem { session_id, item_id },
+        StatusAction::Resume => SellerRequest::ResumeItem { session_id, item_id },
+        StatusAction::Delist => SellerRequest::DelistItem { session_id, item_id },
+    };
+    
+    match send_request(request).await? {
+        SellerResponse::PublishItem => {
+            println!("Item published!");
+            Ok(())
+        }
+        SellerResponse::PauseItem => {
+            println!("Item paused!");
+            Ok(())
+        }
+        SellerResponse::ResumeItem => {
+            println!("Item resumed!");
+            Ok(())
+        }
+        SellerResponse::DelistItem => {
+            println!("Item delisted!");
+            Ok(())
+        }
+        SellerResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+async fn list_categories(session_id_str: String) -> Result<(), Box<dyn std::error::Error>> {
+    let session_id = Uuid::parse_str(&session_id_str)?;
+    
+    let request = SellerRequest::ListCategories { session_id };
+    
+    match send_request(request).await? {
+        SellerResponse::ListCategories(categories) => {
+            if categories.is_empty() {
+                println!("No categories.");
+                return Ok(());
+            }
+            
+            println!("Categories:");
+            print_category_tree(&categories, None, 1);
+            Ok(())
+        }
+        SellerResponse::Error(msg) => {
+            eprintln!("Error: {}", msg);
+            Ok(())
+        }
+        _ => {
+            eprintln!("Unexpected response");
+            Ok(())
+        }
+    }
+}
+
+fn print_category_tree(categories: &[Category], parent_id: Option<i32>, depth: usize) {
+    for category in categories.iter().filter(|c| c.parent_id == parent_id) {
+        println!("{}[{}] {}", "  ".repeat(depth), category.category_id, category.name);
+        print_category_tree(categories, Some(category.category_id), depth + 1);
+    }
+}
+
+fn print_item(item: &Item) {
+    println!("Item ID: {}", item.item_id);
+    println!("  Name: {}", item.item_name);
+    println!("  Category: {}", item.item_category);
+    println!("  Keywords: {}", item.keywords.join(", "));
+    println!("  Condition: {:?}", item.condition);
+    println!("  Price: ${:.2}", item.sale_price);
+    println!("  Quantity: {}", item.quantity);
+    println!("  Status: {}", item.status);
+    println!("  Feedback: ↑{} ↓{}", item.feedback.thumbs_up, item.feedback.thumbs_down);
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, Box<dyn std::error::Error>> {
+    match condition.to_lowercase().as_str() {
+        "new" => Ok(Condition::New),
+        "used" => Ok(Condition::Used),
+        _ => Err("Condition must be 'new' or 'used'".into()),
+    }
+}
+
+// Keywords are limited to 5, each up to 8 characters
+fn normalize_keywords(keywords: Vec<String>) -> Vec<String> {
+    keywords.into_iter()
+        .map(|k| {
+            let k = k.trim().to_string();
+            if k.len() > 8 {
+                k[..8].to_string()
+            } else {
+                k
+            }
+        })
+        .take(5)
+        .collect()
+}
@@ -0,0 +1,476 @@
+// The sellers' frontend: checks each request's session with the customer
+// database and carries it out against the databases. `serve` runs the server
+// loop on a listener the caller has bound, so the binary and in-process tests
+// start it the same way.
+
+use common::clock::Clock;
+use common::metrics::{self, ErrorKind};
+use common::{status, telemetry};
+use common::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, error, info, instrument, warn, Instrument};
+use uuid::Uuid;
+
+// How long a readiness check waits for each database
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+// What request handlers need besides the request
+pub struct Context {
+    pub backends: Backends,
+    // Decides whether a session has expired; customer_db's clock sets expirations
+    pub clock: Arc<dyn Clock>,
+}
+
+// Serves requests from `listener` until accepting a connection fails
+pub async fn serve(listener: TcpListener, context: Arc<Context>) -> std::io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, context).await {
+                error!(error = %e, "error handling connection");
+            }
+        });
+    }
+}
+
+async fn handle_connection(socket: TcpStream, context: Arc<Context>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let _connection = status::connection_opened();
+    let (read_half, mut write_half) = socket.into_split();
+    let reader = BufReader::new(read_half);
+    let mut lines = reader.lines();
+    
+    while let Ok(Some(line)) = lines.next_line().await {
+        // Clients may wrap the request in an envelope to continue their own trace
+        let parsed = serde_json::from_str::<Envelope<SellerRequest>>(&line)
+            .map(|envelope| (Some(envelope.trace_id), envelope.parent_span_id, envelope.request))
+            .or_else(|_| serde_json::from_str::<SellerRequest>(&line).map(|request| (None, None, request)));
+        let (trace_id, parent_span_id, request) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!(error = %e, "invalid request");
+                metrics::count_error(ErrorKind::InvalidRequest, "unknown");
+                let response = SellerResponse::Error(format!("Invalid request: {}", e));
+                let _ = send_response(&mut write_half, response).await;
+                continue;
+            }
+        };
+        
+        let name: &'static str = (&request).into();
+        let (span, trace_id) = telemetry::request_span(name, trace_id, parent_span_id);
+        let response = telemetry::traced(span, trace_id, async {
+            let started = Instant::now();
+            let response = handle_request(request, &context).await;
+            let elapsed = started.elapsed();
+            metrics::observe_request(name, elapsed);
+            if let SellerResponse::Error(msg) = &response {
+                metrics::count_error(ErrorKind::Rejected, name);
+                info!(error = %msg, elapsed_us = elapsed.as_micros() as u64, "request rejected");
+            } else {
+                debug!(elapsed_us = elapsed.as_micros() as u64, "request handled");
+            }
+            response
+        }).await;
+        if let Err(e) = send_response(&mut write_half, response).await {
+            warn!(%trace_id, request = name, error = %e, "failed to send response");
+            metrics::count_error(ErrorKind::Connection, name);
+        }
+    }
+    
+    Ok(())
+}
+
+async fn handle_request(request: SellerRequest, context: &Context) -> SellerResponse {
+    match request {
+        SellerRequest::CreateAccount { seller_name, password } => {
+            match send_to_customer_db(context, CustomerDbRequest::CreateSeller {
+                seller_name: seller_name.clone(),
+                password,
+            }).await {
+                Ok(CustomerDbResponse::SellerCreated(seller_id)) => {
+                    SellerResponse::CreateAccount(seller_id)
+                }
+                Ok(CustomerDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                _ => SellerResponse::Error("Failed to create seller account".to_string()),
+            }
+        }
+        
+        SellerRequest::Login { seller_name, password } => {
+            match send_to_customer_db(context, CustomerDbRequest::GetSellerByName {
+                seller_name: seller_name.clone(),
+            }).await {
+                Ok(CustomerDbResponse::Seller(Some(seller))) => {
+                    if seller.password == password {
+                        match send_to_customer_db(context, CustomerDbRequest::CreateSession {
+                            user_id: seller.seller_id,
+                            user_type: UserType::Seller,
+                        }).await {
+                            Ok(CustomerDbResponse::SessionCreated(session_id, _)) => {
+                                SellerResponse::Login(session_id)
+                            }
+                            Ok(CustomerDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                            _ => SellerResponse::Error("Failed to create session".to_string()),
+                        }
+                    } else {
+                        SellerResponse::Error("Invalid password".to_string())
+                    }
+                }
+                Ok(CustomerDbResponse::Seller(None)) => {
+                    SellerResponse::Error("Seller not found".to_string())
+                }
+                Ok(CustomerDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                _ => SellerResponse::Error("Login failed".to_string()),
+            }
+        }
+        
+        SellerRequest::Logout { session_id } => {
+            match send_to_customer_db(context, CustomerDbRequest::DeleteSession { session_id }).await {
+                Ok(CustomerDbResponse::SessionDeleted) => SellerResponse::Logout,
+                Ok(CustomerDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                _ => SellerResponse::Error("Logout failed".to_string()),
+            }
+        }
+        
+        SellerRequest::GetSellerRating { session_id } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(session) => {
+                    // Ratings are kept with the items they were given on
+                    match send_to_product_db(context, ProductDbRequest::GetSellerFeedback {
+                        seller_id: session.user_id,
+                    }).await {
+                        Ok(ProductDbResponse::SellerFeedback(feedback)) => SellerResponse::GetSellerRating(feedback),
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to get seller rating".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::RegisterItemForSale {
+            session_id,
+            item_name,
+            item_category,
+            keywords,
+            condition,
+            sale_price,
+            quantity,
+            draft,
+        } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(session) => {
+                    let item = Item {
+                        item_id: ItemId::default(), // Will be assigned by product DB
+                        item_name,
+                        item_category,
+                        keywords,
+                        condition,
+                        sale_price,
+                        quantity,
+                        feedback: Feedback { thumbs_up: 0, thumbs_down: 0 },
+                        seller_id: session.user_id,
+                        listed_at: 0, // Will be assigned by product DB
+                        version: 0,
+                        status: if draft { ItemStatus::Draft } else { ItemStatus::Active },
+                    };
+                    
+                    match send_to_product_db(context, ProductDbRequest::CreateItem { item }).await {
+                        Ok(ProductDbResponse::ItemCreated(item_id)) => {
+                            SellerResponse::RegisterItemForSale(item_id)
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to register item".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::ChangeItemPrice { session_id, item_id, new_price } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::SetPrice {
+                        item_id,
+                        seller_id: Some(session.user_id),
+                        price: new_price,
+                    }).await {
+                        Ok(ProductDbResponse::ItemChanged(_)) => SellerResponse::ChangeItemPrice,
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to update price".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::UpdateUnitsForSale { session_id, item_id, update } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(session) => {
+                    let seller_id = Some(session.user_id);
+                    let request = match update {
+                        UnitsUpdate::SetTo(quantity) => ProductDbRequest::PatchItem {
+                            item_id,
+                            seller_id,
+                            patch: ItemPatch {
+                                quantity: Some(quantity),
+                                ..ItemPatch::default()
+                            },
+                        },
+                        UnitsUpdate::Remove(units) if units <= 0 => {
+                            return SellerResponse::Error("Units to remove must be positive".to_string());
+                        }
+                        UnitsUpdate::Remove(units) => ProductDbRequest::AdjustQuantity {
+                            item_id,
+                            seller_id,
+                            delta: -units,
+                            min_result: 0,
+                        },
+                    };
+                    match send_to_product_db(context, request).await {
+                        Ok(ProductDbResponse::ItemChanged(item)) => SellerResponse::UpdateUnitsForSale(item.quantity),
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to update quantity".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::DisplayItemsForSale { session_id } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::GetItemsBySeller {
+                        seller_id: session.user_id,
+                    }).await {
+                        Ok(ProductDbResponse::Items(items)) => {
+                            SellerResponse::DisplayItemsForSale(items)
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to get items".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::EditItem { session_id, item_id, changes } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(session) => {
+                    match send_to_product_db(context, ProductDbRequest::PatchItem {
+                        item_id,
+                        seller_id: Some(session.user_id),
+                        patch: changes,
+                    }).await {
+                        Ok(ProductDbResponse::ItemChanged(item)) => SellerResponse::EditItem(item),
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to edit item".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::PublishItem { session_id, item_id } => {
+            match change_item_status(context, session_id, item_id, StatusAction::Publish).await {
+                Ok(()) => SellerResponse::PublishItem,
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::PauseItem { session_id, item_id } => {
+            match change_item_status(context, session_id, item_id, StatusAction::Pause).await {
+                Ok(()) => SellerResponse::PauseItem,
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::ResumeItem { session_id, item_id } => {
+            match change_item_status(context, session_id, item_id, StatusAction::Resume).await {
+                Ok(()) => SellerResponse::ResumeItem,
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::DelistItem { session_id, item_id } => {
+            match change_item_status(context, session_id, item_id, StatusAction::Delist).await {
+                Ok(()) => SellerResponse::DelistItem,
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::ListCategories { session_id } => {
+            match validate_session(context, session_id, UserType::Seller).await {
+                Ok(_) => {
+                    match send_to_product_db(context, ProductDbRequest::ListCategories).await {
+                        Ok(ProductDbResponse::Categories(categories)) => {
+                            SellerResponse::ListCategories(categories)
+                        }
+                        Ok(ProductDbResponse::Error(msg)) => SellerResponse::Error(msg),
+                        _ => SellerResponse::Error("Failed to list categories".to_string()),
+                    }
+                }
+                Err(e) => SellerResponse::Error(e),
+            }
+        }
+        
+        SellerRequest::Health => SellerResponse::Health,
+        
+        SellerRequest::Ready => SellerResponse::Ready(readiness(&context.backends).await),
+        
+        SellerRequest::Stats => SellerResponse::Stats(status::stats(BTreeMap::new())),
+    }
+}
+
+// Ready when both databases answer a health check
+pub async fn readiness(backends: &Backends) -> Readiness {
+    let (customer_db, product_db) = tokio::join!(
+        check_dependency::<_, CustomerDbResponse>("customer_db", backends.customer_db.clone(), CustomerDbRequest::Health),
+        check_dependency::<_, ProductDbResponse>("product_db", backends.product_db.clone(), ProductDbRequest::Health),
+    );
+    Readiness::of(vec![customer_db, product_db])
+}
+
+// Any well-formed answer within the timeout counts as reachable. Failures
+// are reported to the caller rather than logged, since probes repeat them.
+async fn check_dependency<T, U>(name: &str, addr: String, request: T) -> DependencyStatus
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let envelope = Envelope {
+        trace_id: telemetry::current_trace_id(),
+        parent_span_id: None,
+        request,
+    };
+    let error = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, send_and_receive::<_, U>(&addr, envelope)).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("No answer within {} ms", HEALTH_CHECK_TIMEOUT.as_millis())),
+    };
+    DependencyStatus {
+        name: name.to_string(),
+        addr,
+        reachable: error.is_none(),
+        error,
+    }
+}
+
+async fn change_item_status(context: &Context, session_id: Uuid, item_id: ItemId, action: StatusAction) -> Result<(), String> {
+    let session = validate_session(context, session_id, UserType::Seller).await?;
+    match send_to_product_db(context, ProductDbRequest::ChangeStatus {
+        item_id,
+        seller_id: Some(session.user_id),
+        action,
+    }).await {
+        Ok(ProductDbResponse::ItemChanged(_)) => Ok(()),
+        Ok(ProductDbResponse::Error(msg)) => Err(msg),
+        _ => Err("Failed to change item status".to_string()),
+    }
+}
+
+#[instrument(skip_all, fields(trace_id = %telemetry::current_trace_id()))]
+async fn validate_session(context: &Context, session_id: Uuid, expected_type: UserType) -> Result<Session, String> {
+    match send_to_customer_db(context, CustomerDbRequest::GetSession { session_id }).await {
+        Ok(CustomerDbResponse::Session(Some(session))) => {
+            let now = context.clock.now();
+            
+            if session.expiration < now {
+                let _ = send_to_customer_db(context, CustomerDbRequest::DeleteSession { session_id }).await;
+                return Err("Session expired".to_string());
+            }
+            
+            if session.user_type != expected_type {
+                return Err("Invalid session type".to_string());
+            }
+            
+            Ok(session)
+        }
+        Ok(CustomerDbResponse::Session(None)) => Err("Session not found".to_string()),
+        Ok(CustomerDbResponse::Error(msg)) => Err(msg),
+        Ok(other) => {
+            warn!(response = ?other, "unexpected response to GetSession");
+            Err("Failed to validate session".to_string())
+        }
+        // Already logged by call_backend
+        Err(_) => Err("Failed to validate session".to_string()),
+    }
+}
+
+async fn send_to_customer_db(context: &Context, request: CustomerDbRequest) -> Result<CustomerDbResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let name: &'static str = (&request).into();
+    call_backend("customer_db", &context.backends.customer_db, context.backends.timeout, name, request).await
+}
+
+async fn send_to_product_db(context: &Context, request: ProductDbRequest) -> Result<ProductDbResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let name: &'static str = (&request).into();
+    call_backend("product_db", &context.backends.product_db, context.backends.timeout, name, request).await
+}
+
+// One timed round trip to a database, tagged with the current trace. A
+// database that does not answer within `timeout` counts as unavailable, so a
+// stalled link fails the request instead of holding it open.
+async fn call_backend<T, U>(
+    backend: &str,
+    addr: &str,
+    timeout: Duration,
+    name: &str,
+    request: T,
+) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let span = telemetry::call_span(backend, name);
+    let envelope = telemetry::envelope(&span, request);
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, send_and_receive(addr, envelope)).instrument(span).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("No answer within {} ms", timeout.as_millis()).into()),
+    };
+    let elapsed = started.elapsed();
+    metrics::observe_backend(backend, name, elapsed);
+    match &result {
+        Ok(_) => debug!(backend, request = name, elapsed_us = elapsed.as_micros() as u64, "backend call"),
+        Err(e) => {
+            metrics::count_error(ErrorKind::BackendUnavailable, name);
+            error!(backend, request = name, %addr, error = %e, "backend call failed");
+        }
+    }
+    result
+}
+
+async fn send_and_receive<T, U>(
+    addr: &str,
+    request: T,
+) -> Result<U, Box<dyn std::error::Error + Send + Sync>>
+where
+    T: serde::Serialize,
+    U: for<'de> serde::Deserialize<'de>,
+{
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    let request_str = serde_json::to_string(&request)?;
+    stream.write_all(request_str.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+
+    let mut response_str = String::new();
+    let (reader, _) = stream.split();
+    let mut buf_reader = BufReader::new(reader);
+    buf_reader.read_line(&mut response_str).await?;
+
+    let response: U = serde_json::from_str(response_str.trim())?;
+    Ok(response)
+}
+
+async fn send_response(
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    response: SellerResponse,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response_str = serde_json::to_string(&response)?;
+    writer.write_all(response_str.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
\ No newline at end of file
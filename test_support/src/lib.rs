@@ -0,0 +1,223 @@
+// Runs the whole marketplace inside the calling tokio runtime for integration
+// tests: both databases and both frontends, each on a port the OS picks, so
+// tests can run in parallel without clashing. The services stop with the
+// runtime, so every #[tokio::test] starts from empty databases. All of them
+// tell the time by one manual clock, which only moves when a test advances it.
+
+use common::clock::{Clock, ManualClock, SystemClock};
+use common::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+// Every account `sign_up` creates uses this password
+pub const PASSWORD: &str = "password";
+
+// The services' default, used unless a test picks its own
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(300);
+
+pub struct Marketplace {
+    pub customer_db_addr: String,
+    pub product_db_addr: String,
+    pub seller_server_addr: String,
+    pub buyer_server_addr: String,
+    // The customer database's state, for tests that need to change it directly
+    pub customer_db: Arc<customer_db::Store>,
+    // Starts at the real time, then stands still until advanced
+    pub clock: Arc<ManualClock>,
+}
+
+impl Marketplace {
+    // Starts every service. Their listeners are bound before this returns, so
+    // requests can be sent right away.
+    pub async fn start() -> Self {
+        Self::with_session_timeout(SESSION_TIMEOUT).await
+    }
+
+    pub async fn with_session_timeout(session_timeout: Duration) -> Self {
+        let clock = Arc::new(ManualClock::new(SystemClock.now()));
+        let customer_db = Arc::new(customer_db::Store::new(clock.clone(), session_timeout));
+        let (listener, customer_db_addr) = bind().await;
+        tokio::spawn(customer_db::serve(listener, customer_db.clone()));
+
+        let product_db = Arc::new(product_db::Store::open(None).expect("an in-memory store always opens"));
+        let (listener, product_db_addr) = bind().await;
+        tokio::spawn(product_db::serve(listener, product_db));
+
+        let backends = Backends {
+            customer_db: customer_db_addr.clone(),
+            product_db: product_db_addr.clone(),
+            timeout: BACKEND_TIMEOUT,
+        };
+        let (listener, seller_server_addr) = bind().await;
+        let context = seller_server::Context {
+            backends: backends.clone(),
+            clock: clock.clone(),
+        };
+        tokio::spawn(seller_server::serve(listener, Arc::new(context)));
+        let (listener, buyer_server_addr) = bind().await;
+        let context = buyer_server::Context {
+            backends,
+            clock: clock.clone(),
+        };
+        tokio::spawn(buyer_server::serve(listener, Arc::new(context)));
+
+        Marketplace {
+            customer_db_addr,
+            product_db_addr,
+            seller_server_addr,
+            buyer_server_addr,
+            customer_db,
+            clock,
+        }
+    }
+
+    pub fn seller(&self) -> SellerClient {
+        SellerClient { addr: self.seller_server_addr.clone() }
+    }
+
+    pub fn buyer(&self) -> BuyerClient {
+        BuyerClient { addr: self.buyer_server_addr.clone() }
+    }
+
+    // Sends a request straight to the customer database, bypassing the frontends
+    pub async fn customer_db(&self, request: CustomerDbRequest) -> CustomerDbResponse {
+        call(&self.customer_db_addr, envelope(request)).await
+    }
+
+    // Sends a request straight to the product database, e.g. to set up state
+    // no frontend API creates, such as purchase history
+    pub async fn product_db(&self, request: ProductDbRequest) -> ProductDbResponse {
+        call(&self.product_db_addr, envelope(request)).await
+    }
+}
+
+// Talks to the seller frontend, one connection per request like seller_client
+pub struct SellerClient {
+    addr: String,
+}
+
+impl SellerClient {
+    // For a seller frontend a test starts itself, e.g. with unusual backends
+    pub fn new(addr: String) -> Self {
+        SellerClient { addr }
+    }
+
+    // Panics if the server cannot be reached or its answer cannot be read
+    pub async fn send(&self, request: SellerRequest) -> SellerResponse {
+        call(&self.addr, request).await
+    }
+
+    // Creates an account and logs in; returns the session ID
+    pub async fn sign_up(&self, seller_name: &str) -> Uuid {
+        let request = SellerRequest::CreateAccount {
+            seller_name: seller_name.to_string(),
+            password: PASSWORD.to_string(),
+        };
+        match self.send(request).await {
+            SellerResponse::CreateAccount(_) => {}
+            other => panic!("CreateAccount failed: {:?}", other),
+        }
+        self.login(seller_name).await
+    }
+
+    // Logs in to an account `sign_up` created; returns the session ID
+    pub async fn login(&self, seller_name: &str) -> Uuid {
+        let request = SellerRequest::Login {
+            seller_name: seller_name.to_string(),
+            password: PASSWORD.to_string(),
+        };
+        match self.send(request).await {
+            SellerResponse::Login(session_id) => session_id,
+            other => panic!("Login failed: {:?}", other),
+        }
+    }
+
+    // Lists a new item in category 1 (Electronics), its name as its only keyword
+    pub async fn register_item(&self, session_id: Uuid, item_name: &str, sale_price: f64, quantity: i32) -> ItemId {
+        let request = SellerRequest::RegisterItemForSale {
+            session_id,
+            item_name: item_name.to_string(),
+            item_category: 1,
+            keywords: vec![item_name.to_string()],
+            condition: Condition::New,
+            sale_price,
+            quantity,
+            draft: false,
+        };
+        match self.send(request).await {
+            SellerResponse::RegisterItemForSale(item_id) => item_id,
+            other => panic!("RegisterItemForSale failed: {:?}", other),
+        }
+    }
+}
+
+// Talks to the buyer frontend, one connection per request like buyer_client
+pub struct BuyerClient {
+    addr: String,
+}
+
+impl BuyerClient {
+    // Panics if the server cannot be reached or its answer cannot be read
+    pub async fn send(&self, request: BuyerRequest) -> BuyerResponse {
+        call(&self.addr, request).await
+    }
+
+    // Creates an account and logs in; returns the session ID
+    pub async fn sign_up(&self, buyer_name: &str) -> Uuid {
+        let request = BuyerRequest::CreateAccount {
+            buyer_name: buyer_name.to_string(),
+            password: PASSWORD.to_string(),
+        };
+        match self.send(request).await {
+            BuyerResponse::CreateAccount(_) => {}
+            other => panic!("CreateAccount failed: {:?}", other),
+        }
+        self.login(buyer_name).await
+    }
+
+    // Logs in to an account `sign_up` created; returns the session ID
+    pub async fn login(&self, buyer_name: &str) -> Uuid {
+        let request = BuyerRequest::Login {
+            buyer_name: buyer_name.to_string(),
+            password: PASSWORD.to_string(),
+        };
+        match self.send(request).await {
+            BuyerResponse::Login(session_id) => session_id,
+            other => panic!("Login failed: {:?}", other),
+        }
+    }
+}
+
+async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind a loopback port");
+    let addr = listener.local_addr().expect("a bound listener has an address").to_string();
+    (listener, addr)
+}
+
+fn envelope<T>(request: T) -> Envelope<T> {
+    Envelope {
+        trace_id: TraceId::generate(),
+        parent_span_id: None,
+        request,
+    }
+}
+
+async fn call<T, U>(addr: &str, request: T) -> U
+where
+    T: Serialize,
+    U: for<'de> Deserialize<'de>,
+{
+    let mut stream = TcpStream::connect(addr).await.unwrap_or_else(|e| panic!("failed to connect to {}: {}", addr, e));
+    let request_str = serde_json::to_string(&request).expect("requests serialize");
+    stream.write_all(request_str.as_bytes()).await.expect("failed to send request");
+    stream.write_all(b"\n").await.expect("failed to send request");
+
+    let mut response_str = String::new();
+    let mut reader = BufReader::new(stream);
+    reader.read_line(&mut response_str).await.expect("failed to read response");
+    serde_json::from_str(&response_str).unwrap_or_else(|e| panic!("unreadable response {:?}: {}", response_str, e))
+}
@@ -0,0 +1,257 @@
+// Every BuyerRequest through a real buyer_server and databases
+
+use common::*;
+use test_support::{BuyerClient, Marketplace, PASSWORD};
+use uuid::Uuid;
+
+fn expect_error(response: BuyerResponse, expected: &str) {
+    match response {
+        BuyerResponse::Error(msg) => assert!(msg.contains(expected), "expected an error containing {:?}, got {:?}", expected, msg),
+        other => panic!("expected an error containing {:?}, got {:?}", expected, other),
+    }
+}
+
+async fn cart(buyer: &BuyerClient, session_id: Uuid) -> Vec<(ItemId, i32)> {
+    match buyer.send(BuyerRequest::DisplayCart { session_id }).await {
+        BuyerResponse::DisplayCart(cart) => cart.into_iter().map(|entry| (entry.item_id, entry.quantity)).collect(),
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+async fn get_item(buyer: &BuyerClient, session_id: Uuid, item_id: ItemId) -> Option<Item> {
+    match buyer.send(BuyerRequest::GetItem { session_id, item_id }).await {
+        BuyerResponse::GetItem(item) => item,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+fn search(session_id: Uuid, keywords: &[&str], filters: SearchFilters, sort: SortOrder) -> BuyerRequest {
+    BuyerRequest::SearchItemsForSale {
+        session_id,
+        category: None,
+        keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+        match_mode: KeywordMatch::Exact,
+        filters,
+        sort,
+    }
+}
+
+#[tokio::test]
+async fn create_account_login_and_logout() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+
+    let request = BuyerRequest::CreateAccount { buyer_name: "carol".to_string(), password: PASSWORD.to_string() };
+    let buyer_id = match buyer.send(request).await {
+        BuyerResponse::CreateAccount(buyer_id) => buyer_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    let request = BuyerRequest::Login { buyer_name: "carol".to_string(), password: "wrong".to_string() };
+    expect_error(buyer.send(request).await, "Invalid password");
+    let request = BuyerRequest::Login { buyer_name: "dave".to_string(), password: PASSWORD.to_string() };
+    expect_error(buyer.send(request).await, "Buyer not found");
+
+    let request = BuyerRequest::Login { buyer_name: "carol".to_string(), password: PASSWORD.to_string() };
+    let session_id = match buyer.send(request).await {
+        BuyerResponse::Login(session_id) => session_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    match market.customer_db(CustomerDbRequest::GetSession { session_id }).await {
+        CustomerDbResponse::Session(Some(session)) => {
+            assert_eq!(session.user_id, buyer_id);
+            assert_eq!(session.user_type, UserType::Buyer);
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    assert!(matches!(buyer.send(BuyerRequest::Logout { session_id }).await, BuyerResponse::Logout));
+    expect_error(buyer.send(BuyerRequest::DisplayCart { session_id }).await, "Session not found");
+}
+
+#[tokio::test]
+async fn search_items_for_sale() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let seller_session = seller.sign_up("alice").await;
+    let cheap = seller.register_item(seller_session, "lamp", 10.0, 1).await;
+    let dear = seller.register_item(seller_session, "lamp", 30.0, 1).await;
+    seller.register_item(seller_session, "chair", 20.0, 1).await;
+    let paused = seller.register_item(seller_session, "lamp", 15.0, 1).await;
+    seller.send(SellerRequest::PauseItem { session_id: seller_session, item_id: paused }).await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    let found = |response| match response {
+        BuyerResponse::SearchItemsForSale(results) => results.into_iter().map(|result: SearchResult| result.item.item_id).collect::<Vec<_>>(),
+        other => panic!("unexpected response: {:?}", other),
+    };
+    // Only listed items that match come back
+    let ids = found(buyer.send(search(session_id, &["lamp"], SearchFilters::default(), SortOrder::PriceDescending)).await);
+    assert_eq!(ids, vec![dear, cheap]);
+    let filters = SearchFilters {
+        max_price: Some(20.0),
+        ..SearchFilters::default()
+    };
+    let ids = found(buyer.send(search(session_id, &["lamp"], filters, SortOrder::Relevance)).await);
+    assert_eq!(ids, vec![cheap]);
+    let ids = found(buyer.send(search(session_id, &[], SearchFilters::default(), SortOrder::PriceAscending)).await);
+    assert_eq!(ids.len(), 3);
+}
+
+#[tokio::test]
+async fn get_item_by_id() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let seller_session = seller.sign_up("alice").await;
+    let item_id = seller.register_item(seller_session, "lamp", 25.0, 3).await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    let item = get_item(&buyer, session_id, item_id).await.expect("item exists");
+    assert_eq!(item.item_name, "lamp");
+    assert_eq!(item.quantity, 3);
+    assert!(get_item(&buyer, session_id, ItemId { category: 1, sequence: 999 }).await.is_none());
+}
+
+#[tokio::test]
+async fn cart_operations() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let seller_session = seller.sign_up("alice").await;
+    let lamp = seller.register_item(seller_session, "lamp", 25.0, 3).await;
+    let chair = seller.register_item(seller_session, "chair", 40.0, 2).await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    let add = |item_id, quantity| BuyerRequest::AddItemToCart { session_id, item_id, quantity };
+    let remove = |item_id, quantity| BuyerRequest::RemoveItemFromCart { session_id, item_id, quantity };
+    assert!(cart(&buyer, session_id).await.is_empty());
+    assert!(matches!(buyer.send(add(lamp, 2)).await, BuyerResponse::AddItemToCart));
+    assert!(matches!(buyer.send(add(chair, 1)).await, BuyerResponse::AddItemToCart));
+    assert!(matches!(buyer.send(add(lamp, 1)).await, BuyerResponse::AddItemToCart));
+    assert_eq!(cart(&buyer, session_id).await, vec![(lamp, 3), (chair, 1)]);
+
+    assert!(matches!(buyer.send(remove(lamp, 1)).await, BuyerResponse::RemoveItemFromCart));
+    assert_eq!(cart(&buyer, session_id).await, vec![(lamp, 2), (chair, 1)]);
+    // Removing more than the cart holds takes the item out
+    assert!(matches!(buyer.send(remove(chair, 5)).await, BuyerResponse::RemoveItemFromCart));
+    assert_eq!(cart(&buyer, session_id).await, vec![(lamp, 2)]);
+
+    assert!(matches!(buyer.send(BuyerRequest::SaveCart { session_id }).await, BuyerResponse::SaveCart));
+    assert_eq!(cart(&buyer, session_id).await, vec![(lamp, 2)]);
+    assert!(matches!(buyer.send(BuyerRequest::ClearCart { session_id }).await, BuyerResponse::ClearCart));
+    assert!(cart(&buyer, session_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn cart_rejects_unavailable_items() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let seller_session = seller.sign_up("alice").await;
+    let lamp = seller.register_item(seller_session, "lamp", 25.0, 3).await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    let add = |item_id, quantity| BuyerRequest::AddItemToCart { session_id, item_id, quantity };
+    expect_error(buyer.send(add(lamp, 4)).await, "Insufficient quantity");
+    expect_error(buyer.send(add(lamp, -2)).await, "Quantity must be positive");
+    expect_error(buyer.send(add(lamp, 0)).await, "Quantity must be positive");
+    let request = BuyerRequest::RemoveItemFromCart { session_id, item_id: lamp, quantity: -1 };
+    expect_error(buyer.send(request).await, "Quantity must be positive");
+    // The product database enforces it too
+    let buyer_id = match market.customer_db(CustomerDbRequest::GetBuyerByName { buyer_name: "carol".to_string() }).await {
+        CustomerDbResponse::Buyer(Some(buyer)) => buyer.buyer_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    let response = market.product_db(ProductDbRequest::AddToCart { buyer_id, item_id: lamp, quantity: -2 }).await;
+    assert!(matches!(&response, ProductDbResponse::Error(msg) if msg == "Quantity must be positive"), "{:?}", response);
+    expect_error(buyer.send(add(ItemId { category: 1, sequence: 999 }, 1)).await, "Item not found");
+    seller.send(SellerRequest::PauseItem { session_id: seller_session, item_id: lamp }).await;
+    expect_error(buyer.send(add(lamp, 1)).await, "Item is not available (paused)");
+    assert!(cart(&buyer, session_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn feedback_requires_a_purchase_and_rates_item_and_seller() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let seller_session = seller.sign_up("alice").await;
+    let lamp = seller.register_item(seller_session, "lamp", 25.0, 3).await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+    let seller_id = get_item(&buyer, session_id, lamp).await.expect("item exists").seller_id;
+
+    let vote = |thumbs_up| BuyerRequest::ProvideFeedback { session_id, item_id: lamp, thumbs_up };
+    expect_error(buyer.send(vote(true)).await, "Only buyers who purchased this item can rate it");
+
+    // There is no purchase API, so record the purchase in the database
+    let buyer_id = match market.customer_db(CustomerDbRequest::GetBuyerByName { buyer_name: "carol".to_string() }).await {
+        CustomerDbResponse::Buyer(Some(buyer)) => buyer.buyer_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    let response = market.product_db(ProductDbRequest::AddPurchaseHistory { buyer_id, item_id: lamp }).await;
+    assert!(matches!(response, ProductDbResponse::PurchaseRecorded), "{:?}", response);
+    match buyer.send(BuyerRequest::GetBuyerPurchases { session_id }).await {
+        BuyerResponse::GetBuyerPurchases(purchases) => assert_eq!(purchases, vec![lamp]),
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    let rating = || async {
+        match buyer.send(BuyerRequest::GetSellerRating { session_id, seller_id }).await {
+            BuyerResponse::GetSellerRating(feedback) => (feedback.thumbs_up, feedback.thumbs_down),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    };
+    assert!(matches!(buyer.send(vote(true)).await, BuyerResponse::ProvideFeedback));
+    assert_eq!(rating().await, (1, 0));
+    // Voting again replaces the buyer's earlier vote
+    assert!(matches!(buyer.send(vote(false)).await, BuyerResponse::ProvideFeedback));
+    assert_eq!(rating().await, (0, 1));
+    let feedback = get_item(&buyer, session_id, lamp).await.expect("item exists").feedback;
+    assert_eq!((feedback.thumbs_up, feedback.thumbs_down), (0, 1));
+
+    let request = BuyerRequest::GetSellerRating { session_id, seller_id: Uuid::new_v4() };
+    expect_error(buyer.send(request).await, "Seller not found");
+}
+
+#[tokio::test]
+async fn new_buyer_has_no_purchases() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    match buyer.send(BuyerRequest::GetBuyerPurchases { session_id }).await {
+        BuyerResponse::GetBuyerPurchases(purchases) => assert!(purchases.is_empty()),
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn list_categories() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    match buyer.send(BuyerRequest::ListCategories { session_id }).await {
+        BuyerResponse::ListCategories(categories) => {
+            assert!(categories.iter().any(|category| category.category_id == 1 && category.name == "Electronics"));
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn health_ready_and_stats() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+
+    assert!(matches!(buyer.send(BuyerRequest::Health).await, BuyerResponse::Health));
+    match buyer.send(BuyerRequest::Ready).await {
+        BuyerResponse::Ready(readiness) => {
+            assert!(readiness.ready, "{:?}", readiness);
+            assert_eq!(readiness.dependencies.len(), 2);
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+    assert!(matches!(buyer.send(BuyerRequest::Stats).await, BuyerResponse::Stats(_)));
+}
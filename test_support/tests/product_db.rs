@@ -0,0 +1,255 @@
+// product_db under concurrent requests that touch the same items and indexes,
+// across restarts, and with indexes that drifted from the items
+
+use common::*;
+use std::sync::Arc;
+use std::time::Duration;
+use test_support::Marketplace;
+
+const ITEMS: usize = 20;
+const ROUNDS: usize = 200;
+
+fn patch_category(item_id: ItemId, category: i32) -> ProductDbRequest {
+    let patch = ItemPatch {
+        item_category: Some(category),
+        ..ItemPatch::default()
+    };
+    ProductDbRequest::PatchItem { item_id, seller_id: None, patch }
+}
+
+fn unranked_search(category: Option<i32>, seller_id: Option<uuid::Uuid>) -> ProductDbRequest {
+    ProductDbRequest::SearchItems {
+        category,
+        keywords: Vec::new(),
+        match_mode: KeywordMatch::Exact,
+        filters: SearchFilters {
+            seller_id,
+            ..SearchFilters::default()
+        },
+        sort: SortOrder::Relevance,
+    }
+}
+
+// Category changes lock an item and then the indexes; searches by category or
+// seller walk the indexes and look up items. Neither may wait on the other.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn category_changes_do_not_block_searches() {
+    let market = Arc::new(Marketplace::start().await);
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let mut item_ids = Vec::new();
+    for n in 0..ITEMS {
+        item_ids.push(seller.register_item(session_id, &format!("item{}", n), 10.0, 1).await);
+    }
+    let seller_id = match market.product_db(ProductDbRequest::GetItem { item_id: item_ids[0] }).await {
+        ProductDbResponse::Item(Some(item)) => item.seller_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+
+    let mut tasks = Vec::new();
+    for item_id in item_ids.clone() {
+        let market = market.clone();
+        tasks.push(tokio::spawn(async move {
+            for round in 0..ROUNDS {
+                let category = if round % 2 == 0 { 2 } else { 1 };
+                let response = market.product_db(patch_category(item_id, category)).await;
+                assert!(matches!(response, ProductDbResponse::ItemChanged(_)), "{:?}", response);
+            }
+        }));
+    }
+    for filter in [(Some(1), None), (Some(2), None), (None, Some(seller_id))] {
+        let market = market.clone();
+        tasks.push(tokio::spawn(async move {
+            for _ in 0..ROUNDS {
+                let response = market.product_db(unranked_search(filter.0, filter.1)).await;
+                assert!(matches!(response, ProductDbResponse::SearchResults(_)), "{:?}", response);
+            }
+        }));
+    }
+
+    let all_done = async {
+        for task in tasks {
+            task.await.expect("task panicked");
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(30), all_done).await.expect("requests deadlocked");
+
+    // Every item ends up back in category 1, listed there exactly once
+    match market.product_db(unranked_search(Some(1), None)).await {
+        ProductDbResponse::SearchResults(results) => assert_eq!(results.len(), ITEMS),
+        other => panic!("unexpected response: {:?}", other),
+    }
+    match market.product_db(ProductDbRequest::VerifyIndexes).await {
+        ProductDbResponse::IndexReport(report) => {
+            assert_eq!((report.category_index.missing, report.category_index.stale), (0, 0));
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+// A category is deleted only while it is empty, so items moved into it at the
+// same moment either block the delete or are rejected
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn deleted_category_never_holds_items() {
+    let market = Arc::new(Marketplace::start().await);
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let mut item_ids = Vec::new();
+    for n in 0..ITEMS {
+        item_ids.push(seller.register_item(session_id, &format!("item{}", n), 10.0, 1).await);
+    }
+
+    for round in 0..ROUNDS {
+        let request = ProductDbRequest::CreateCategory { name: format!("temp{}", round), parent_id: None };
+        let category_id = match market.product_db(request).await {
+            ProductDbResponse::CategoryCreated(category_id) => category_id,
+            other => panic!("unexpected response: {:?}", other),
+        };
+        let moves: Vec<_> = item_ids.iter()
+            .map(|&item_id| {
+                let market = market.clone();
+                tokio::spawn(async move { market.product_db(patch_category(item_id, category_id)).await })
+            })
+            .collect();
+        let deleted = market.product_db(ProductDbRequest::DeleteCategory { category_id }).await;
+
+        let mut moved = Vec::new();
+        for (item_id, task) in item_ids.iter().zip(moves) {
+            match task.await.expect("task panicked") {
+                ProductDbResponse::ItemChanged(_) => moved.push(*item_id),
+                ProductDbResponse::Error(msg) => assert_eq!(msg, "Unknown category"),
+                other => panic!("unexpected response: {:?}", other),
+            }
+        }
+        match deleted {
+            ProductDbResponse::CategoryDeleted => assert!(moved.is_empty(), "round {}: {:?} moved into a deleted category", round, moved),
+            ProductDbResponse::Error(msg) => assert_eq!(msg, "Category still has items"),
+            other => panic!("unexpected response: {:?}", other),
+        }
+        for item_id in moved {
+            let response = market.product_db(patch_category(item_id, 1)).await;
+            assert!(matches!(response, ProductDbResponse::ItemChanged(_)), "{:?}", response);
+        }
+    }
+}
+
+fn new_item(item_category: i32) -> ProductDbRequest {
+    let item = Item {
+        item_id: ItemId::default(),
+        item_name: "lamp".to_string(),
+        item_category,
+        keywords: vec!["lamp".to_string()],
+        condition: Condition::New,
+        sale_price: 10.0,
+        quantity: 1,
+        feedback: Feedback { thumbs_up: 0, thumbs_down: 0 },
+        seller_id: uuid::Uuid::nil(),
+        listed_at: 0,
+        version: 0,
+        status: ItemStatus::Active,
+    };
+    ProductDbRequest::CreateItem { item }
+}
+
+async fn create_item(store: &product_db::Store, category: i32) -> ItemId {
+    match store.handle(new_item(category)).await {
+        ProductDbResponse::ItemCreated(item_id) => item_id,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+async fn create_category(store: &product_db::Store, name: &str) -> i32 {
+    match store.handle(ProductDbRequest::CreateCategory { name: name.to_string(), parent_id: None }).await {
+        ProductDbResponse::CategoryCreated(category_id) => category_id,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+// A restarted product_db keeps its categories and hands out neither a category
+// id nor an item id it gave out before, even one whose category was deleted
+#[tokio::test]
+async fn restart_keeps_categories_and_never_reuses_ids() {
+    let dir = std::env::temp_dir().join(format!("product_db_restart_{}", uuid::Uuid::new_v4().simple()));
+
+    let store = product_db::Store::open(Some(dir.clone())).unwrap();
+    let lamps = create_category(&store, "Lamps").await;
+    let temp = create_category(&store, "Temp").await;
+    assert!(matches!(store.handle(ProductDbRequest::DeleteCategory { category_id: temp }).await, ProductDbResponse::CategoryDeleted));
+    let old_items = [create_item(&store, lamps).await, create_item(&store, 1).await];
+    drop(store);
+
+    let store = product_db::Store::open(Some(dir.clone())).unwrap();
+    let categories = match store.handle(ProductDbRequest::ListCategories).await {
+        ProductDbResponse::Categories(categories) => categories,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    assert!(categories.iter().any(|c| c.category_id == lamps && c.name == "Lamps"));
+    assert!(categories.iter().all(|c| c.category_id != temp));
+    let rugs = create_category(&store, "Rugs").await;
+    assert!(rugs > temp, "category id {} handed out again", rugs);
+    for (old, category) in old_items.into_iter().zip([lamps, 1]) {
+        let new = create_item(&store, category).await;
+        assert_eq!(new.category, category);
+        assert!(new.sequence > old.sequence, "{} handed out after {}", new, old);
+    }
+
+    drop(store);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn keyword_search(keyword: &str) -> ProductDbRequest {
+    ProductDbRequest::SearchItems {
+        category: None,
+        keywords: vec![keyword.to_string()],
+        match_mode: KeywordMatch::Exact,
+        filters: SearchFilters::default(),
+        sort: SortOrder::Relevance,
+    }
+}
+
+async fn found(store: &product_db::Store, request: ProductDbRequest) -> Vec<ItemId> {
+    match store.handle(request).await {
+        ProductDbResponse::SearchResults(results) => results.iter().map(|r| r.item.item_id).collect(),
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+async fn verify(store: &product_db::Store) -> IndexReport {
+    match store.handle(ProductDbRequest::VerifyIndexes).await {
+        ProductDbResponse::IndexReport(report) => report,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+// An item indexed under the wrong terms, category and seller is invisible to
+// the searches that should find it until VerifyIndexes counts and repairs
+// every wrong entry
+#[tokio::test]
+async fn verify_indexes_repairs_drift() {
+    let store = product_db::Store::open(None).unwrap();
+    let item_id = create_item(&store, 1).await;
+    let other_id = create_item(&store, 1).await;
+    assert!(store.misindex(item_id));
+    assert_eq!(found(&store, keyword_search("lamp")).await, vec![other_id]);
+    assert_eq!(found(&store, unranked_search(Some(1), None)).await, vec![other_id]);
+    assert_eq!(found(&store, keyword_search("notlamp")).await, vec![item_id]);
+
+    let report = verify(&store).await;
+    assert_eq!(report.items_checked, 2);
+    for drift in [&report.seller_index, &report.category_index] {
+        assert_eq!((drift.missing, drift.stale), (1, 1));
+    }
+    // The keyword and the name token, in and out
+    assert_eq!((report.text_index.missing, report.text_index.stale), (2, 2));
+
+    let mut everything = found(&store, keyword_search("lamp")).await;
+    everything.sort();
+    assert_eq!(everything, vec![item_id, other_id]);
+    assert_eq!(found(&store, unranked_search(Some(1), None)).await.len(), 2);
+    assert!(found(&store, keyword_search("notlamp")).await.is_empty());
+
+    let report = verify(&store).await;
+    for drift in [report.seller_index, report.category_index, report.text_index] {
+        assert_eq!((drift.missing, drift.stale), (0, 0));
+    }
+}
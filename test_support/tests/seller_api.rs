@@ -0,0 +1,395 @@
+// Every SellerRequest through a real seller_server and databases
+
+use common::clock::SystemClock;
+use common::*;
+use std::sync::Arc;
+use std::time::Duration;
+use test_support::{Marketplace, PASSWORD};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+fn expect_error(response: SellerResponse, expected: &str) {
+    match response {
+        SellerResponse::Error(msg) => assert!(msg.contains(expected), "expected an error containing {:?}, got {:?}", expected, msg),
+        other => panic!("expected an error containing {:?}, got {:?}", expected, other),
+    }
+}
+
+async fn items_for_sale(seller: &test_support::SellerClient, session_id: Uuid) -> Vec<Item> {
+    match seller.send(SellerRequest::DisplayItemsForSale { session_id }).await {
+        SellerResponse::DisplayItemsForSale(items) => items,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn create_account_login_and_logout() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+
+    let request = SellerRequest::CreateAccount { seller_name: "alice".to_string(), password: PASSWORD.to_string() };
+    let seller_id = match seller.send(request).await {
+        SellerResponse::CreateAccount(seller_id) => seller_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    let request = SellerRequest::Login { seller_name: "alice".to_string(), password: PASSWORD.to_string() };
+    let session_id = match seller.send(request).await {
+        SellerResponse::Login(session_id) => session_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    match market.customer_db(CustomerDbRequest::GetSession { session_id }).await {
+        CustomerDbResponse::Session(Some(session)) => {
+            assert_eq!(session.user_id, seller_id);
+            assert_eq!(session.user_type, UserType::Seller);
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    assert!(matches!(seller.send(SellerRequest::Logout { session_id }).await, SellerResponse::Logout));
+    expect_error(seller.send(SellerRequest::GetSellerRating { session_id }).await, "Session not found");
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_password_and_unknown_seller() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    seller.sign_up("alice").await;
+
+    let request = SellerRequest::Login { seller_name: "alice".to_string(), password: "wrong".to_string() };
+    expect_error(seller.send(request).await, "Invalid password");
+    let request = SellerRequest::Login { seller_name: "bob".to_string(), password: PASSWORD.to_string() };
+    expect_error(seller.send(request).await, "Seller not found");
+}
+
+#[tokio::test]
+async fn new_seller_has_no_rating() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+
+    match seller.send(SellerRequest::GetSellerRating { session_id }).await {
+        SellerResponse::GetSellerRating(feedback) => {
+            assert_eq!((feedback.thumbs_up, feedback.thumbs_down), (0, 0));
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn register_and_display_items() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 3).await;
+    let request = SellerRequest::RegisterItemForSale {
+        session_id,
+        item_name: "sketch".to_string(),
+        item_category: 4,
+        keywords: vec!["art".to_string()],
+        condition: Condition::Used,
+        sale_price: 10.0,
+        quantity: 1,
+        draft: true,
+    };
+    let draft_id = match seller.send(request).await {
+        SellerResponse::RegisterItemForSale(item_id) => item_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    assert_ne!(item_id, draft_id);
+
+    let items = items_for_sale(&seller, session_id).await;
+    assert_eq!(items.len(), 2);
+    let lamp = items.iter().find(|item| item.item_id == item_id).expect("lamp is listed");
+    assert_eq!(lamp.item_name, "lamp");
+    assert_eq!(lamp.sale_price, 25.0);
+    assert_eq!(lamp.quantity, 3);
+    assert_eq!(lamp.status, ItemStatus::Active);
+    let draft = items.iter().find(|item| item.item_id == draft_id).expect("draft is listed");
+    assert_eq!(draft.status, ItemStatus::Draft);
+
+    // Other sellers' items are not shown
+    let other_session = seller.sign_up("bob").await;
+    assert!(items_for_sale(&seller, other_session).await.is_empty());
+}
+
+#[tokio::test]
+async fn register_rejects_invalid_listings() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+
+    let listing = |item_category, sale_price| SellerRequest::RegisterItemForSale {
+        session_id,
+        item_name: "lamp".to_string(),
+        item_category,
+        keywords: Vec::new(),
+        condition: Condition::New,
+        sale_price,
+        quantity: 1,
+        draft: false,
+    };
+    expect_error(seller.send(listing(999, 1.0)).await, "Unknown category");
+    expect_error(seller.send(listing(1, -1.0)).await, "Price must be a non-negative number");
+}
+
+#[tokio::test]
+async fn change_item_price() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 3).await;
+
+    let request = SellerRequest::ChangeItemPrice { session_id, item_id, new_price: 19.5 };
+    assert!(matches!(seller.send(request).await, SellerResponse::ChangeItemPrice));
+    assert_eq!(items_for_sale(&seller, session_id).await[0].sale_price, 19.5);
+
+    // Only the owner may change it
+    let other_session = seller.sign_up("bob").await;
+    let request = SellerRequest::ChangeItemPrice { session_id: other_session, item_id, new_price: 1.0 };
+    expect_error(seller.send(request).await, "Not your item");
+    let missing = ItemId { category: 1, sequence: 999 };
+    let request = SellerRequest::ChangeItemPrice { session_id, item_id: missing, new_price: 1.0 };
+    expect_error(seller.send(request).await, "Item not found");
+}
+
+#[tokio::test]
+async fn update_units_for_sale() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 3).await;
+
+    let update = |update| SellerRequest::UpdateUnitsForSale { session_id, item_id, update };
+    assert!(matches!(seller.send(update(UnitsUpdate::SetTo(10))).await, SellerResponse::UpdateUnitsForSale(10)));
+    assert!(matches!(seller.send(update(UnitsUpdate::Remove(4))).await, SellerResponse::UpdateUnitsForSale(6)));
+    expect_error(seller.send(update(UnitsUpdate::Remove(0))).await, "Units to remove must be positive");
+    expect_error(seller.send(update(UnitsUpdate::Remove(-3))).await, "Units to remove must be positive");
+    expect_error(seller.send(update(UnitsUpdate::Remove(7))).await, "would drop below 0");
+    expect_error(seller.send(update(UnitsUpdate::SetTo(-1))).await, "Quantity cannot be negative");
+    assert_eq!(items_for_sale(&seller, session_id).await[0].quantity, 6);
+
+    // Removing the last units sells the item out
+    assert!(matches!(seller.send(update(UnitsUpdate::Remove(6))).await, SellerResponse::UpdateUnitsForSale(0)));
+    assert_eq!(items_for_sale(&seller, session_id).await[0].status, ItemStatus::SoldOut);
+}
+
+#[tokio::test]
+async fn edit_item_changes_only_given_fields() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 3).await;
+
+    let changes = ItemPatch {
+        item_name: Some("desk lamp".to_string()),
+        condition: Some(Condition::Used),
+        ..ItemPatch::default()
+    };
+    match seller.send(SellerRequest::EditItem { session_id, item_id, changes }).await {
+        SellerResponse::EditItem(item) => {
+            assert_eq!(item.item_id, item_id);
+            assert_eq!(item.item_name, "desk lamp");
+            assert!(matches!(item.condition, Condition::Used));
+            assert_eq!(item.sale_price, 25.0);
+            assert_eq!(item.quantity, 3);
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    let changes = ItemPatch {
+        item_category: Some(999),
+        ..ItemPatch::default()
+    };
+    expect_error(seller.send(SellerRequest::EditItem { session_id, item_id, changes }).await, "Unknown category");
+}
+
+#[tokio::test]
+async fn conditional_update_rejects_stale_version() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 3).await;
+    let read = items_for_sale(&seller, session_id).await.remove(0);
+
+    // Another edit lands after the item was read
+    let changes = ItemPatch {
+        sale_price: Some(30.0),
+        ..ItemPatch::default()
+    };
+    assert!(matches!(seller.send(SellerRequest::EditItem { session_id, item_id, changes }).await, SellerResponse::EditItem(_)));
+
+    let update = |expected_version| ProductDbRequest::UpdateItem {
+        item_id,
+        seller_id: Some(read.seller_id),
+        patch: ItemPatch {
+            quantity: Some(5),
+            ..ItemPatch::default()
+        },
+        expected_version,
+    };
+    let current = match market.product_db(update(read.version)).await {
+        ProductDbResponse::VersionConflict(current) => current,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    assert_eq!(current.version, read.version + 1);
+    assert_eq!((current.sale_price, current.quantity), (30.0, 3));
+
+    // Retrying against the current version applies the change
+    match market.product_db(update(current.version)).await {
+        ProductDbResponse::ItemChanged(item) => {
+            assert_eq!((item.sale_price, item.quantity), (30.0, 5));
+            assert_eq!(item.version, current.version + 1);
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+    let request = ProductDbRequest::UpdateItem {
+        item_id,
+        seller_id: Some(Uuid::new_v4()),
+        patch: ItemPatch::default(),
+        expected_version: current.version + 1,
+    };
+    assert!(matches!(market.product_db(request).await, ProductDbResponse::Error(msg) if msg == "Not your item"));
+}
+
+#[tokio::test]
+async fn edit_item_keeps_units_held_in_carts() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 5).await;
+    let buyer = market.buyer();
+    let buyer_session = buyer.sign_up("carol").await;
+    let request = BuyerRequest::AddItemToCart { session_id: buyer_session, item_id, quantity: 3 };
+    assert!(matches!(buyer.send(request).await, BuyerResponse::AddItemToCart));
+
+    let edit = |quantity| SellerRequest::EditItem {
+        session_id,
+        item_id,
+        changes: ItemPatch {
+            quantity: Some(quantity),
+            ..ItemPatch::default()
+        },
+    };
+    expect_error(seller.send(edit(2)).await, "3 of the 5 units are held in buyers' carts");
+    match seller.send(edit(3)).await {
+        SellerResponse::EditItem(item) => assert_eq!(item.quantity, 3),
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn remove_units_keeps_units_held_in_carts() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 5).await;
+    let buyer = market.buyer();
+    let buyer_session = buyer.sign_up("carol").await;
+    let request = BuyerRequest::AddItemToCart { session_id: buyer_session, item_id, quantity: 3 };
+    assert!(matches!(buyer.send(request).await, BuyerResponse::AddItemToCart));
+
+    let remove = |units| SellerRequest::UpdateUnitsForSale { session_id, item_id, update: UnitsUpdate::Remove(units) };
+    expect_error(seller.send(remove(3)).await, "Cannot remove 3 units: 3 of the 5 units are held in buyers' carts");
+    assert_eq!(items_for_sale(&seller, session_id).await[0].quantity, 5);
+    assert!(matches!(seller.send(remove(2)).await, SellerResponse::UpdateUnitsForSale(3)));
+    expect_error(seller.send(remove(1)).await, "3 of the 3 units are held in buyers' carts");
+}
+
+#[tokio::test]
+async fn item_status_transitions() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let request = SellerRequest::RegisterItemForSale {
+        session_id,
+        item_name: "lamp".to_string(),
+        item_category: 1,
+        keywords: Vec::new(),
+        condition: Condition::New,
+        sale_price: 25.0,
+        quantity: 3,
+        draft: true,
+    };
+    let item_id = match seller.send(request).await {
+        SellerResponse::RegisterItemForSale(item_id) => item_id,
+        other => panic!("unexpected response: {:?}", other),
+    };
+    let status = || async { items_for_sale(&seller, session_id).await[0].status };
+
+    expect_error(seller.send(SellerRequest::ResumeItem { session_id, item_id }).await, "Cannot resume an item that is draft");
+    assert!(matches!(seller.send(SellerRequest::PublishItem { session_id, item_id }).await, SellerResponse::PublishItem));
+    assert_eq!(status().await, ItemStatus::Active);
+    assert!(matches!(seller.send(SellerRequest::PauseItem { session_id, item_id }).await, SellerResponse::PauseItem));
+    assert_eq!(status().await, ItemStatus::Paused);
+    assert!(matches!(seller.send(SellerRequest::ResumeItem { session_id, item_id }).await, SellerResponse::ResumeItem));
+    assert_eq!(status().await, ItemStatus::Active);
+    assert!(matches!(seller.send(SellerRequest::DelistItem { session_id, item_id }).await, SellerResponse::DelistItem));
+    assert_eq!(status().await, ItemStatus::Delisted);
+
+    // Delisting is final
+    expect_error(seller.send(SellerRequest::PublishItem { session_id, item_id }).await, "Item has been delisted");
+    let request = SellerRequest::ChangeItemPrice { session_id, item_id, new_price: 1.0 };
+    expect_error(seller.send(request).await, "Item has been delisted");
+}
+
+#[tokio::test]
+async fn list_categories() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+
+    match seller.send(SellerRequest::ListCategories { session_id }).await {
+        SellerResponse::ListCategories(categories) => {
+            let electronics = categories.iter().find(|category| category.category_id == 1).expect("default categories exist");
+            assert_eq!(electronics.name, "Electronics");
+            assert!(categories.iter().any(|category| category.parent_id == Some(1)));
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn health_ready_and_stats() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+
+    assert!(matches!(seller.send(SellerRequest::Health).await, SellerResponse::Health));
+    match seller.send(SellerRequest::Ready).await {
+        SellerResponse::Ready(readiness) => {
+            assert!(readiness.ready, "{:?}", readiness);
+            assert_eq!(readiness.dependencies.len(), 2);
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+    assert!(matches!(seller.send(SellerRequest::Stats).await, SellerResponse::Stats(_)));
+}
+
+// A database that accepts connections but never answers fails the request
+// once the backend timeout is up instead of holding it open
+#[tokio::test]
+async fn stalled_database_fails_the_request() {
+    let stalled = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stalled_addr = stalled.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((socket, _)) = stalled.accept().await {
+            held.push(socket);
+        }
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let seller = test_support::SellerClient::new(listener.local_addr().unwrap().to_string());
+    let context = seller_server::Context {
+        backends: Backends {
+            customer_db: stalled_addr.clone(),
+            product_db: stalled_addr,
+            timeout: Duration::from_millis(200),
+        },
+        clock: Arc::new(SystemClock),
+    };
+    tokio::spawn(seller_server::serve(listener, Arc::new(context)));
+
+    let request = SellerRequest::GetSellerRating { session_id: Uuid::new_v4() };
+    let response = tokio::time::timeout(Duration::from_secs(5), seller.send(request)).await.expect("request hung");
+    expect_error(response, "Failed to validate session");
+}
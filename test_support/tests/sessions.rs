@@ -0,0 +1,155 @@
+// Session checks both frontends make before carrying out a request
+
+use common::*;
+use std::time::Duration;
+use test_support::{Marketplace, SESSION_TIMEOUT};
+use uuid::Uuid;
+
+fn seller_error(response: SellerResponse) -> String {
+    match response {
+        SellerResponse::Error(msg) => msg,
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+fn buyer_error(response: BuyerResponse) -> String {
+    match response {
+        BuyerResponse::Error(msg) => msg,
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+fn active_sessions(response: CustomerDbResponse) -> u64 {
+    match response {
+        CustomerDbResponse::Stats(stats) => stats.entries["active_sessions"],
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn buyer_session_is_rejected_by_seller_server() {
+    let market = Marketplace::start().await;
+    let session_id = market.buyer().sign_up("carol").await;
+    let seller = market.seller();
+
+    assert_eq!(seller_error(seller.send(SellerRequest::DisplayItemsForSale { session_id }).await), "Invalid session type");
+    assert_eq!(seller_error(seller.send(SellerRequest::GetSellerRating { session_id }).await), "Invalid session type");
+    let request = SellerRequest::PublishItem { session_id, item_id: ItemId { category: 1, sequence: 1 } };
+    assert_eq!(seller_error(seller.send(request).await), "Invalid session type");
+}
+
+#[tokio::test]
+async fn seller_session_is_rejected_by_buyer_server() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+    let item_id = seller.register_item(session_id, "lamp", 25.0, 3).await;
+    let buyer = market.buyer();
+
+    assert_eq!(buyer_error(buyer.send(BuyerRequest::DisplayCart { session_id }).await), "Invalid session type");
+    let request = BuyerRequest::AddItemToCart { session_id, item_id, quantity: 1 };
+    assert_eq!(buyer_error(buyer.send(request).await), "Invalid session type");
+}
+
+#[tokio::test]
+async fn unknown_session_is_rejected() {
+    let market = Marketplace::start().await;
+    let session_id = Uuid::new_v4();
+
+    let response = market.seller().send(SellerRequest::ListCategories { session_id }).await;
+    assert_eq!(seller_error(response), "Session not found");
+    let response = market.buyer().send(BuyerRequest::ListCategories { session_id }).await;
+    assert_eq!(buyer_error(response), "Session not found");
+}
+
+#[tokio::test]
+async fn session_expires_after_timeout_of_inactivity() {
+    let market = Marketplace::start().await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+
+    // Still valid at the last second of the timeout
+    market.clock.advance(SESSION_TIMEOUT);
+    assert!(matches!(seller.send(SellerRequest::DisplayItemsForSale { session_id }).await, SellerResponse::DisplayItemsForSale(_)));
+
+    market.clock.advance(SESSION_TIMEOUT + Duration::from_secs(1));
+    assert_eq!(seller_error(seller.send(SellerRequest::DisplayItemsForSale { session_id }).await), "Session expired");
+    // The frontend deletes the expired session
+    assert!(!market.customer_db.sessions.contains_key(&session_id));
+    assert_eq!(seller_error(seller.send(SellerRequest::DisplayItemsForSale { session_id }).await), "Session not found");
+
+    // Logging in again gives a working session
+    let session_id = seller.login("alice").await;
+    assert!(matches!(seller.send(SellerRequest::DisplayItemsForSale { session_id }).await, SellerResponse::DisplayItemsForSale(_)));
+}
+
+#[tokio::test]
+async fn activity_refreshes_session() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    // Each request restarts the timeout, so the session outlives it in total
+    for _ in 0..3 {
+        market.clock.advance(SESSION_TIMEOUT - Duration::from_secs(10));
+        assert!(matches!(buyer.send(BuyerRequest::DisplayCart { session_id }).await, BuyerResponse::DisplayCart(_)));
+    }
+
+    market.clock.advance(SESSION_TIMEOUT + Duration::from_secs(1));
+    assert_eq!(buyer_error(buyer.send(BuyerRequest::DisplayCart { session_id }).await), "Session expired");
+    assert!(!market.customer_db.sessions.contains_key(&session_id));
+}
+
+#[tokio::test]
+async fn rejected_requests_also_refresh_session() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+    let session_id = buyer.sign_up("carol").await;
+
+    // The session is looked up, and so refreshed, before the request fails
+    market.clock.advance(SESSION_TIMEOUT - Duration::from_secs(10));
+    let request = BuyerRequest::AddItemToCart { session_id, item_id: ItemId { category: 1, sequence: 999 }, quantity: 1 };
+    assert_eq!(buyer_error(buyer.send(request).await), "Item not found");
+    market.clock.advance(SESSION_TIMEOUT - Duration::from_secs(10));
+    assert!(matches!(buyer.send(BuyerRequest::DisplayCart { session_id }).await, BuyerResponse::DisplayCart(_)));
+}
+
+#[tokio::test]
+async fn session_timeout_is_configurable() {
+    let market = Marketplace::with_session_timeout(Duration::from_secs(30)).await;
+    let seller = market.seller();
+    let session_id = seller.sign_up("alice").await;
+
+    market.clock.advance(Duration::from_secs(30));
+    assert!(matches!(seller.send(SellerRequest::GetSellerRating { session_id }).await, SellerResponse::GetSellerRating(_)));
+    market.clock.advance(Duration::from_secs(31));
+    assert_eq!(seller_error(seller.send(SellerRequest::GetSellerRating { session_id }).await), "Session expired");
+}
+
+#[tokio::test]
+async fn cleanup_removes_only_expired_sessions() {
+    let market = Marketplace::start().await;
+    let buyer = market.buyer();
+    let stale = buyer.sign_up("carol").await;
+    market.clock.advance(SESSION_TIMEOUT / 2);
+    let fresh = market.seller().sign_up("alice").await;
+    assert_eq!(active_sessions(market.customer_db(CustomerDbRequest::Stats).await), 2);
+
+    // Only the first session has gone unused for longer than the timeout
+    market.clock.advance(SESSION_TIMEOUT / 2 + Duration::from_secs(1));
+    assert_eq!(active_sessions(market.customer_db(CustomerDbRequest::Stats).await), 1);
+    match market.customer_db(CustomerDbRequest::CleanupSessions).await {
+        CustomerDbResponse::SessionsCleaned(removed) => assert_eq!(removed, 1),
+        other => panic!("unexpected response: {:?}", other),
+    }
+    assert!(!market.customer_db.sessions.contains_key(&stale));
+    assert!(market.customer_db.sessions.contains_key(&fresh));
+    assert_eq!(buyer_error(buyer.send(BuyerRequest::DisplayCart { session_id: stale }).await), "Session not found");
+
+    market.clock.advance(SESSION_TIMEOUT);
+    match market.customer_db(CustomerDbRequest::CleanupSessions).await {
+        CustomerDbResponse::SessionsCleaned(removed) => assert_eq!(removed, 1),
+        other => panic!("unexpected response: {:?}", other),
+    }
+    assert!(market.customer_db.sessions.is_empty());
+}